@@ -0,0 +1,98 @@
+use clap::ValueEnum;
+use futures::future::BoxFuture;
+use rig::{
+    agent::Agent,
+    completion::{CompletionModel, Prompt},
+};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+// Rank offset in reciprocal rank fusion; 60 is the value from the original RRF paper
+pub const RRF_K: f64 = 60.0;
+
+pub const DECOMPOSER_PREAMBLE: &str = r#"
+    You split customer analytics questions into their independent aspects.
+    Reply with one short aspect per line and nothing else. If the question has
+    a single aspect, reply with the question unchanged.
+"#;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum AspectStrategy {
+    /// Split the query on ';', e.g. "churn signals; high income"
+    Semicolon,
+    /// Ask the completion model to decompose the query into aspects
+    Llm,
+}
+
+// Turns a query into the aspects retrieved separately
+pub type AspectSplitter = Box<dyn Fn(String) -> BoxFuture<'static, Vec<String>> + Send + Sync>;
+
+pub fn split_semicolon(query: &str) -> Vec<String> {
+    query
+        .split(';')
+        .map(str::trim)
+        .filter(|aspect| !aspect.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+pub fn semicolon_splitter() -> AspectSplitter {
+    Box::new(|query| Box::pin(async move { split_semicolon(&query) }))
+}
+
+// Falls back to the whole query if the model call fails
+pub fn llm_splitter<M: CompletionModel + 'static>(agent: Agent<M>) -> AspectSplitter {
+    let agent = Arc::new(agent);
+    Box::new(move |query| {
+        let agent = agent.clone();
+        Box::pin(async move {
+            match agent.as_ref().prompt(&query).await {
+                Ok(response) => response
+                    .lines()
+                    .map(|line| line.trim().trim_start_matches(['-', '*']).trim())
+                    .filter(|line| !line.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+                Err(err) => {
+                    tracing::warn!("Aspect decomposition failed ({}), using the whole query", err);
+                    vec![query]
+                }
+            }
+        })
+    })
+}
+
+// A profile's 1-based rank in one aspect's ranking and what that added to its fused score
+pub type RankContribution = (usize, f64);
+
+// Per-aspect contributions of each retrieved profile, keyed by customer id
+#[derive(Debug, Clone, Default)]
+pub struct AspectBreakdown {
+    pub aspects: Vec<String>,
+    pub contributions: HashMap<String, Vec<RankContribution>>,
+}
+
+// Breakdowns of past lookups by query, shared between the retriever and the
+// explain output
+pub type AspectLog = Arc<Mutex<HashMap<String, AspectBreakdown>>>;
+
+// 1-based rank of each item in each ranking (given as item positions, best
+// first), fused as the sum of 1 / (RRF_K + rank). Returns (item, fused score,
+// per-ranking (rank, contribution)), best first.
+pub fn reciprocal_rank_fusion(rankings: &[Vec<usize>], items: usize) -> Vec<(usize, f64, Vec<RankContribution>)> {
+    let mut per_item: Vec<Vec<RankContribution>> = vec![Vec::with_capacity(rankings.len()); items];
+    for ranking in rankings {
+        for (position, &item) in ranking.iter().enumerate() {
+            let rank = position + 1;
+            per_item[item].push((rank, 1.0 / (RRF_K + rank as f64)));
+        }
+    }
+
+    let mut fused: Vec<(usize, f64, Vec<RankContribution>)> = per_item
+        .into_iter()
+        .enumerate()
+        .map(|(item, contributions)| (item, contributions.iter().map(|(_, c)| c).sum(), contributions))
+        .collect();
+    fused.sort_by(|a, b| b.1.total_cmp(&a.1));
+    fused
+}
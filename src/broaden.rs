@@ -0,0 +1,45 @@
+use futures::future::BoxFuture;
+use rig::{
+    agent::Agent,
+    completion::{CompletionModel, Prompt},
+};
+use std::sync::Arc;
+
+// Upper bound on rephrasings per query, whatever --retry-empty asks for
+pub const MAX_ATTEMPTS: usize = 5;
+
+pub const BROADENER_PREAMBLE: &str = r#"
+    You rewrite customer analytics questions so they match more customer
+    profiles. Replace jargon and abbreviations with plain descriptions of
+    customer attributes (age, income, satisfaction, loyalty, purchase
+    frequency, product and service quality, country) and make the question
+    more general. Reply with the rewritten question only.
+"#;
+
+// Rewrites a query that retrieved nothing useful into a broader one, or None
+// if no rewrite could be produced
+pub type QueryBroadener = Box<dyn Fn(String) -> BoxFuture<'static, Option<String>> + Send + Sync>;
+
+pub fn llm_broadener<M: CompletionModel + 'static>(agent: Agent<M>) -> QueryBroadener {
+    let agent = Arc::new(agent);
+    Box::new(move |query| {
+        let agent = agent.clone();
+        Box::pin(async move {
+            match agent.as_ref().prompt(&query).await {
+                Ok(response) => {
+                    let rewritten = response.trim().trim_matches('"').trim().to_string();
+                    (!rewritten.is_empty()).then_some(rewritten)
+                }
+                Err(err) => {
+                    tracing::warn!("Query rephrasing failed: {}", err);
+                    None
+                }
+            }
+        })
+    })
+}
+
+// Nothing was retrieved, or nothing worth showing the agent
+pub fn is_empty<T>(results: &[(f64, String, T)], threshold: f64) -> bool {
+    results.iter().all(|(score, _, _)| *score < threshold)
+}
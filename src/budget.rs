@@ -0,0 +1,48 @@
+use crate::record::EmbeddableRecord;
+use crate::tokens::estimate_tokens;
+
+// Caps how much embedding work a single run may launch. Chunks are admitted
+// whole, so a run stops before the first chunk that would exceed a limit.
+#[derive(Debug, Default)]
+pub struct EmbeddingBudget {
+    max_calls: Option<usize>,
+    max_tokens: Option<usize>,
+    calls_used: usize,
+    tokens_used: usize,
+}
+
+impl EmbeddingBudget {
+    pub fn new(max_calls: Option<usize>, max_tokens: Option<usize>) -> Self {
+        Self {
+            max_calls,
+            max_tokens,
+            ..Default::default()
+        }
+    }
+
+    // Reserve the API calls and tokens needed to embed `chunk`, returning false
+    // (and reserving nothing) if that would exceed the budget. `max_documents`
+    // is the number of documents sent per embedding request.
+    pub fn try_reserve<T: EmbeddableRecord>(&mut self, chunk: &[T], max_documents: usize) -> bool {
+        let calls = chunk.len().div_ceil(max_documents);
+        let tokens: usize = chunk.iter().map(|record| estimate_tokens(record.summary())).sum();
+
+        let over_calls = self.max_calls.is_some_and(|max| self.calls_used + calls > max);
+        let over_tokens = self.max_tokens.is_some_and(|max| self.tokens_used + tokens > max);
+        if over_calls || over_tokens {
+            return false;
+        }
+
+        self.calls_used += calls;
+        self.tokens_used += tokens;
+        true
+    }
+
+    pub fn calls_used(&self) -> usize {
+        self.calls_used
+    }
+
+    pub fn tokens_used(&self) -> usize {
+        self.tokens_used
+    }
+}
@@ -0,0 +1,152 @@
+use crate::persist::{self, StoreFormat};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// Content-addressed embedding cache keyed by the hash of the embedding model name
+// and the exact summary text, so unchanged records are never re-embedded even when
+// other records in the file change. Safe to share between concurrent chunks.
+pub struct SummaryCache {
+    path: PathBuf,
+    model: String,
+    format: StoreFormat,
+    entries: Mutex<HashMap<String, Vec<f64>>>,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+}
+
+impl SummaryCache {
+    // Open the cache at `path` in whichever format it was written, starting
+    // empty if the file doesn't exist yet. It is saved back as `format`.
+    pub fn load(path: &Path, model: &str, format: StoreFormat) -> Result<Self, anyhow::Error> {
+        let entries = if path.exists() {
+            let (found, entries) = persist::decode(&std::fs::read(path)?)
+                .map_err(|e| anyhow::anyhow!("Cannot read summary cache {}: {}", path.display(), e))?;
+            if found != format {
+                tracing::info!("Summary cache {} is stored as {:?}; it will be saved as {:?}", path.display(), found, format);
+            }
+            entries
+        } else {
+            HashMap::new()
+        };
+
+        let mut cache = Self::empty(path, model, format);
+        cache.entries = Mutex::new(entries);
+        Ok(cache)
+    }
+
+    // A cache that starts empty and replaces whatever is at `path` when saved
+    pub fn empty(path: &Path, model: &str, format: StoreFormat) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            model: model.to_string(),
+            format,
+            entries: Mutex::new(HashMap::new()),
+            hits: AtomicUsize::new(0),
+            misses: AtomicUsize::new(0),
+        }
+    }
+
+    fn key(&self, summary: &str) -> String {
+        sha256_hex(format!("{}\n{}", self.model, summary).as_bytes())
+    }
+
+    pub fn get(&self, summary: &str) -> Option<Vec<f64>> {
+        let key = self.key(summary);
+        let found = self.entries.lock().unwrap().get(&key).cloned();
+        match found {
+            Some(_) => self.hits.fetch_add(1, Ordering::Relaxed),
+            None => self.misses.fetch_add(1, Ordering::Relaxed),
+        };
+        found
+    }
+
+    // Like `get`, without counting toward the hit rate
+    pub fn contains(&self, summary: &str) -> bool {
+        self.entries.lock().unwrap().contains_key(&self.key(summary))
+    }
+
+    pub fn insert(&self, summary: &str, vector: Vec<f64>) {
+        let key = self.key(summary);
+        self.entries.lock().unwrap().insert(key, vector);
+    }
+
+    pub fn save(&self) -> Result<(), anyhow::Error> {
+        let entries = self.entries.lock().unwrap();
+        std::fs::write(&self.path, persist::encode(&entries, self.format)?)?;
+        Ok(())
+    }
+
+    // (hits, lookups)
+    pub fn stats(&self) -> (usize, usize) {
+        let hits = self.hits.load(Ordering::Relaxed);
+        (hits, hits + self.misses.load(Ordering::Relaxed))
+    }
+}
+
+// Bounded, least-recently-used map from a lookup key to what the lookup
+// returned, so a repeated query skips both its embedding and the index search
+pub struct RetrievalCache<T> {
+    capacity: usize,
+    // Entries with the tick they were last used at
+    entries: Mutex<HashMap<String, (u64, T)>>,
+    tick: AtomicU64,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+}
+
+impl<T: Clone> RetrievalCache<T> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::new(HashMap::new()),
+            tick: AtomicU64::new(0),
+            hits: AtomicUsize::new(0),
+            misses: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn get(&self, key: &str) -> Option<T> {
+        let tick = self.tick.fetch_add(1, Ordering::Relaxed);
+        let found = self.entries.lock().unwrap().get_mut(key).map(|(used, value)| {
+            *used = tick;
+            value.clone()
+        });
+        match found {
+            Some(_) => self.hits.fetch_add(1, Ordering::Relaxed),
+            None => self.misses.fetch_add(1, Ordering::Relaxed),
+        };
+        found
+    }
+
+    pub fn insert(&self, key: &str, value: T) {
+        if self.capacity == 0 {
+            return;
+        }
+        let tick = self.tick.fetch_add(1, Ordering::Relaxed);
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= self.capacity && !entries.contains_key(key) {
+            let oldest = entries.iter().min_by_key(|(_, (used, _))| *used).map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
+        }
+        entries.insert(key.to_string(), (tick, value));
+    }
+
+    // (hits, lookups)
+    pub fn stats(&self) -> (usize, usize) {
+        let hits = self.hits.load(Ordering::Relaxed);
+        (hits, hits + self.misses.load(Ordering::Relaxed))
+    }
+}
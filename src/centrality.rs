@@ -0,0 +1,80 @@
+use crate::models::CustomerFeedback;
+use crate::search::ScoreAdjustment;
+use rig::{embeddings::Embedding, OneOrMany};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+fn normalized(vec: &[f64]) -> Vec<f64> {
+    let norm = vec.iter().map(|x| x * x).sum::<f64>().sqrt();
+    if norm > 0.0 {
+        vec.iter().map(|x| x / norm).collect()
+    } else {
+        vec.to_vec()
+    }
+}
+
+// In-degree of each profile in the k-nearest-neighbor graph of the stored
+// embeddings: how many other profiles count it among their k most similar. A
+// high in-degree marks a central, representative customer of its region.
+#[derive(Debug)]
+pub struct Centrality {
+    k: usize,
+    in_degree: HashMap<String, usize>,
+    max_in_degree: usize,
+}
+
+impl Centrality {
+    // Exact, brute force over all pairs (each profile's first vector), so it
+    // costs O(n^2) similarity computations once at startup
+    pub fn compute(embeddings: &[(CustomerFeedback, OneOrMany<Embedding>)], k: usize) -> Self {
+        let vectors: Vec<(&str, Vec<f64>)> = embeddings
+            .iter()
+            .map(|(customer, embedding)| (customer.customer_id.as_str(), normalized(&embedding.first().vec)))
+            .collect();
+        let mut in_degree: HashMap<String, usize> = vectors.iter().map(|(id, _)| (id.to_string(), 0)).collect();
+        let k = k.min(vectors.len().saturating_sub(1));
+
+        let mut similarities: Vec<(f64, usize)> = Vec::with_capacity(vectors.len());
+        for (i, (_, vector)) in vectors.iter().enumerate() {
+            similarities.clear();
+            similarities.extend(vectors.iter().enumerate().filter(|(j, _)| *j != i).map(|(j, (_, other))| {
+                (vector.iter().zip(other).map(|(a, b)| a * b).sum::<f64>(), j)
+            }));
+            if k == 0 {
+                continue;
+            }
+            similarities.select_nth_unstable_by(k - 1, |a, b| b.0.total_cmp(&a.0));
+            for (_, j) in &similarities[..k] {
+                *in_degree.entry(vectors[*j].0.to_string()).or_default() += 1;
+            }
+        }
+
+        let max_in_degree = in_degree.values().copied().max().unwrap_or(0);
+        Self { k, in_degree, max_in_degree }
+    }
+
+    pub fn k(&self) -> usize {
+        self.k
+    }
+
+    pub fn in_degree(&self, customer_id: &str) -> Option<usize> {
+        self.in_degree.get(customer_id).copied()
+    }
+
+    // The `n` highest in-degree profiles, ties by id
+    pub fn most_central(&self, n: usize) -> Vec<(&str, usize)> {
+        let mut ranked: Vec<(&str, usize)> = self.in_degree.iter().map(|(id, degree)| (id.as_str(), *degree)).collect();
+        ranked.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        ranked.truncate(n);
+        ranked
+    }
+
+    // Add up to `weight` to a profile's score in proportion to its in-degree,
+    // relative to the most central profile
+    pub fn boost(self: Arc<Self>, weight: f64) -> ScoreAdjustment {
+        Box::new(move |customer, score| match (self.in_degree(&customer.customer_id), self.max_in_degree) {
+            (Some(degree), max) if max > 0 => score + weight * degree as f64 / max as f64,
+            _ => score,
+        })
+    }
+}
@@ -0,0 +1,83 @@
+use crate::models::CustomerFeedback;
+
+// How many of the figures in one analysis appear in its retrieved profiles
+#[derive(Debug, Default)]
+pub struct CitationCheck {
+    pub cited: usize,
+    pub supported: usize,
+    // Unsupported figures as written in the analysis, without repeats
+    pub unsupported: Vec<String>,
+}
+
+impl CitationCheck {
+    pub fn ratio(&self) -> Option<f64> {
+        (self.cited > 0).then(|| self.supported as f64 / self.cited as f64)
+    }
+}
+
+// The numeric fields a figure can be quoting
+fn field_values(profile: &CustomerFeedback) -> impl Iterator<Item = f64> {
+    [
+        Some(profile.age as f64),
+        profile.income,
+        Some(profile.product_quality as f64),
+        Some(profile.service_quality as f64),
+        Some(profile.purchase_frequency as f64),
+        profile.satisfaction_score,
+    ]
+    .into_iter()
+    .flatten()
+}
+
+// Parse one whitespace-separated word as a figure, e.g. `$45,500`, `72.5%`,
+// `45.5k` or the `8` of `8/10`. Ids (CUST0042), dates and other words with
+// digits in them are not figures.
+fn parse_figure(word: &str) -> Option<(f64, &str)> {
+    let word = word.trim_start_matches(['(', '[', '"', '$', '~', '*']);
+    let word = word.split('/').next()?;
+    let word = word.trim_end_matches(['.', ',', ';', ':', ')', ']', '"', '%', '*', '!', '?']);
+    let (digits, scale) = match word.strip_suffix(['k', 'K']) {
+        Some(digits) => (digits, 1000.0),
+        None => (word, 1.0),
+    };
+    if !digits.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    let value: f64 = digits.replace(',', "").parse().ok()?;
+    Some((value * scale, word))
+}
+
+// Every figure in `text`, skipping numbered-list markers such as `2.` or `2)`
+// at the start of a line
+fn figures(text: &str) -> Vec<(f64, &str)> {
+    let mut found = Vec::new();
+    for line in text.lines() {
+        for (i, word) in line.split_whitespace().enumerate() {
+            let list_marker =
+                i == 0 && word.len() > 1 && word[..word.len() - 1].bytes().all(|b| b.is_ascii_digit()) && word.ends_with(['.', ')']);
+            if list_marker {
+                continue;
+            }
+            found.extend(parse_figure(word));
+        }
+    }
+    found
+}
+
+// Check each figure in `analysis` against the fields of `profiles`. A figure is
+// supported when some field is within rounding of it (0.5%, at least 0.05), so
+// 45,500 written as 45.5k or 72.46 written as 72.5 still count.
+pub fn verify(analysis: &str, profiles: &[CustomerFeedback]) -> CitationCheck {
+    let values: Vec<f64> = profiles.iter().flat_map(field_values).collect();
+    let mut check = CitationCheck::default();
+    for (figure, written) in figures(analysis) {
+        check.cited += 1;
+        let tolerance = (figure.abs() * 0.005).max(0.05);
+        if values.iter().any(|value| (value - figure).abs() <= tolerance) {
+            check.supported += 1;
+        } else if !check.unsupported.iter().any(|seen| seen == written) {
+            check.unsupported.push(written.to_string());
+        }
+    }
+    check
+}
@@ -0,0 +1,763 @@
+use crate::aspects::AspectStrategy;
+use crate::confidence::ConfidenceMetric;
+use crate::currency::Currency;
+use crate::degenerate::ZeroNormPolicy;
+use crate::embed::MissingEmbeddingPolicy;
+use crate::export::LineEnding;
+use crate::features::NumericNormalization;
+use crate::field_match::MatchBonus;
+use crate::filter::Filter;
+use crate::health::{HealthWeight, RankOrder};
+use crate::manifest::ManifestFormat;
+use crate::matrix::SubsetFilter;
+use crate::missing::MissingValuePolicy;
+use crate::persist::StoreFormat;
+use crate::postprocess::{AnalysisFormat, BuiltinPostProcessor};
+use crate::prompt::PromptFormat;
+use crate::providers::Provider;
+use crate::redact::{ProfileField, RedactMode};
+use crate::report::ReportFormat;
+use crate::rules::RuleSet;
+use crate::sheet::SheetFormat;
+use crate::score_display::{PercentTransform, ScoreDisplay};
+use crate::stop;
+use crate::stratify::StratifyField;
+use crate::variants::PreambleVariant;
+use chrono::NaiveDate;
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[derive(Debug, Parser)]
+#[command(about = "Customer feedback analysis pipeline built on Rig")]
+pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// CSV file to analyze, or an http(s) URL to download it from (plain or
+    /// gzip-compressed)
+    #[arg(long, default_value = "data/customer_feedback_satisfaction.csv")]
+    pub data_path: String,
+
+    /// Records loaded, embedded and cached per processing chunk
+    #[arg(long, default_value_t = 1000)]
+    pub chunk_size: usize,
+
+    /// API used for embeddings and completions. Reads OPENAI_API_KEY or
+    /// COHERE_API_KEY.
+    #[arg(long, value_enum, default_value_t = Provider::OpenAi)]
+    pub provider: Provider,
+
+    /// Embedding model for profiles and queries. Defaults to
+    /// text-embedding-ada-002 (OpenAI) or embed-english-v3.0 (Cohere).
+    #[arg(long)]
+    pub embedding_model: Option<String>,
+
+    /// Chat model that writes the analyses and any other agent output.
+    /// Defaults to gpt-4 (OpenAI) or command-r (Cohere).
+    #[arg(long)]
+    pub chat_model: Option<String>,
+
+    /// Profiles retrieved per query
+    #[arg(long, default_value_t = 5)]
+    pub top_k: usize,
+
+    /// Read customer records from this SQLite database instead of
+    /// --data-path, using the rows returned by --sql
+    #[arg(long, value_name = "PATH", requires = "sql")]
+    pub sqlite: Option<String>,
+
+    /// Query to run against --sqlite. Its result columns are matched by the
+    /// same names as CSV headers (CustomerID, Age, ...), or through --column-map.
+    #[arg(long, value_name = "QUERY", requires = "sqlite")]
+    pub sql: Option<String>,
+
+    /// File of queries to run as a batch instead of reading them from
+    /// standard input, one per line. Blank lines and lines starting with `#`
+    /// are skipped.
+    #[arg(long)]
+    pub queries_file: Option<PathBuf>,
+
+    /// Run the built-in example queries as a batch instead of reading queries
+    /// from standard input
+    #[arg(long, conflicts_with = "queries_file")]
+    pub batch: bool,
+
+    /// TOML or JSON file with `[embedding]` and `[completion]` tables setting
+    /// `timeout_secs`, `max_in_flight`, `retries` and `delay_ms` per provider
+    #[arg(long)]
+    pub provider_config: Option<PathBuf>,
+
+    /// Maximum number of retries for transient analysis agent failures
+    /// (rate limits, server errors, timeouts). `retries` in the
+    /// `[completion]` table of --provider-config takes precedence.
+    #[arg(long, default_value_t = 3)]
+    pub agent_max_retries: usize,
+
+    /// Maximum number of retries for a rate-limited or otherwise transiently
+    /// failing embedding batch, backing off from 200ms. `retries` in the
+    /// `[embedding]` table of --provider-config takes precedence.
+    #[arg(long, default_value_t = 4)]
+    pub embedding_max_retries: usize,
+
+    /// Cheaper completion model to switch to after repeated failures, e.g. gpt-3.5-turbo
+    #[arg(long)]
+    pub fallback_model: Option<String>,
+
+    /// Number of failed attempts before switching to the fallback model. At
+    /// most the analysis retry limit.
+    #[arg(long, default_value_t = 2)]
+    pub fallback_after: usize,
+
+    /// TOML or JSON file mapping expected column names to the headers used in
+    /// the data file, e.g. `CustomerID = "cust_id"`
+    #[arg(long)]
+    pub column_map: Option<PathBuf>,
+
+    /// CSV column holding the unique customer id (default CustomerID). Takes
+    /// precedence over a CustomerID entry in --column-map.
+    #[arg(long)]
+    pub id_column: Option<String>,
+
+    /// File caching embeddings by summary text, so records whose summary
+    /// hasn't changed are not re-embedded on later runs
+    #[arg(long)]
+    pub summary_cache: Option<PathBuf>,
+
+    /// Re-embed every record instead of reusing the embedding cache, the
+    /// --store-file or --summary-cache entries, replacing them with the fresh
+    /// embeddings
+    #[arg(long)]
+    pub no_cache: bool,
+
+    /// Directory of the default embedding cache. Each run's embedded store is
+    /// saved there under a hash of the input data, embedding model and
+    /// settings, and loaded instead of re-embedding when they match. Not used
+    /// with --store-file, which names the file itself.
+    #[arg(long, default_value = ".embedding-cache")]
+    pub cache_dir: PathBuf,
+
+    /// Save the finished vector store (profiles, summaries and vectors) to
+    /// this file, and on later runs load it instead of embedding the data
+    /// again. Rebuilt when the input data or embedding settings change.
+    #[arg(long)]
+    pub store_file: Option<PathBuf>,
+
+    /// Rebuild and overwrite --store-file even if it matches this run
+    #[arg(long, requires = "store_file")]
+    pub rebuild_store: bool,
+
+    /// Encoding used when saving --summary-cache and --store-file. Files
+    /// record their format, so either is read back regardless of this setting.
+    #[arg(long, value_enum, default_value_t = StoreFormat::Binary)]
+    pub store_format: StoreFormat,
+
+    /// JSONL file of precomputed embeddings, one `{"customer_id": ..., "vector":
+    /// [...]}` per line, used instead of calling the embedding API. Every
+    /// customer needs a vector of the embedding model's dimension (1536 for
+    /// ada-002).
+    #[arg(long)]
+    pub embeddings_file: Option<PathBuf>,
+
+    /// Stop launching new embedding chunks once this many embedding API calls
+    /// have been made. Records that were already embedded are still used.
+    #[arg(long)]
+    pub max_embedding_calls: Option<usize>,
+
+    /// Stop launching new embedding chunks once roughly this many tokens have
+    /// been sent for embedding
+    #[arg(long)]
+    pub max_embedding_tokens: Option<usize>,
+
+    /// Sample records from across the whole file and stop embedding once this
+    /// many profiles are in the store, instead of embedding every record
+    #[arg(long, value_name = "N")]
+    pub target_store_size: Option<usize>,
+
+    /// Sample --target-store-size records in proportion to this field's values
+    /// instead of uniformly at random
+    #[arg(long, value_enum, requires = "target_store_size")]
+    pub sample_stratify_by: Option<StratifyField>,
+
+    /// Seed for --target-store-size sampling, to draw the same sample again.
+    /// Defaults to the current time; the seed used is printed.
+    #[arg(long, requires = "target_store_size")]
+    pub sample_seed: Option<u64>,
+
+    /// What to do with profiles whose embedding has zero norm, which would make
+    /// their similarity scores NaN
+    #[arg(long, value_enum, default_value_t = ZeroNormPolicy::Drop)]
+    pub zero_norm: ZeroNormPolicy,
+
+    /// What to do when an embedding response has fewer vectors than records sent
+    #[arg(long, value_enum, default_value_t = MissingEmbeddingPolicy::Retry)]
+    pub missing_embeddings: MissingEmbeddingPolicy,
+
+    /// Text prepended to every profile summary before embedding, for
+    /// instruction-tuned models, e.g. "passage: " for e5. Empty for ada-002.
+    #[arg(long, default_value = "")]
+    pub document_prefix: String,
+
+    /// Text prepended to every query before embedding, e.g. "query: " for e5
+    #[arg(long, default_value = "")]
+    pub query_prefix: String,
+
+    /// Append normalized numeric fields (age, income, satisfaction, quality
+    /// ratings, purchase frequency) to each profile's text embedding. They
+    /// affect `similar`, clustering and centrality only; queries are compared
+    /// with the text part of each vector.
+    #[arg(long, value_enum)]
+    pub numeric_features: Option<NumericNormalization>,
+
+    /// Scale applied to the numeric components relative to the unit-length
+    /// text embedding
+    #[arg(long, default_value_t = 0.1)]
+    pub numeric_weight: f64,
+
+    /// Documents sent per embedding API request, independent of the 1000-record
+    /// processing chunk. Defaults to the chunk size.
+    #[arg(long)]
+    pub embed_batch_size: Option<usize>,
+
+    /// Processing chunks embedded concurrently. Higher values finish large
+    /// files sooner but trip OpenAI's rate limits sooner too, which is why the
+    /// pause after each embedding request is kept.
+    #[arg(long, default_value_t = 4)]
+    pub chunk_concurrency: usize,
+
+    /// Canonicalize Gender and Country spellings ("M" -> "Male", "United
+    /// States" -> "USA") using the built-in mapping
+    #[arg(long)]
+    pub normalize_fields: bool,
+
+    /// TOML or JSON file with `[gender]` / `[country]` tables extending the
+    /// built-in normalization mapping (implies --normalize-fields)
+    #[arg(long)]
+    pub normalization_map: Option<PathBuf>,
+
+    /// Validate the CSV and print record, chunk and API call counts as
+    /// key=value lines, then exit without calling any API
+    #[arg(long)]
+    pub count_only: bool,
+
+    /// Print the estimated embedding tokens, cost, chunks and requests for
+    /// this run, then exit without calling any API
+    #[arg(long, conflicts_with = "count_only")]
+    pub dry_run: bool,
+
+    /// Stream the CSV record by record instead of reading it into memory.
+    /// Enabled automatically for files of 512 MiB or more.
+    #[arg(long)]
+    pub streaming: bool,
+
+    /// Collapse multiple rows per customer into one profile whose summary
+    /// describes how their metrics changed over time (ordered by the Date
+    /// column when present, otherwise by file order)
+    #[arg(long)]
+    pub trend_summaries: bool,
+
+    /// Embed every row even when a customer id repeats, e.g. one per survey
+    /// submission. By default only the last row for each id is kept.
+    #[arg(long)]
+    pub keep_duplicates: bool,
+
+    /// Only analyze rows whose Date is on or after this day (YYYY-MM-DD).
+    /// Requires a Date column; undated rows are dropped.
+    #[arg(long)]
+    pub since: Option<NaiveDate>,
+
+    /// Only analyze rows whose Date is on or before this day (YYYY-MM-DD)
+    #[arg(long)]
+    pub until: Option<NaiveDate>,
+
+    /// Only embed and analyze customers from this country. Repeat the flag or
+    /// pass a comma-separated list to keep several; case-insensitive.
+    #[arg(long, value_delimiter = ',')]
+    pub country: Vec<String>,
+
+    /// Only embed and analyze customers with at least this income, in whole
+    /// currency units after --income-scale; rows without an income are dropped
+    #[arg(long)]
+    pub min_income: Option<f64>,
+
+    /// Only embed and analyze customers at this loyalty level. Repeat the
+    /// flag or pass a comma-separated list to keep several; case-insensitive.
+    #[arg(long, value_delimiter = ',')]
+    pub loyalty_level: Vec<String>,
+
+    /// How to handle records with a blank SatisfactionScore
+    #[arg(long, value_enum, default_value_t = MissingValuePolicy::Skip)]
+    pub missing_satisfaction: MissingValuePolicy,
+
+    /// How to handle records with a blank Income
+    #[arg(long, value_enum, default_value_t = MissingValuePolicy::Skip)]
+    pub missing_income: MissingValuePolicy,
+
+    /// Currency of the Income column, used when writing amounts in summaries
+    /// and prompts
+    #[arg(long, value_enum, default_value_t = Currency::Usd)]
+    pub income_currency: Currency,
+
+    /// Size of one Income unit in the file, e.g. 1000 when incomes are given
+    /// in thousands. Incomes are converted to whole units on load.
+    #[arg(long, default_value_t = 1.0)]
+    pub income_scale: f64,
+
+    /// Distinct queries whose retrieved profiles are kept in memory, so a
+    /// repeated query skips its embedding and the index search
+    #[arg(long, default_value_t = 256)]
+    pub retrieval_cache_size: usize,
+
+    /// Always embed and search, even for a query seen earlier in the run
+    #[arg(long)]
+    pub no_retrieval_cache: bool,
+
+    /// Query the daemon runs through the full pipeline before it starts
+    /// listening, to prime caches and connections. Repeatable; defaults to the
+    /// first example query.
+    #[arg(long = "warmup-query", value_name = "QUERY")]
+    pub warmup_queries: Vec<String>,
+
+    /// Project embeddings down to this many dimensions to save memory, at a
+    /// small cost in retrieval accuracy
+    #[arg(long, value_name = "N")]
+    pub reduce_dims: Option<usize>,
+
+    /// Where the --reduce-dims projection matrix is kept. Created on first
+    /// use and reused after, so stored and query vectors stay comparable.
+    #[arg(long, default_value = "projection.json")]
+    pub projection_file: PathBuf,
+
+    /// Start serving without running any warmup queries
+    #[arg(long, conflicts_with = "warmup_queries")]
+    pub no_warmup: bool,
+
+    /// Restrict every lookup to profiles matching this expression, e.g.
+    /// "(country = US OR country = Canada) AND satisfaction_score < 50".
+    /// Combine comparisons (=, !=, <, <=, >, >=) with AND, OR, NOT and
+    /// parentheses; quote text values containing spaces.
+    #[arg(long)]
+    pub filter: Option<Filter>,
+
+    /// Loyalty levels from lowest to highest, for loyalty_level comparisons in
+    /// --filter, health scores and ranking ties. Unlisted levels sort last.
+    #[arg(long, value_delimiter = ',', default_value = "Bronze,Silver,Gold,Platinum")]
+    pub loyalty_order: Vec<String>,
+
+    /// How profile scores are shown in printed output, reports and profile
+    /// exports. Ranking and the agent's prompt always use raw scores.
+    #[arg(long, value_enum, default_value_t = ScoreDisplay::Raw)]
+    pub score_display: ScoreDisplay,
+
+    /// Mapping from similarity to a percentage for --score-display percent
+    #[arg(long, value_enum, default_value_t = PercentTransform::Linear)]
+    pub percent_transform: PercentTransform,
+
+    /// Print the stages of the analysis pipeline and the settings they
+    /// resolved to before running any queries
+    #[arg(long)]
+    pub explain_pipeline: bool,
+
+    /// Write a JSON manifest of the run (command line, resolved arguments,
+    /// input hash, record counts, models, queries and estimated cost) when it
+    /// finishes, or a partial one if interrupted with Ctrl-C
+    #[arg(long, value_name = "PATH", num_args = 0..=1, default_missing_value = "run_manifest.json")]
+    pub run_manifest: Option<PathBuf>,
+
+    /// Append a JSONL record per query with the assembled prompt, retrieved
+    /// profiles and raw agent response
+    #[arg(long)]
+    pub transcript_file: Option<PathBuf>,
+
+    /// Write each query's analysis, retrieved profiles and confidence to this
+    /// file, in a form compare-runs can read back
+    #[arg(long)]
+    pub report_file: Option<PathBuf>,
+
+    /// Report layout: `json` writes one array when the run ends; `jsonl`
+    /// writes and flushes a line as each query completes, so partial runs
+    /// survive a crash and the file can be tailed
+    #[arg(long, value_enum, default_value_t = ReportFormat::Json)]
+    pub report_format: ReportFormat,
+
+    /// Resume an interrupted batch from its --report-format jsonl report:
+    /// queries that already have an analysis there (matched by exact text)
+    /// are skipped and new results are appended to the same file
+    #[arg(long, conflicts_with = "report_file")]
+    pub continue_from: Option<PathBuf>,
+
+    /// Write every retrieved profile (query, rank, score and key fields) to
+    /// this CSV file
+    #[arg(long)]
+    pub export_profiles: Option<PathBuf>,
+
+    /// Write one spreadsheet row per query: the query, the retrieved customer
+    /// ids, their mean score and the analysis split into its sections
+    #[arg(long, value_name = "PATH")]
+    pub export_sheet: Option<PathBuf>,
+
+    /// Field separator for --export-sheet
+    #[arg(long, value_enum, default_value_t = SheetFormat::Tsv)]
+    pub sheet_format: SheetFormat,
+
+    /// Start exported CSV files with a UTF-8 byte order mark, so Excel shows
+    /// non-ASCII text correctly
+    #[arg(long)]
+    pub csv_bom: bool,
+
+    /// Line endings for exported CSV files
+    #[arg(long, value_enum, default_value_t = LineEnding::Lf)]
+    pub csv_line_ending: LineEnding,
+
+    /// Favor recent feedback when a Date column is present: similarity is halved
+    /// for every this-many days a record is older than the newest record
+    #[arg(long)]
+    pub recency_half_life_days: Option<f64>,
+
+    /// Build the K-nearest-neighbor graph over the stored embeddings and
+    /// record each profile's in-degree (how many profiles have it as a
+    /// neighbor), shown in --explain output
+    #[arg(long, value_name = "K")]
+    pub knn_centrality: Option<usize>,
+
+    /// Add up to WEIGHT to the scores of central profiles, in proportion to
+    /// their --knn-centrality in-degree
+    #[arg(long, value_name = "WEIGHT", requires = "knn_centrality")]
+    pub centrality_boost: Option<f64>,
+
+    /// Group embedded profiles into K clusters (k-means) after ingestion and
+    /// report each query's nearest cluster and the clusters of retrieved profiles
+    #[arg(long, value_name = "K")]
+    pub cluster: Option<usize>,
+
+    /// Withhold these fields from the profiles sent to the agent, e.g.
+    /// `income,country`. Retrieval still uses the full embeddings.
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub redact_fields: Vec<ProfileField>,
+
+    /// Whether redacted fields are left out or shown as [REDACTED]
+    #[arg(long, value_enum, default_value_t = RedactMode::Mask)]
+    pub redact_mode: RedactMode,
+
+    /// Retry once with a reframed prompt when the analysis looks like a refusal
+    /// or non-answer
+    #[arg(long)]
+    pub retry_refusals: bool,
+
+    /// Check that the numbers an analysis cites appear in the profiles it was
+    /// given, flag the ones that don't, and report each query's support ratio
+    #[arg(long)]
+    pub verify_citations: bool,
+
+    /// Run every query once per analyst preamble and show the answers side by
+    /// side: `builtin`, `NAME=PATH` or `PATH`. Repeat for each variant; cost
+    /// grows with the number of variants.
+    #[arg(long = "preamble-variant", value_name = "VARIANT")]
+    pub preamble_variants: Vec<PreambleVariant>,
+
+    /// How retrieved profiles are laid out in the analysis prompt
+    #[arg(long, value_enum, default_value_t = PromptFormat::Blocks)]
+    pub prompt_format: PromptFormat,
+
+    /// Approximate token budget for the profile block in each analysis prompt.
+    /// The lowest-scoring profiles are dropped until it fits.
+    #[arg(long, default_value_t = 6000)]
+    pub max_context_tokens: usize,
+
+    /// Adjust a profile's score when a field value named in the query matches
+    /// the profile, as FIELD=WEIGHT (fields: gender, country, loyalty,
+    /// feedback), e.g. `country=0.05,loyalty=0.03`. Negative weights penalize.
+    #[arg(long, value_delimiter = ',', allow_hyphen_values = true)]
+    pub match_bonus: Vec<MatchBonus>,
+
+    /// Built-in business rules that boost matching profiles before top-k
+    /// selection, e.g. `churn-risk`. Repeat or comma-separate to combine.
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub rescore: Vec<RuleSet>,
+
+    /// When no retrieved profile reaches --retry-empty-below, have the agent
+    /// rewrite the query more generally and search again, up to N times (at
+    /// most 5). Each attempt costs a completion and an embedding request.
+    #[arg(long, value_name = "N")]
+    pub retry_empty: Option<usize>,
+
+    /// Similarity a retrieved profile must reach for --retry-empty to accept
+    /// the results
+    #[arg(long, default_value_t = 0.7)]
+    pub retry_empty_below: f64,
+
+    /// When the retrieved profiles are near-identical matches, sample them
+    /// across values of this field so the agent sees a varied cohort
+    #[arg(long, value_enum)]
+    pub stratify_by: Option<StratifyField>,
+
+    /// Score gap from the best match within which profiles count as
+    /// interchangeable for --stratify-by
+    #[arg(long, default_value_t = 0.01)]
+    pub stratify_within: f64,
+
+    /// Leave out retrieved profiles whose score is below this similarity, so
+    /// vague queries don't fill the prompt with noise. Unset keeps every match.
+    #[arg(long, value_name = "SCORE")]
+    pub min_score: Option<f64>,
+
+    /// Split each query into aspects, retrieve for each separately and combine
+    /// the rankings with reciprocal rank fusion. Suited to compound queries such
+    /// as "churn risk; high income".
+    #[arg(long, value_enum)]
+    pub aspects: Option<AspectStrategy>,
+
+    /// Cleanup steps applied to each analysis before it is printed, in order.
+    /// Repeat the flag or pass a comma-separated list to compose them.
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub post_process: Vec<BuiltinPostProcessor>,
+
+    /// Ask the agent for one output format and normalize every analysis to
+    /// it, so a batch reads consistently. Runs before --post-process steps.
+    #[arg(long, value_enum)]
+    pub analysis_format: Option<AnalysisFormat>,
+
+    /// Have the agent submit its analysis through rig's extractor as key
+    /// patterns, risk factors, recommendations and opportunities, printed as
+    /// pretty JSON instead of freeform text
+    #[arg(long, conflicts_with_all = ["analysis_format", "post_process"])]
+    pub structured: bool,
+
+    /// Print each analysis as the model generates it. Batch queries then run
+    /// one at a time so their output doesn't interleave.
+    #[arg(long, conflicts_with_all = ["structured", "analysis_format", "post_process", "fallback_model", "preamble_variants"])]
+    pub stream: bool,
+
+    /// After each analysis, explain which field groups (demographics, income,
+    /// quality, behavior, sentiment) drove each retrieved profile's similarity.
+    /// Costs one extra embedding request per query.
+    #[arg(long)]
+    pub explain: bool,
+
+    /// At the end of the run, rank queries by how strongly their retrieved
+    /// profiles matched and flag low-confidence ones
+    #[arg(long)]
+    pub top_queries: bool,
+
+    /// Retrieval score used to rank queries
+    #[arg(long, value_enum, default_value_t = ConfidenceMetric::Top1)]
+    pub confidence_metric: ConfidenceMetric,
+
+    /// Queries scoring below this are flagged as low confidence. Also the
+    /// per-profile similarity threshold for --annotate-confidence.
+    #[arg(long, default_value_t = 0.75)]
+    pub low_confidence_below: f64,
+
+    /// Flag a query as an ambiguous retrieval when two consecutive results
+    /// score within this gap of each other yet describe clearly different
+    /// customers
+    #[arg(long, value_name = "GAP")]
+    pub min_score_gap: Option<f64>,
+
+    /// Print a confidence score derived from retrieval quality with each
+    /// analysis, and record it in the transcript
+    #[arg(long)]
+    pub annotate_confidence: bool,
+
+    /// Pass the confidence score to the agent and instruct it to hedge its
+    /// conclusions when confidence is low
+    #[arg(long)]
+    pub hedge_low_confidence: bool,
+
+    /// Leave the dataset-wide income, satisfaction, purchase frequency and
+    /// loyalty statistics out of the analyst preamble
+    #[arg(long)]
+    pub no_dataset_stats: bool,
+
+    /// Stop the query run at the first query that fails
+    #[arg(long)]
+    pub stop_on_error: bool,
+
+    /// Stop the query run after N consecutive queries whose retrieval score
+    /// is below --low-confidence-below
+    #[arg(long, value_name = "N")]
+    pub stop_after: Option<usize>,
+
+    /// Wall-clock budget for the query run, e.g. `90s`, `15m` or `2h`. No new
+    /// query starts once it is used up.
+    #[arg(long, value_parser = stop::parse_duration)]
+    pub max_duration: Option<Duration>,
+
+    /// At the end of the run, print estimated requests, tokens and cost for
+    /// ingestion (embeddings) and analysis (completions) separately
+    #[arg(long)]
+    pub explain_cost: bool,
+
+    /// Print full error chains (and backtraces) instead of only the top-level message
+    #[arg(long)]
+    pub verbose_errors: bool,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Embed a query and print the raw nearest-neighbor vectors and scores,
+    /// bypassing profile formatting and the analysis agent
+    RawSearch {
+        /// Text to embed and search for
+        query: String,
+        /// Number of nearest neighbors to return
+        #[arg(long, default_value_t = 5)]
+        top_k: usize,
+    },
+    /// List the customers most similar to an existing customer, using only the
+    /// stored embeddings (no query or agent involved)
+    Similar {
+        /// Customer ID to find neighbors for
+        customer_id: String,
+        /// Number of similar customers to return
+        #[arg(long, default_value_t = 5)]
+        top_k: usize,
+    },
+    /// Write the pairwise cosine similarity of a subset of customers' stored
+    /// embeddings to a CSV matrix (no query or agent involved)
+    SimilarityMatrix {
+        /// Only these customer ids (comma-separated or repeated)
+        #[arg(long = "customer", value_delimiter = ',')]
+        customers: Vec<String>,
+        /// Only customers whose FIELD equals VALUE, e.g. `country=UK`;
+        /// fields are country, gender and loyalty-level. Repeat to combine.
+        #[arg(long = "where")]
+        filters: Vec<SubsetFilter>,
+        /// Matrix CSV to write
+        #[arg(long, default_value = "similarity_matrix.csv")]
+        output: PathBuf,
+        /// Largest subset accepted; the matrix grows with its square
+        #[arg(long, default_value_t = 500)]
+        max_customers: usize,
+    },
+    /// Look up customers by a partial or mistyped id, ranked by edit distance.
+    /// Works on the loaded records alone; no embeddings are generated unless
+    /// --similar is given.
+    FindCustomer {
+        /// Full, partial or approximate customer ID
+        partial: String,
+        /// Number of candidates to list
+        #[arg(long, default_value_t = 5)]
+        limit: usize,
+        /// Also list the K customers most similar to the best candidate
+        #[arg(long, value_name = "K")]
+        similar: Option<usize>,
+    },
+    /// Print the embedding summaries generated for a sample of records, without
+    /// calling any API. Useful when tuning summary phrasing.
+    PreviewSummaries {
+        /// Number of records to show, spread evenly across the file
+        #[arg(long, default_value_t = 5)]
+        sample: usize,
+    },
+    /// Rank every customer by a composite health score computed from the
+    /// numeric fields and write the ranking to a CSV file. No API calls.
+    RankCustomers {
+        /// CSV file to write
+        #[arg(long, default_value = "customer_health.csv")]
+        output: PathBuf,
+        /// Factor weights as FACTOR=WEIGHT (factors: satisfaction, loyalty,
+        /// purchase-frequency, product-quality, service-quality). Unlisted
+        /// factors keep their defaults of 0.35, 0.2, 0.15, 0.15 and 0.15.
+        #[arg(long, value_delimiter = ',')]
+        weights: Vec<HealthWeight>,
+        /// Ranking direction
+        #[arg(long, value_enum, default_value_t = RankOrder::AtRisk)]
+        order: RankOrder,
+    },
+    /// List every profile the store holds (customer id, embedding dimension,
+    /// norm, summary) from the loaded records and --summary-cache, without
+    /// calling any API
+    InspectStore {
+        /// Manifest format
+        #[arg(long, value_enum, default_value_t = ManifestFormat::Table)]
+        format: ManifestFormat,
+        /// Write the manifest to this file instead of stdout
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Cluster the embedded profiles with k-means and have the agent name and
+    /// describe each segment from its most representative members
+    DiscoverSegments {
+        /// Number of segments to look for
+        #[arg(long, default_value_t = 5)]
+        k: usize,
+        /// Representative profiles sent to the agent per segment
+        #[arg(long, default_value_t = 8)]
+        sample: usize,
+    },
+    /// Ask the agent a fixed question about each customer, with its nearest
+    /// neighbors as context, and write the answers as a new Recommendation
+    /// column next to the record's fields. Rerunning resumes an interrupted
+    /// output file.
+    Enrich {
+        /// Enriched CSV to write (or resume)
+        #[arg(long, default_value = "enriched_customers.csv")]
+        output: PathBuf,
+        /// Question asked for every customer
+        #[arg(long)]
+        instruction: Option<String>,
+        /// Only these customer ids (comma-separated or repeated)
+        #[arg(long = "customer", value_delimiter = ',')]
+        customers: Vec<String>,
+        /// Stop after this many customers
+        #[arg(long)]
+        limit: Option<usize>,
+        /// Similar customers included as context
+        #[arg(long, default_value_t = 3)]
+        neighbors: usize,
+    },
+    /// Produce an executive summary of the whole dataset by summarizing chunks
+    /// of profiles with the agent and then summarizing those summaries
+    Summarize {
+        /// Approximate token budget per summarization request, sized for the
+        /// completion model's context window
+        #[arg(long, default_value_t = 6000)]
+        max_chunk_tokens: usize,
+        /// Also print the intermediate per-chunk summaries
+        #[arg(long)]
+        show_chunks: bool,
+    },
+    /// Analyze a single query. Answered by the daemon if one is running,
+    /// otherwise the store is built in-process first.
+    Query {
+        /// The analysis question
+        text: String,
+    },
+    /// Keep the store and agent in memory in a background process, so
+    /// repeated `query` invocations skip loading and embedding
+    Daemon {
+        #[command(subcommand)]
+        action: DaemonAction,
+    },
+    /// Check a queries file for empty lines, duplicates, very short queries
+    /// and overlong lines without running it. Exits non-zero on errors.
+    LintQueries {
+        /// Queries file, in the --queries-file format
+        file: PathBuf,
+        /// Longest query accepted, in characters
+        #[arg(long, default_value_t = 2000)]
+        max_chars: usize,
+    },
+    /// Ask the agent how findings changed across two or more persisted runs
+    /// (JSON reports or JSONL transcripts), comparing only shared queries
+    CompareRuns {
+        /// Run output files, at least two
+        #[arg(required = true, num_args = 2..)]
+        runs: Vec<PathBuf>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Subcommand)]
+pub enum DaemonAction {
+    /// Build the store and start serving on rig-pipeline.sock in the
+    /// background. Pipeline flags given here apply to every query it serves.
+    Start,
+    /// Stop the running daemon
+    Stop,
+    /// Report whether a daemon is running
+    Status,
+    /// Run the daemon in the foreground (used by `daemon start`)
+    #[command(hide = true)]
+    Serve,
+}
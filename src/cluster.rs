@@ -0,0 +1,132 @@
+use crate::models::CustomerFeedback;
+use rig::{embeddings::Embedding, OneOrMany};
+use std::collections::HashMap;
+
+const MAX_ITERATIONS: usize = 25;
+
+fn squared_distance(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).map(|(x, y)| (x - y) * (x - y)).sum()
+}
+
+fn nearest_centroid(centroids: &[Vec<f64>], vector: &[f64]) -> usize {
+    centroids
+        .iter()
+        .enumerate()
+        .map(|(i, centroid)| (i, squared_distance(centroid, vector)))
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(i, _)| i)
+        .unwrap_or(0)
+}
+
+// Lloyd's k-means with deterministic initialization (evenly spaced input vectors),
+// so repeated runs over the same data produce the same clusters. Returns the
+// centroids and each vector's cluster index.
+pub fn kmeans(vectors: &[&[f64]], k: usize) -> (Vec<Vec<f64>>, Vec<usize>) {
+    let k = k.min(vectors.len());
+    if k == 0 {
+        return (Vec::new(), vec![0; vectors.len()]);
+    }
+
+    let step = vectors.len() / k;
+    let mut centroids: Vec<Vec<f64>> = (0..k).map(|i| vectors[i * step].to_vec()).collect();
+    let mut assignments = vec![usize::MAX; vectors.len()];
+
+    for _ in 0..MAX_ITERATIONS {
+        let mut changed = false;
+        for (vector, assignment) in vectors.iter().zip(assignments.iter_mut()) {
+            let nearest = nearest_centroid(&centroids, vector);
+            if *assignment != nearest {
+                *assignment = nearest;
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+
+        let dims = centroids[0].len();
+        let mut sums = vec![vec![0.0; dims]; k];
+        let mut counts = vec![0usize; k];
+        for (vector, &cluster) in vectors.iter().zip(&assignments) {
+            counts[cluster] += 1;
+            for (sum, value) in sums[cluster].iter_mut().zip(vector.iter()) {
+                *sum += value;
+            }
+        }
+        // Empty clusters keep their previous centroid
+        for ((centroid, sum), count) in centroids.iter_mut().zip(sums).zip(counts) {
+            if count > 0 {
+                *centroid = sum.into_iter().map(|v| v / count as f64).collect();
+            }
+        }
+    }
+
+    (centroids, assignments)
+}
+
+// Cluster assignments for every embedded profile, computed once at ingestion
+pub struct Clustering {
+    centroids: Vec<Vec<f64>>,
+    assignments: HashMap<String, usize>,
+    sizes: Vec<usize>,
+}
+
+impl Clustering {
+    pub fn fit(embeddings: &[(CustomerFeedback, OneOrMany<Embedding>)], k: usize) -> Self {
+        let vectors: Vec<&[f64]> = embeddings
+            .iter()
+            .map(|(_, e)| e.iter().next().map(|e| e.vec.as_slice()).unwrap_or(&[]))
+            .collect();
+        let (centroids, labels) = kmeans(&vectors, k);
+
+        let mut sizes = vec![0; centroids.len()];
+        for &label in &labels {
+            sizes[label] += 1;
+        }
+        let assignments = embeddings
+            .iter()
+            .zip(labels)
+            .map(|((customer, _), label)| (customer.customer_id.clone(), label))
+            .collect();
+
+        Self { centroids, assignments, sizes }
+    }
+
+    pub fn nearest(&self, vector: &[f64]) -> usize {
+        nearest_centroid(&self.centroids, vector)
+    }
+
+    pub fn cluster_of(&self, customer_id: &str) -> Option<usize> {
+        self.assignments.get(customer_id).copied()
+    }
+
+    pub fn sizes(&self) -> &[usize] {
+        &self.sizes
+    }
+
+    // Members of `cluster` with their cosine similarity to its centroid,
+    // closest (most representative) first
+    pub fn members<'a>(
+        &self,
+        embeddings: &'a [(CustomerFeedback, OneOrMany<Embedding>)],
+        cluster: usize,
+    ) -> Vec<(f64, &'a CustomerFeedback)> {
+        let Some(centroid) = self.centroids.get(cluster) else {
+            return Vec::new();
+        };
+        let centroid_norm = centroid.iter().map(|v| v * v).sum::<f64>().sqrt();
+        let mut members: Vec<(f64, &CustomerFeedback)> = embeddings
+            .iter()
+            .filter(|(customer, _)| self.cluster_of(&customer.customer_id) == Some(cluster))
+            .map(|(customer, embedding)| {
+                let vector = &embedding.first().vec;
+                let norm = vector.iter().map(|v| v * v).sum::<f64>().sqrt();
+                let dot: f64 = vector.iter().zip(centroid).map(|(a, b)| a * b).sum();
+                let similarity = if norm > 0.0 && centroid_norm > 0.0 { dot / (norm * centroid_norm) } else { 0.0 };
+                (similarity, customer)
+            })
+            .collect();
+        members.sort_by(|a, b| b.0.total_cmp(&a.0).then_with(|| a.1.cmp(b.1)));
+        members
+    }
+}
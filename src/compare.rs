@@ -0,0 +1,107 @@
+use chrono::{DateTime, Utc};
+use rig::{
+    agent::Agent,
+    completion::{CompletionModel, Prompt},
+};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+pub const COMPARE_PREAMBLE: &str = r#"
+    You are an expert customer insights analyst reviewing how findings changed over time.
+    You will be given one analysis query and the analyses produced for it by several runs,
+    oldest first. Report concisely, in diff style:
+    + findings that are new in later runs
+    - findings that disappeared
+    ~ findings whose figures or emphasis changed
+    Do not restate findings that stayed the same.
+"#;
+
+// One analyzed query from a persisted run. Accepts report records (`analysis`,
+// `timestamp`) as well as transcript lines (`response`, `started_at`).
+#[derive(Debug, Deserialize)]
+struct RunRecord {
+    query: String,
+    #[serde(alias = "response")]
+    analysis: Option<String>,
+    #[serde(alias = "started_at")]
+    timestamp: Option<DateTime<Utc>>,
+}
+
+struct Run {
+    path: PathBuf,
+    started: Option<DateTime<Utc>>,
+    analyses: HashMap<String, String>,
+}
+
+// Read a run from a JSON array or a JSONL file
+fn load_run(path: &Path) -> Result<Run, anyhow::Error> {
+    let content = std::fs::read_to_string(path)?;
+    let records: Vec<RunRecord> = if content.trim_start().starts_with('[') {
+        serde_json::from_str(&content)?
+    } else {
+        content
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()?
+    };
+
+    let started = records.iter().filter_map(|r| r.timestamp).min();
+    let analyses = records
+        .into_iter()
+        .filter_map(|r| r.analysis.map(|analysis| (r.query, analysis)))
+        .collect();
+
+    Ok(Run { path: path.to_path_buf(), started, analyses })
+}
+
+// For every query answered by all runs, ask the agent what changed between them.
+// Returns (query, report) pairs in the order the queries appear in the first run file.
+pub async fn compare_runs<M: CompletionModel>(
+    agent: &Agent<M>,
+    paths: &[PathBuf],
+) -> Result<Vec<(String, String)>, anyhow::Error> {
+    if paths.len() < 2 {
+        return Err(anyhow::anyhow!("compare-runs needs at least two run files"));
+    }
+
+    let mut runs = paths.iter().map(|p| load_run(p)).collect::<Result<Vec<_>, _>>()?;
+    // Oldest run first; runs without timestamps keep their command-line order
+    runs.sort_by_key(|run| run.started);
+
+    let mut shared: Vec<&String> = runs[0]
+        .analyses
+        .keys()
+        .filter(|query| runs[1..].iter().all(|run| run.analyses.contains_key(*query)))
+        .collect();
+    shared.sort();
+
+    let total: usize = runs.iter().map(|run| run.analyses.len()).max().unwrap_or(0);
+    println!(
+        "Comparing {} runs on {} shared queries ({} queries not present in every run are skipped)",
+        runs.len(),
+        shared.len(),
+        total.saturating_sub(shared.len())
+    );
+
+    let mut reports = Vec::with_capacity(shared.len());
+    for query in shared {
+        let analyses = runs
+            .iter()
+            .enumerate()
+            .map(|(i, run)| {
+                let when = run
+                    .started
+                    .map(|t| t.to_rfc3339())
+                    .unwrap_or_else(|| run.path.display().to_string());
+                format!("Run {} ({}):\n{}", i + 1, when, run.analyses[query])
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        let prompt = format!("Analysis Query: {}\n\n{}", query, analyses);
+        reports.push((query.clone(), agent.prompt(&prompt).await?));
+    }
+
+    Ok(reports)
+}
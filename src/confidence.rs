@@ -0,0 +1,125 @@
+use crate::models::{CustomerFeedback, RetrievedProfile};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ConfidenceMetric {
+    /// Score of the best-matching profile
+    Top1,
+    /// Mean score over all retrieved profiles
+    Mean,
+}
+
+// How well a query's retrieved profiles matched it, or None if nothing was retrieved
+pub fn retrieval_score(profiles: &[RetrievedProfile], metric: ConfidenceMetric) -> Option<f64> {
+    if profiles.is_empty() {
+        return None;
+    }
+    Some(match metric {
+        ConfidenceMetric::Top1 => profiles.iter().map(|p| p.score).fold(f64::NEG_INFINITY, f64::max),
+        ConfidenceMetric::Mean => profiles.iter().map(|p| p.score).sum::<f64>() / profiles.len() as f64,
+    })
+}
+
+// Queries ranked by retrieval score, strongest first, with those below
+// `low_threshold` (or with no profiles at all) flagged
+pub fn top_queries_table(scores: &[(String, Option<f64>)], low_threshold: f64) -> String {
+    let mut ranked: Vec<&(String, Option<f64>)> = scores.iter().collect();
+    ranked.sort_by(|a, b| {
+        b.1.unwrap_or(f64::NEG_INFINITY).total_cmp(&a.1.unwrap_or(f64::NEG_INFINITY))
+    });
+
+    let mut table = format!("{:>4}  {:>7}  {:<4}  Query\n", "Rank", "Score", "");
+    for (i, (query, score)) in ranked.into_iter().enumerate() {
+        let (score, flag) = match score {
+            Some(score) => (format!("{:.3}", score), if *score < low_threshold { "LOW" } else { "" }),
+            None => ("-".to_string(), "LOW"),
+        };
+        table.push_str(&format!("{:>4}  {:>7}  {:<4}  {}\n", i + 1, score, flag, query));
+    }
+    table
+}
+
+// Appended to the analyst preamble with --hedge-low-confidence
+pub const HEDGE_INSTRUCTIONS: &str = r#"
+    Each query states a retrieval confidence computed from how closely the
+    profiles matched it. When confidence is low, say so up front, keep
+    conclusions tentative and avoid generalizing beyond the profiles shown.
+"#;
+
+// Self-describing trust signal for one analysis, derived from its lookup
+#[derive(Debug, Clone, Serialize)]
+pub struct Confidence {
+    pub score: f64,
+    pub level: &'static str,
+    pub mean_similarity: f64,
+    pub above_threshold: usize,
+    pub cohort: usize,
+    pub threshold: f64,
+}
+
+// Mean similarity scaled by the share of the expected `top_k` profiles that
+// cleared `threshold`, so a thin or weak cohort both lower the score
+pub fn assess(profiles: &[RetrievedProfile], threshold: f64, top_k: usize) -> Confidence {
+    let mean_similarity = retrieval_score(profiles, ConfidenceMetric::Mean).unwrap_or(0.0);
+    let above_threshold = profiles.iter().filter(|p| p.score >= threshold).count();
+    let coverage = above_threshold as f64 / top_k.max(1) as f64;
+    let score = (mean_similarity.clamp(0.0, 1.0) * coverage).min(1.0);
+    let level = if score >= 0.75 {
+        "high"
+    } else if score >= 0.5 {
+        "medium"
+    } else {
+        "low"
+    };
+    Confidence { score, level, mean_similarity, above_threshold, cohort: profiles.len(), threshold }
+}
+
+impl fmt::Display for Confidence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:.2} ({}; mean similarity {:.3}, {}/{} profiles at or above {})",
+            self.score, self.level, self.mean_similarity, self.above_threshold, self.cohort, self.threshold
+        )
+    }
+}
+
+// Two consecutive results that score almost the same but describe quite
+// different customers, so the lookup couldn't tell which case the query is about
+#[derive(Debug, Clone)]
+pub struct Ambiguity {
+    // 1-based rank of the first result of the pair
+    pub rank: usize,
+    pub gap: f64,
+    pub differing: Vec<&'static str>,
+}
+
+fn differing_fields(a: &CustomerFeedback, b: &CustomerFeedback) -> Vec<&'static str> {
+    [
+        ("gender", a.gender.eq_ignore_ascii_case(&b.gender)),
+        ("country", a.country.eq_ignore_ascii_case(&b.country)),
+        ("loyalty", a.loyalty_level.eq_ignore_ascii_case(&b.loyalty_level)),
+        ("feedback", a.feedback_score.eq_ignore_ascii_case(&b.feedback_score)),
+    ]
+    .into_iter()
+    .filter(|(_, same)| !same)
+    .map(|(field, _)| field)
+    .collect()
+}
+
+// Consecutive results closer than `min_gap` whose profiles differ in at least
+// half of their categorical fields. `documents` is in the same order as `profiles`.
+pub fn ambiguous_pairs(profiles: &[RetrievedProfile], documents: &[CustomerFeedback], min_gap: f64) -> Vec<Ambiguity> {
+    profiles
+        .windows(2)
+        .zip(documents.windows(2))
+        .enumerate()
+        .filter_map(|(i, (scores, docs))| {
+            let gap = (scores[0].score - scores[1].score).abs();
+            let differing = differing_fields(&docs[0], &docs[1]);
+            (gap < min_gap && differing.len() >= 2).then_some(Ambiguity { rank: i + 1, gap, differing })
+        })
+        .collect()
+}
@@ -0,0 +1,54 @@
+use crate::models::CustomerFeedback;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+// Currency the Income column is denominated in; only affects how amounts are written
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Currency {
+    #[default]
+    Usd,
+    Eur,
+    Gbp,
+    Jpy,
+    Chf,
+    Cad,
+    Aud,
+    Inr,
+}
+
+impl Currency {
+    fn symbol(self) -> &'static str {
+        match self {
+            Currency::Usd => "$",
+            Currency::Eur => "€",
+            Currency::Gbp => "£",
+            Currency::Jpy => "¥",
+            Currency::Chf => "CHF ",
+            Currency::Cad => "CA$",
+            Currency::Aud => "A$",
+            Currency::Inr => "₹",
+        }
+    }
+
+    pub fn format(self, amount: f64) -> String {
+        format!("{}{:.2}", self.symbol(), amount)
+    }
+}
+
+// How to read the Income column: `scale` is the size of one unit in the file
+// (1000 when values are in thousands), so 45.5 with scale 1000 is 45,500
+#[derive(Debug, Clone, Copy)]
+pub struct CurrencyConfig {
+    pub currency: Currency,
+    pub scale: f64,
+}
+
+impl CurrencyConfig {
+    // Convert the record's income to whole currency units and tag it with the
+    // currency, so summaries, prompts and income thresholds all see real amounts
+    pub fn apply(&self, customer: &mut CustomerFeedback) {
+        customer.income = customer.income.map(|income| income * self.scale);
+        customer.currency = self.currency;
+    }
+}
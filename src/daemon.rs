@@ -0,0 +1,255 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+// Lifecycle files live in the working directory, next to the data they serve
+pub const SOCKET_PATH: &str = "rig-pipeline.sock";
+pub const PID_PATH: &str = "rig-pipeline.pid";
+const LOG_PATH: &str = "rig-pipeline.log";
+// How long a client may take to send its request line before it is dropped
+#[cfg(unix)]
+const REQUEST_READ_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+// One JSON line per request and per response
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DaemonRequest {
+    Query { query: String },
+    Status,
+    Stop,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DaemonResponse {
+    pub ok: bool,
+    pub output: String,
+}
+
+#[cfg(unix)]
+mod unix {
+    use super::*;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::{UnixListener, UnixStream};
+    use tokio::signal::unix::{signal, Signal, SignalKind};
+
+    // Send a request to the running daemon. Returns None if no daemon is listening.
+    pub async fn send(request: &DaemonRequest) -> Result<Option<DaemonResponse>, anyhow::Error> {
+        let stream = match UnixStream::connect(SOCKET_PATH).await {
+            Ok(stream) => stream,
+            Err(_) => return Ok(None),
+        };
+        let (reader, mut writer) = stream.into_split();
+        writer.write_all(format!("{}\n", serde_json::to_string(request)?).as_bytes()).await?;
+
+        let mut line = String::new();
+        BufReader::new(reader).read_line(&mut line).await?;
+        Ok(Some(serde_json::from_str(&line)?))
+    }
+
+    // Launch `daemon serve` with the same flags in the background and wait for
+    // its socket to come up. Building the store can take a while for big files.
+    pub async fn start() -> Result<(), anyhow::Error> {
+        if send(&DaemonRequest::Status).await?.is_some() {
+            return Err(anyhow::anyhow!("A daemon is already listening on {}", SOCKET_PATH));
+        }
+
+        let mut args: Vec<std::ffi::OsString> = std::env::args_os().skip(1).collect();
+        let action = serve_position(&args).ok_or_else(|| anyhow::anyhow!("Cannot find the `daemon start` subcommand in the arguments"))?;
+        args[action] = "serve".into();
+        let log = std::fs::File::create(LOG_PATH)?;
+        let mut child = std::process::Command::new(std::env::current_exe()?)
+            .args(args)
+            .stdin(std::process::Stdio::null())
+            .stdout(log.try_clone()?)
+            .stderr(log)
+            .spawn()?;
+        println!("Started daemon (pid {}), building the store; output goes to {}", child.id(), LOG_PATH);
+
+        loop {
+            tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+            if let Some(status) = child.try_wait()? {
+                return Err(anyhow::anyhow!("Daemon exited during startup ({}); see {}", status, LOG_PATH));
+            }
+            if send(&DaemonRequest::Status).await?.is_some() {
+                println!("Daemon ready on {}", SOCKET_PATH);
+                return Ok(());
+            }
+        }
+    }
+
+    // Accepts requests for the daemon process and removes its lifecycle files on drop
+    pub struct DaemonListener {
+        listener: UnixListener,
+        interrupt: Signal,
+        terminate: Signal,
+        served: usize,
+    }
+
+    impl DaemonListener {
+        pub fn bind() -> Result<Self, anyhow::Error> {
+            // A socket file left behind by a crashed daemon would make bind fail
+            if Path::new(SOCKET_PATH).exists() {
+                std::fs::remove_file(SOCKET_PATH)?;
+            }
+            let listener = UnixListener::bind(SOCKET_PATH)?;
+            std::fs::write(PID_PATH, std::process::id().to_string())?;
+            Ok(Self {
+                listener,
+                interrupt: signal(SignalKind::interrupt())?,
+                terminate: signal(SignalKind::terminate())?,
+                served: 0,
+            })
+        }
+
+        // The next request, or None once SIGINT or SIGTERM arrives. Signals are
+        // only acted on here, between requests, so a query in progress is
+        // always answered first; one received meanwhile is handled on return.
+        pub async fn next(&mut self) -> Option<(DaemonRequest, DaemonConnection)> {
+            let received = tokio::select! {
+                biased;
+                _ = self.terminate.recv() => "SIGTERM",
+                _ = self.interrupt.recv() => "SIGINT",
+                request = next_request(&self.listener) => {
+                    self.served += 1;
+                    return Some(request);
+                }
+            };
+            println!(
+                "Received {}; drained {} requests, none left in progress. Shutting down.",
+                received, self.served
+            );
+            None
+        }
+    }
+
+    // The next well-formed request. A client that fails, disconnects or
+    // stalls only loses its own connection; the daemon keeps serving.
+    async fn next_request(listener: &UnixListener) -> (DaemonRequest, DaemonConnection) {
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    tracing::warn!("Failed to accept a daemon connection: {}", e);
+                    continue;
+                }
+            };
+            let (reader, writer) = stream.into_split();
+            let mut line = String::new();
+            match tokio::time::timeout(REQUEST_READ_TIMEOUT, BufReader::new(reader).read_line(&mut line)).await {
+                Ok(Ok(_)) => {}
+                Ok(Err(e)) => {
+                    tracing::warn!("Failed to read a daemon request: {}", e);
+                    continue;
+                }
+                Err(_) => {
+                    tracing::warn!("Dropping a daemon client that sent no request within {:?}", REQUEST_READ_TIMEOUT);
+                    continue;
+                }
+            }
+            match serde_json::from_str(&line) {
+                Ok(request) => return (request, DaemonConnection { writer }),
+                Err(e) => tracing::warn!("Ignoring malformed daemon request: {}", e),
+            }
+        }
+    }
+
+    impl Drop for DaemonListener {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(SOCKET_PATH);
+            let _ = std::fs::remove_file(PID_PATH);
+        }
+    }
+
+    pub struct DaemonConnection {
+        writer: tokio::net::unix::OwnedWriteHalf,
+    }
+
+    impl DaemonConnection {
+        // A client that went away before its answer is logged, not an error
+        pub async fn respond(mut self, ok: bool, output: String) {
+            let response = match serde_json::to_string(&DaemonResponse { ok, output }) {
+                Ok(response) => response,
+                Err(e) => {
+                    tracing::warn!("Failed to encode a daemon response: {}", e);
+                    return;
+                }
+            };
+            if let Err(e) = self.writer.write_all(format!("{}\n", response).as_bytes()).await {
+                tracing::warn!("Failed to answer a daemon client: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+pub use unix::{send, start, DaemonListener};
+
+// Index of the `start` action in `daemon start`. Only that position is
+// rewritten for the serving child, so a flag value or path that happens to be
+// "start" is passed through unchanged. Clap accepts `start` nowhere else as a
+// bare argument right after `daemon`.
+#[cfg(unix)]
+fn serve_position(args: &[std::ffi::OsString]) -> Option<usize> {
+    args.windows(2).position(|pair| pair[0] == "daemon" && pair[1] == "start").map(|i| i + 1)
+}
+
+#[cfg(not(unix))]
+pub async fn send(_request: &DaemonRequest) -> Result<Option<DaemonResponse>, anyhow::Error> {
+    Ok(None)
+}
+
+#[cfg(not(unix))]
+pub struct DaemonListener;
+
+#[cfg(not(unix))]
+pub struct DaemonConnection;
+
+#[cfg(not(unix))]
+impl DaemonListener {
+    pub fn bind() -> Result<Self, anyhow::Error> {
+        Err(anyhow::anyhow!("The daemon needs Unix domain sockets and is not available on this platform"))
+    }
+
+    pub async fn next(&mut self) -> Option<(DaemonRequest, DaemonConnection)> {
+        unreachable!("DaemonListener cannot be bound on this platform")
+    }
+}
+
+#[cfg(not(unix))]
+impl DaemonConnection {
+    pub async fn respond(self, _ok: bool, _output: String) {}
+}
+
+#[cfg(not(unix))]
+pub async fn start() -> Result<(), anyhow::Error> {
+    Err(anyhow::anyhow!("The daemon needs Unix domain sockets and is not available on this platform"))
+}
+
+// Daemon state as seen from the PID and socket files, cleaning up files left
+// by a daemon that is no longer running
+pub async fn status() -> Result<String, anyhow::Error> {
+    let pid = std::fs::read_to_string(PID_PATH).ok();
+    match send(&DaemonRequest::Status).await? {
+        Some(response) => Ok(response.output),
+        None if pid.is_some() || Path::new(SOCKET_PATH).exists() => {
+            let _ = std::fs::remove_file(SOCKET_PATH);
+            let _ = std::fs::remove_file(PID_PATH);
+            Ok("not running (removed stale socket/PID files)".to_string())
+        }
+        None => Ok("not running".to_string()),
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_start_action_becomes_serve() {
+        let args: Vec<std::ffi::OsString> = ["--warmup-query", "start", "--data-path", "daemon", "daemon", "start", "--top-k", "3"]
+            .iter()
+            .map(Into::into)
+            .collect();
+        assert_eq!(serve_position(&args), Some(5));
+        assert_eq!(serve_position(&args[..4]), None);
+    }
+}
@@ -0,0 +1,127 @@
+use crate::models::CustomerFeedback;
+use clap::ValueEnum;
+use rig::{embeddings::Embedding, OneOrMany};
+
+// Component used in place of a degenerate vector's values under `Epsilon`
+const EPSILON: f64 = 1e-9;
+
+// What to do with embeddings whose norm is zero (or not finite), for which
+// cosine similarity is undefined
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ZeroNormPolicy {
+    /// Leave the profile out of the store
+    Drop,
+    /// Keep the embedding as is and only log it
+    Warn,
+    /// Replace the vector with a tiny constant one so scores stay finite
+    Epsilon,
+}
+
+pub fn is_degenerate(vec: &[f64]) -> bool {
+    let norm_squared: f64 = vec.iter().map(|v| v * v).sum();
+    norm_squared == 0.0 || !norm_squared.is_finite()
+}
+
+// Check every embedding of every profile, logging the affected customer ids
+pub fn apply_zero_norm_policy(
+    embeddings: Vec<(CustomerFeedback, OneOrMany<Embedding>)>,
+    policy: ZeroNormPolicy,
+) -> Vec<(CustomerFeedback, OneOrMany<Embedding>)> {
+    let mut affected = Vec::new();
+    let kept = embeddings
+        .into_iter()
+        .filter_map(|(customer, vectors)| {
+            if !vectors.iter().any(|embedding| is_degenerate(&embedding.vec)) {
+                return Some((customer, vectors));
+            }
+            affected.push(customer.customer_id.clone());
+            match policy {
+                ZeroNormPolicy::Drop => None,
+                ZeroNormPolicy::Warn => Some((customer, vectors)),
+                ZeroNormPolicy::Epsilon => {
+                    let repaired: Vec<Embedding> = vectors
+                        .into_iter()
+                        .map(|mut embedding| {
+                            if is_degenerate(&embedding.vec) {
+                                embedding.vec = vec![EPSILON; embedding.vec.len()];
+                            }
+                            embedding
+                        })
+                        .collect();
+                    Some((customer, OneOrMany::many(repaired).ok()?))
+                }
+            }
+        })
+        .collect();
+
+    if !affected.is_empty() {
+        tracing::warn!(
+            "{} profiles have zero-norm embeddings ({}): {}",
+            affected.len(),
+            match policy {
+                ZeroNormPolicy::Drop => "dropped",
+                ZeroNormPolicy::Warn => "kept as is",
+                ZeroNormPolicy::Epsilon => "replaced with epsilon vectors",
+            },
+            affected.join(", ")
+        );
+    }
+    kept
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rig::embeddings::distance::VectorDistance;
+
+    fn profile(id: &str, vec: Vec<f64>) -> (CustomerFeedback, OneOrMany<Embedding>) {
+        let customer = CustomerFeedback {
+            customer_id: id.to_string(),
+            age: 30,
+            gender: "Male".to_string(),
+            country: "UK".to_string(),
+            income: None,
+            product_quality: 5,
+            service_quality: 5,
+            purchase_frequency: 1,
+            feedback_score: "Low".to_string(),
+            loyalty_level: "Bronze".to_string(),
+            satisfaction_score: None,
+            date: None,
+            profile_summary: String::new(),
+            currency: Default::default(),
+        };
+        (customer, OneOrMany::one(Embedding { document: id.to_string(), vec }))
+    }
+
+    fn store() -> Vec<(CustomerFeedback, OneOrMany<Embedding>)> {
+        vec![profile("ok", vec![0.5, 0.5, 0.0]), profile("zero", vec![0.0; 3])]
+    }
+
+    #[test]
+    fn drop_removes_zero_vectors() {
+        let kept = apply_zero_norm_policy(store(), ZeroNormPolicy::Drop);
+        let ids: Vec<&str> = kept.iter().map(|(c, _)| c.customer_id.as_str()).collect();
+        assert_eq!(ids, ["ok"]);
+    }
+
+    #[test]
+    fn warn_keeps_zero_vectors() {
+        let kept = apply_zero_norm_policy(store(), ZeroNormPolicy::Warn);
+        assert_eq!(kept.len(), 2);
+        assert!(is_degenerate(&kept[1].1.first().vec));
+    }
+
+    #[test]
+    fn epsilon_gives_finite_similarity() {
+        let query = Embedding { document: String::new(), vec: vec![1.0, 0.0, 0.0] };
+        let zero = &store()[1].1.first();
+        assert!(zero.cosine_similarity(&query, false).is_nan());
+
+        let kept = apply_zero_norm_policy(store(), ZeroNormPolicy::Epsilon);
+        assert_eq!(kept.len(), 2);
+        let repaired = kept[1].1.first();
+        assert!(!is_degenerate(&repaired.vec));
+        assert!(repaired.cosine_similarity(&query, false).is_finite());
+    }
+}
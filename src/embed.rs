@@ -0,0 +1,128 @@
+use crate::record::EmbeddableRecord;
+use crate::retry::{self, RetryPolicy};
+use clap::ValueEnum;
+use rig::{
+    embeddings::{Embedding, EmbeddingError, EmbeddingModel},
+    OneOrMany,
+};
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::time::sleep;
+
+// What to do with records an embedding request returned no vector for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum MissingEmbeddingPolicy {
+    /// Re-embed the missing records once, dropping any still missing
+    Retry,
+    /// Drop the missing records with a warning
+    Drop,
+}
+
+// How each chunk is sent to the embedding provider
+#[derive(Debug, Clone)]
+pub struct EmbedOptions {
+    // Documents per batch, independent of the processing chunk size
+    pub batch_size: usize,
+    pub missing: MissingEmbeddingPolicy,
+    pub retry: RetryPolicy,
+    // Batches embedded concurrently
+    pub max_in_flight: usize,
+    // Pause after each batch, to respect rate limits
+    pub delay: Duration,
+}
+
+// rig's OpenAI and Cohere clients reject a response with fewer vectors than
+// documents outright, as a ResponseError or DocumentError that doesn't say
+// which documents were left out
+fn is_count_mismatch(err: &EmbeddingError) -> bool {
+    match err {
+        EmbeddingError::ResponseError(message) => message.contains("does not match input length"),
+        EmbeddingError::DocumentError(err) => {
+            let message = err.to_string();
+            message.starts_with("Expected ") && message.contains(" embeddings, got ")
+        }
+        _ => false,
+    }
+}
+
+// Embed each record's summary and pair the vectors back up with records by the
+// text they were generated for, not by position, so a short response can't
+// shift vectors onto the wrong profiles. When the provider client rejects a
+// short response instead of returning it, the request is re-sent one record at
+// a time to find the records that get no vector. Returns the embedded records
+// and the ones that got no vector.
+pub async fn embed_records<M: EmbeddingModel, T: EmbeddableRecord>(
+    model: &M,
+    records: &[T],
+) -> Result<(Vec<(T, OneOrMany<Embedding>)>, Vec<T>), EmbeddingError> {
+    let mut embedded = Vec::with_capacity(records.len());
+    let mut missing = Vec::new();
+
+    for request in records.chunks(M::MAX_DOCUMENTS) {
+        let texts: Vec<String> = request.iter().map(|record| record.summary().to_string()).collect();
+        let vectors: HashMap<String, Embedding> = match model.embed_texts(texts).await {
+            Ok(vectors) => vectors.into_iter().map(|embedding| (embedding.document.clone(), embedding)).collect(),
+            // A single record is simply missing
+            Err(err) if request.len() == 1 && is_count_mismatch(&err) => HashMap::new(),
+            Err(err) if is_count_mismatch(&err) => {
+                tracing::warn!("Embedding response had fewer vectors than records ({}); embedding them one at a time", err);
+                let mut vectors = HashMap::new();
+                // Not `embed_text`, which panics on an empty response
+                for record in request {
+                    match model.embed_texts(vec![record.summary().to_string()]).await {
+                        Ok(embeddings) => {
+                            vectors.extend(embeddings.into_iter().map(|embedding| (embedding.document.clone(), embedding)));
+                        }
+                        Err(err) if is_count_mismatch(&err) => {}
+                        Err(err) => return Err(err),
+                    }
+                }
+                vectors
+            }
+            Err(err) => return Err(err),
+        };
+
+        for record in request {
+            match vectors.get(record.summary()) {
+                Some(embedding) => embedded.push((record.clone(), OneOrMany::one(embedding.clone()))),
+                None => missing.push(record.clone()),
+            }
+        }
+    }
+
+    Ok((embedded, missing))
+}
+
+// `embed_records` with a per-attempt timeout and backoff on transient failures
+// (rate limits, server errors, timeouts). Other errors are returned at once.
+pub async fn embed_with_retry<M: EmbeddingModel, T: EmbeddableRecord>(
+    model: &M,
+    records: &[T],
+    policy: &RetryPolicy,
+    chunk_num: usize,
+) -> Result<(Vec<(T, OneOrMany<Embedding>)>, Vec<T>), EmbeddingError> {
+    let mut attempt = 0;
+    loop {
+        let result = match policy.timeout {
+            Some(limit) => tokio::time::timeout(limit, embed_records(model, records)).await.unwrap_or_else(|_| {
+                Err(EmbeddingError::ProviderError(format!("request timed out after {:?}", limit)))
+            }),
+            None => embed_records(model, records).await,
+        };
+        match result {
+            Err(err) if attempt < policy.max_retries && retry::is_retryable_embedding_error(&err) => {
+                let delay = policy.delay(attempt);
+                tracing::warn!(
+                    "Embedding chunk {} attempt {} failed ({}), retrying in {:?}",
+                    chunk_num,
+                    attempt + 1,
+                    err,
+                    delay
+                );
+                sleep(delay).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
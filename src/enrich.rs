@@ -0,0 +1,164 @@
+use crate::export::CsvExportOptions;
+use crate::models::CustomerFeedback;
+use crate::prompt;
+use crate::redact::Redaction;
+use crate::retry::RetryingPrompt;
+use crate::search;
+use futures::{stream, StreamExt};
+use rig::{completion::CompletionModel, embeddings::EmbeddingModel, pipeline::Op, vector_store::in_memory_store::InMemoryVectorIndex};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::Path;
+
+pub const ENRICH_PREAMBLE: &str = r#"
+    You are an expert customer insights analyst writing account notes.
+    You will be given one customer's profile and the profiles of the customers
+    most similar to them. Answer the instruction for that customer in one or
+    two sentences of plain text, with no heading or list.
+"#;
+
+pub const DEFAULT_INSTRUCTION: &str =
+    "Recommend the single most useful next action for this customer and say briefly why.";
+
+// The record as loaded, plus the agent's answer
+#[derive(Serialize)]
+struct EnrichedRow<'a> {
+    #[serde(rename = "CustomerID")]
+    customer_id: &'a str,
+    #[serde(rename = "Age")]
+    age: i32,
+    #[serde(rename = "Gender")]
+    gender: &'a str,
+    #[serde(rename = "Country")]
+    country: &'a str,
+    #[serde(rename = "Income")]
+    income: Option<f64>,
+    #[serde(rename = "ProductQuality")]
+    product_quality: i32,
+    #[serde(rename = "ServiceQuality")]
+    service_quality: i32,
+    #[serde(rename = "PurchaseFrequency")]
+    purchase_frequency: i32,
+    #[serde(rename = "FeedbackScore")]
+    feedback_score: &'a str,
+    #[serde(rename = "LoyaltyLevel")]
+    loyalty_level: &'a str,
+    #[serde(rename = "SatisfactionScore")]
+    satisfaction_score: Option<f64>,
+    #[serde(rename = "Date")]
+    date: Option<&'a str>,
+    #[serde(rename = "Recommendation")]
+    recommendation: &'a str,
+}
+
+// Customer ids already written to a previous, interrupted run's output
+fn completed(path: &Path) -> Result<HashSet<String>, anyhow::Error> {
+    if !path.exists() {
+        return Ok(HashSet::new());
+    }
+    let mut reader = csv::Reader::from_path(path)?;
+    let column = reader
+        .headers()?
+        .iter()
+        .position(|header| header.trim_start_matches('\u{feff}') == "CustomerID")
+        .ok_or_else(|| anyhow::anyhow!("{} has no CustomerID column to resume from", path.display()))?;
+    let mut ids = HashSet::new();
+    for record in reader.records() {
+        if let Some(id) = record?.get(column) {
+            ids.insert(id.to_string());
+        }
+    }
+    Ok(ids)
+}
+
+// The customer itself followed by its nearest stored neighbors for context
+fn customer_prompt<E: EmbeddingModel>(
+    index: &InMemoryVectorIndex<E, CustomerFeedback>,
+    customer: &CustomerFeedback,
+    instruction: &str,
+    neighbors: usize,
+    redaction: &Redaction,
+) -> String {
+    let mut prompt = format!(
+        "Instruction: {}\n\nCustomer:\n{}",
+        instruction,
+        prompt::format_profile(1, 1.0, customer, redaction)
+    );
+    match search::similar_customers(index, &customer.customer_id, neighbors) {
+        Ok(similar) if !similar.is_empty() => {
+            prompt.push_str("\nMost similar customers:\n");
+            for (i, (score, profile)) in similar.iter().enumerate() {
+                prompt.push_str(&prompt::format_profile(i + 1, *score, profile, redaction));
+            }
+        }
+        Ok(_) => {}
+        Err(e) => tracing::warn!("No neighbors for {}: {}", customer.customer_id, e),
+    }
+    prompt
+}
+
+pub struct EnrichOptions<'a> {
+    pub instruction: &'a str,
+    pub neighbors: usize,
+    pub max_in_flight: usize,
+    pub redaction: &'a Redaction,
+    pub csv: &'a CsvExportOptions,
+}
+
+// Ask the agent about each customer and write the answers, `max_in_flight` at
+// a time. Rows are flushed as they complete; customers already in `output`
+// are skipped, so rerunning after an interruption picks up where it stopped.
+// Returns (written, skipped as already done, failed).
+pub async fn enrich<M: CompletionModel, E: EmbeddingModel>(
+    analyst: &RetryingPrompt<M>,
+    index: &InMemoryVectorIndex<E, CustomerFeedback>,
+    customers: &[&CustomerFeedback],
+    output: &Path,
+    options: &EnrichOptions<'_>,
+) -> Result<(usize, usize, usize), anyhow::Error> {
+    let done = completed(output)?;
+    let todo: Vec<&CustomerFeedback> =
+        customers.iter().copied().filter(|customer| !done.contains(&customer.customer_id)).collect();
+    let skipped = customers.len() - todo.len();
+    let mut writer = if done.is_empty() { options.csv.create(output)? } else { options.csv.append(output)? };
+
+    let mut answers = stream::iter(todo)
+        .map(|customer| async move {
+            let prompt = customer_prompt(index, customer, options.instruction, options.neighbors, options.redaction);
+            (customer, analyst.call(prompt).await)
+        })
+        .buffered(options.max_in_flight);
+
+    let (mut written, mut failed) = (0, 0);
+    while let Some((customer, answer)) = answers.next().await {
+        match answer {
+            Ok(answer) => {
+                writer.serialize(EnrichedRow {
+                    customer_id: &customer.customer_id,
+                    age: customer.age,
+                    gender: &customer.gender,
+                    country: &customer.country,
+                    income: customer.income,
+                    product_quality: customer.product_quality,
+                    service_quality: customer.service_quality,
+                    purchase_frequency: customer.purchase_frequency,
+                    feedback_score: &customer.feedback_score,
+                    loyalty_level: &customer.loyalty_level,
+                    satisfaction_score: customer.satisfaction_score,
+                    date: customer.date.as_deref(),
+                    recommendation: answer.trim(),
+                })?;
+                writer.flush()?;
+                written += 1;
+                if written % 50 == 0 {
+                    println!("Enriched {} customers", written);
+                }
+            }
+            Err(e) => {
+                failed += 1;
+                eprintln!("Error analyzing customer {}: {}", customer.customer_id, e);
+            }
+        }
+    }
+    Ok((written, skipped, failed))
+}
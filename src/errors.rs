@@ -0,0 +1,29 @@
+use rig::completion::PromptError;
+use rig::vector_store::VectorStoreError;
+
+// Render an error for logging. By default only the top-level message is shown;
+// in verbose mode the full cause chain (and a backtrace, when captured) is included.
+pub fn describe_error(err: impl Into<anyhow::Error>, verbose: bool) -> String {
+    let err = err.into();
+    if verbose {
+        format!("{:?}", err)
+    } else {
+        err.to_string()
+    }
+}
+
+// Why a query got no analysis. A lookup failure stops the query before a
+// prompt is sent, so only `Analysis` ever reached the model.
+#[derive(Debug, thiserror::Error)]
+pub enum PipelineError {
+    #[error("Failed to retrieve relevant customer profiles: {0}")]
+    Lookup(VectorStoreError),
+    #[error(transparent)]
+    Analysis(#[from] PromptError),
+}
+
+impl PipelineError {
+    pub fn reached_model(&self) -> bool {
+        matches!(self, PipelineError::Analysis(_))
+    }
+}
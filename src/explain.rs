@@ -0,0 +1,94 @@
+use crate::models::CustomerFeedback;
+use rig::embeddings::{distance::VectorDistance, EmbeddingModel};
+
+type DescribeGroup = fn(&CustomerFeedback) -> String;
+
+// Field groups embedded separately to approximate which part of a profile
+// drove its similarity to the query
+const FIELD_GROUPS: &[(&str, DescribeGroup)] = &[
+    ("demographics", |c| {
+        format!("Customer Profile: {} year old {} from {}", c.age, c.gender, c.country)
+    }),
+    ("income", |c| format!("Customer with income {}", c.income_display())),
+    ("quality ratings", |c| {
+        format!(
+            "Product Quality Rating: {}/10, Service Quality: {}/10",
+            c.product_quality, c.service_quality
+        )
+    }),
+    ("behavioral metrics", |c| {
+        format!(
+            "Purchases {} times per year. Loyalty Level: {}",
+            c.purchase_frequency, c.loyalty_level
+        )
+    }),
+    ("sentiment", |c| {
+        format!(
+            "Feedback Score: {}. Satisfaction Score: {}",
+            c.feedback_score,
+            c.satisfaction_display()
+        )
+    }),
+];
+
+pub struct GroupContribution {
+    pub group: &'static str,
+    pub similarity: f64,
+    // Share of the profile's total (non-negative) group similarity
+    pub share: f64,
+}
+
+// Embed the query and each profile's field groups and report per-group cosine
+// similarity to the query. Field groups are embedded as documents, so they get
+// the same prefix as stored profiles.
+pub async fn explain_matches<M: EmbeddingModel>(
+    query_model: &M,
+    document_model: &M,
+    query: &str,
+    profiles: &[&CustomerFeedback],
+) -> Result<Vec<Vec<GroupContribution>>, anyhow::Error> {
+    let texts: Vec<String> = profiles
+        .iter()
+        .flat_map(|profile| FIELD_GROUPS.iter().map(|(_, describe)| describe(profile)))
+        .collect();
+
+    let query_embedding = query_model.embed_text(query).await?;
+    let group_embeddings = document_model.embed_texts(texts).await?;
+
+    Ok(group_embeddings
+        .chunks(FIELD_GROUPS.len())
+        .map(|groups| {
+            let similarities: Vec<f64> = groups
+                .iter()
+                .map(|embedding| embedding.cosine_similarity(&query_embedding, false))
+                .collect();
+            let total: f64 = similarities.iter().map(|s| s.max(0.0)).sum();
+            FIELD_GROUPS
+                .iter()
+                .zip(similarities)
+                .map(|((group, _), similarity)| GroupContribution {
+                    group,
+                    similarity,
+                    share: if total > 0.0 { similarity.max(0.0) / total } else { 0.0 },
+                })
+                .collect()
+        })
+        .collect())
+}
+
+// One-line description such as "matched mostly on behavioral metrics (31% ...)"
+pub fn describe_contributions(contributions: &[GroupContribution]) -> String {
+    let mut sorted: Vec<&GroupContribution> = contributions.iter().collect();
+    sorted.sort_by(|a, b| b.share.total_cmp(&a.share));
+
+    let breakdown = sorted
+        .iter()
+        .map(|c| format!("{} {:.0}% (sim {:.3})", c.group, c.share * 100.0, c.similarity))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    match sorted.first() {
+        Some(top) => format!("matched mostly on {} ({})", top.group, breakdown),
+        None => "no field groups to compare".to_string(),
+    }
+}
@@ -0,0 +1,110 @@
+use crate::models::AnalysisRequest;
+use crate::score_display::ScoreFormatter;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LineEnding {
+    Lf,
+    Crlf,
+}
+
+// Writer settings shared by every CSV the tool exports, so files open the same
+// way in Excel wherever they come from
+#[derive(Debug, Clone, Copy)]
+pub struct CsvExportOptions {
+    pub bom: bool,
+    pub line_ending: LineEnding,
+}
+
+impl CsvExportOptions {
+    pub fn create(&self, path: &Path) -> Result<csv::Writer<File>, anyhow::Error> {
+        self.create_delimited(path, b',')
+    }
+
+    // Same settings with another field separator, e.g. tab for TSV
+    pub fn create_delimited(&self, path: &Path, delimiter: u8) -> Result<csv::Writer<File>, anyhow::Error> {
+        let mut file = File::create(path)?;
+        if self.bom {
+            file.write_all("\u{feff}".as_bytes())?;
+        }
+        Ok(csv::WriterBuilder::new().delimiter(delimiter).terminator(self.terminator()).from_writer(file))
+    }
+
+    // Continue an existing export: no BOM or header row is written again
+    pub fn append(&self, path: &Path) -> Result<csv::Writer<File>, anyhow::Error> {
+        let file = std::fs::OpenOptions::new().append(true).open(path)?;
+        Ok(csv::WriterBuilder::new().terminator(self.terminator()).has_headers(false).from_writer(file))
+    }
+
+    fn terminator(&self) -> csv::Terminator {
+        match self.line_ending {
+            LineEnding::Lf => csv::Terminator::Any(b'\n'),
+            LineEnding::Crlf => csv::Terminator::CRLF,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ProfileRow<'a> {
+    #[serde(rename = "Query")]
+    query: &'a str,
+    #[serde(rename = "Rank")]
+    rank: usize,
+    #[serde(rename = "Score")]
+    score: f64,
+    #[serde(rename = "CustomerID")]
+    customer_id: &'a str,
+    #[serde(rename = "Age")]
+    age: i32,
+    #[serde(rename = "Gender")]
+    gender: &'a str,
+    #[serde(rename = "Country")]
+    country: &'a str,
+    #[serde(rename = "Income")]
+    income: Option<f64>,
+    #[serde(rename = "LoyaltyLevel")]
+    loyalty_level: &'a str,
+    #[serde(rename = "SatisfactionScore")]
+    satisfaction_score: Option<f64>,
+}
+
+// One row per retrieved profile per query
+pub struct ProfileExport {
+    writer: csv::Writer<File>,
+    scores: Option<ScoreFormatter>,
+}
+
+impl ProfileExport {
+    pub fn create(path: &Path, options: &CsvExportOptions) -> Result<Self, anyhow::Error> {
+        Ok(Self { writer: options.create(path)?, scores: None })
+    }
+
+    // Write scores as the formatter shows them instead of raw
+    pub fn with_score_display(mut self, scores: ScoreFormatter) -> Self {
+        self.scores = Some(scores);
+        self
+    }
+
+    pub fn record(&mut self, request: &AnalysisRequest) -> Result<(), anyhow::Error> {
+        for (i, (profile, doc)) in request.profiles.iter().zip(&request.documents).enumerate() {
+            self.writer.serialize(ProfileRow {
+                query: &request.query,
+                rank: i + 1,
+                score: self.scores.map_or(profile.score, |scores| scores.value(profile.score)),
+                customer_id: &doc.customer_id,
+                age: doc.age,
+                gender: &doc.gender,
+                country: &doc.country,
+                income: doc.income,
+                loyalty_level: &doc.loyalty_level,
+                satisfaction_score: doc.satisfaction_score,
+            })?;
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,83 @@
+use crate::models::CustomerFeedback;
+use rig::{embeddings::Embedding, OneOrMany};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+#[derive(Deserialize)]
+struct VectorLine {
+    customer_id: String,
+    vector: Vec<f64>,
+}
+
+// Embeddings produced by another pipeline, one `{customer_id, vector}` JSON
+// object per line, used in place of calling the embedding API
+pub struct ExternalEmbeddings {
+    vectors: HashMap<String, Vec<f64>>,
+    used: HashSet<String>,
+    dims: usize,
+}
+
+impl ExternalEmbeddings {
+    // All vectors must have `expected_dims` components so they are comparable
+    // with query embeddings
+    pub fn load(path: &Path, expected_dims: usize) -> Result<Self, anyhow::Error> {
+        let reader = BufReader::new(std::fs::File::open(path)?);
+        let mut vectors = HashMap::new();
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record: VectorLine = serde_json::from_str(&line)
+                .map_err(|e| anyhow::anyhow!("{} line {}: {}", path.display(), i + 1, e))?;
+            if record.vector.len() != expected_dims {
+                return Err(anyhow::anyhow!(
+                    "{} line {}: vector for {} has {} dimensions, expected {} to match the query embedding model",
+                    path.display(),
+                    i + 1,
+                    record.customer_id,
+                    record.vector.len(),
+                    expected_dims
+                ));
+            }
+            if vectors.insert(record.customer_id.clone(), record.vector).is_some() {
+                return Err(anyhow::anyhow!("{} line {}: duplicate vector for {}", path.display(), i + 1, record.customer_id));
+            }
+        }
+        Ok(Self { vectors, used: HashSet::new(), dims: expected_dims })
+    }
+
+    pub fn len(&self) -> usize {
+        self.vectors.len()
+    }
+
+    pub fn dims(&self) -> usize {
+        self.dims
+    }
+
+    // Pair every customer in the chunk with its vector; a customer without one
+    // is an error rather than a silently missing profile
+    pub fn attach(
+        &mut self,
+        chunk: Vec<CustomerFeedback>,
+    ) -> Result<Vec<(CustomerFeedback, OneOrMany<Embedding>)>, anyhow::Error> {
+        chunk
+            .into_iter()
+            .map(|customer| {
+                let vec = self.vectors.get(&customer.customer_id).cloned().ok_or_else(|| {
+                    anyhow::anyhow!("No vector for customer {} in the embeddings file", customer.customer_id)
+                })?;
+                self.used.insert(customer.customer_id.clone());
+                let embedding = Embedding { document: customer.profile_summary.clone(), vec };
+                Ok((customer, OneOrMany::one(embedding)))
+            })
+            .collect()
+    }
+
+    // Vectors whose customer id was never seen in the data
+    pub fn unused(&self) -> usize {
+        self.vectors.len() - self.used.len()
+    }
+}
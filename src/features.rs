@@ -0,0 +1,150 @@
+use crate::models::CustomerFeedback;
+use clap::ValueEnum;
+use rig::{embeddings::Embedding, OneOrMany};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum NumericNormalization {
+    /// Scale each field to [0, 1] using the dataset's minimum and maximum
+    MinMax,
+    /// Center each field on the dataset mean, in standard deviations
+    ZScore,
+}
+
+// Numeric fields appended to the text embedding, in order
+const FEATURE_NAMES: &[&str] = &[
+    "age",
+    "income",
+    "satisfaction_score",
+    "product_quality",
+    "service_quality",
+    "purchase_frequency",
+];
+
+fn raw_features(customer: &CustomerFeedback) -> [Option<f64>; 6] {
+    [
+        Some(customer.age as f64),
+        customer.income,
+        customer.satisfaction_score,
+        Some(customer.product_quality as f64),
+        Some(customer.service_quality as f64),
+        Some(customer.purchase_frequency as f64),
+    ]
+}
+
+// Per-field statistics fitted on the dataset, used to normalize every profile
+// the same way
+pub struct FeatureScaler {
+    normalization: NumericNormalization,
+    // (min, max) or (mean, std dev) per field, depending on `normalization`
+    stats: Vec<(f64, f64)>,
+    weight: f64,
+}
+
+impl FeatureScaler {
+    pub fn fit(customers: &[&CustomerFeedback], normalization: NumericNormalization, weight: f64) -> Self {
+        let stats = (0..FEATURE_NAMES.len())
+            .map(|i| {
+                let values: Vec<f64> = customers.iter().filter_map(|c| raw_features(c)[i]).collect();
+                if values.is_empty() {
+                    return (0.0, 1.0);
+                }
+                match normalization {
+                    NumericNormalization::MinMax => (
+                        values.iter().copied().fold(f64::INFINITY, f64::min),
+                        values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+                    ),
+                    NumericNormalization::ZScore => {
+                        let mean = values.iter().sum::<f64>() / values.len() as f64;
+                        let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / values.len() as f64;
+                        (mean, variance.sqrt())
+                    }
+                }
+            })
+            .collect();
+        Self { normalization, stats, weight }
+    }
+
+    pub fn dims(&self) -> usize {
+        FEATURE_NAMES.len()
+    }
+
+    // Normalized, weighted numeric fields. Missing values map to the neutral
+    // point (mid-range or mean), as do fields that don't vary in the dataset.
+    pub fn numeric_features(&self, customer: &CustomerFeedback) -> Vec<f64> {
+        raw_features(customer)
+            .iter()
+            .zip(&self.stats)
+            .map(|(value, &(a, b))| {
+                let normalized = match (self.normalization, value) {
+                    (NumericNormalization::MinMax, Some(v)) if b > a => (v - a) / (b - a),
+                    (NumericNormalization::MinMax, _) => 0.5,
+                    (NumericNormalization::ZScore, Some(v)) if b > 0.0 => (v - a) / b,
+                    (NumericNormalization::ZScore, _) => 0.0,
+                };
+                normalized * self.weight
+            })
+            .collect()
+    }
+
+    // Append each profile's numeric features to all of its embeddings
+    pub fn extend(
+        &self,
+        embeddings: Vec<(CustomerFeedback, OneOrMany<Embedding>)>,
+    ) -> Vec<(CustomerFeedback, OneOrMany<Embedding>)> {
+        embeddings
+            .into_iter()
+            .map(|(customer, vectors)| {
+                let features = self.numeric_features(&customer);
+                let extended: Vec<Embedding> = vectors
+                    .into_iter()
+                    .map(|mut embedding| {
+                        embedding.vec.extend_from_slice(&features);
+                        embedding
+                    })
+                    .collect();
+                let vectors = OneOrMany::many(extended).expect("profiles have at least one embedding");
+                (customer, vectors)
+            })
+            .collect()
+    }
+}
+
+// Cosine similarity between a query and a stored vector. Queries have no
+// numeric fields, so only the stored vector's leading text components, as many
+// as the query has, are compared; the appended features would otherwise only
+// shrink each score by an amount that depends on the profile. A zero-length
+// vector (kept by `--zero-norm warn`) scores 0 rather than NaN, which would
+// otherwise sort ahead of every real match.
+pub fn query_similarity(stored: &Embedding, query: &Embedding) -> f64 {
+    let text = &stored.vec[..query.vec.len().min(stored.vec.len())];
+    let dot: f64 = text.iter().zip(&query.vec).map(|(a, b)| a * b).sum();
+    let norms = text.iter().map(|a| a * a).sum::<f64>().sqrt() * query.vec.iter().map(|b| b * b).sum::<f64>().sqrt();
+    if norms == 0.0 {
+        return 0.0;
+    }
+    dot / norms
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn embedding(vec: Vec<f64>) -> Embedding {
+        Embedding { document: String::new(), vec }
+    }
+
+    #[test]
+    fn queries_ignore_appended_features() {
+        let query = embedding(vec![0.6, 0.8]);
+        let plain = embedding(vec![0.6, 0.8]);
+        let extended = embedding(vec![0.6, 0.8, 0.3, -0.7, 0.1]);
+        assert!((query_similarity(&plain, &query) - 1.0).abs() < 1e-12);
+        assert!((query_similarity(&extended, &query) - 1.0).abs() < 1e-12);
+
+        let orthogonal = embedding(vec![0.8, -0.6, 0.5]);
+        assert!(query_similarity(&orthogonal, &query).abs() < 1e-12);
+
+        let zero_text = embedding(vec![0.0, 0.0, 0.4]);
+        assert_eq!(query_similarity(&zero_text, &query), 0.0);
+    }
+}
@@ -0,0 +1,121 @@
+use crate::models::CustomerFeedback;
+use clap::ValueEnum;
+use std::collections::BTreeSet;
+use std::str::FromStr;
+
+// Structured fields a query can name directly
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum MatchField {
+    Gender,
+    Country,
+    Loyalty,
+    Feedback,
+}
+
+impl MatchField {
+    fn value<'a>(&self, customer: &'a CustomerFeedback) -> &'a str {
+        match self {
+            MatchField::Gender => &customer.gender,
+            MatchField::Country => &customer.country,
+            MatchField::Loyalty => &customer.loyalty_level,
+            MatchField::Feedback => &customer.feedback_score,
+        }
+    }
+}
+
+// Score added to a profile whose `field` matches a value named in the query;
+// negative weights penalize matches instead
+#[derive(Debug, Clone, Copy)]
+pub struct MatchBonus {
+    pub field: MatchField,
+    pub weight: f64,
+}
+
+impl FromStr for MatchBonus {
+    type Err = String;
+
+    // Parses `field=weight`, e.g. `country=0.05`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (field, weight) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected FIELD=WEIGHT, got '{}'", s))?;
+        Ok(Self {
+            field: MatchField::from_str(field.trim(), true)?,
+            weight: weight
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid weight '{}' for {}", weight.trim(), field.trim()))?,
+        })
+    }
+}
+
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+fn contains_phrase(haystack: &[String], phrase: &[String]) -> bool {
+    !phrase.is_empty() && haystack.windows(phrase.len()).any(|window| window == phrase)
+}
+
+// A lowercased field value and its words, for phrase matching against queries
+type VocabularyEntry = (String, Vec<String>);
+
+// Finds dataset values named in a query by a whole-word keyword scan. The
+// vocabulary is the set of distinct values each field takes in the store.
+pub struct FieldMatcher {
+    fields: Vec<(MatchBonus, Vec<VocabularyEntry>)>,
+}
+
+impl FieldMatcher {
+    pub fn new(bonuses: &[MatchBonus], customers: &[&CustomerFeedback]) -> Self {
+        let fields = bonuses
+            .iter()
+            .map(|bonus| {
+                let values: BTreeSet<String> = customers
+                    .iter()
+                    .map(|customer| bonus.field.value(customer).to_lowercase())
+                    .collect();
+                let vocabulary = values.into_iter().map(|value| {
+                    let value_words = words(&value);
+                    (value, value_words)
+                });
+                (*bonus, vocabulary.collect())
+            })
+            .collect();
+        Self { fields }
+    }
+
+    pub fn hints(&self, query: &str) -> QueryHints {
+        let query_words = words(query);
+        QueryHints(
+            self.fields
+                .iter()
+                .map(|(bonus, vocabulary)| {
+                    let mentioned = vocabulary
+                        .iter()
+                        .filter(|(_, value_words)| contains_phrase(&query_words, value_words))
+                        .map(|(value, _)| value.clone())
+                        .collect();
+                    (*bonus, mentioned)
+                })
+                .filter(|(_, mentioned): &(MatchBonus, Vec<String>)| !mentioned.is_empty())
+                .collect(),
+        )
+    }
+}
+
+// Field values a particular query mentions, with the bonus for matching each field
+pub struct QueryHints(Vec<(MatchBonus, Vec<String>)>);
+
+impl QueryHints {
+    pub fn bonus(&self, customer: &CustomerFeedback) -> f64 {
+        self.0
+            .iter()
+            .filter(|(bonus, mentioned)| mentioned.contains(&bonus.field.value(customer).to_lowercase()))
+            .map(|(bonus, _)| bonus.weight)
+            .sum()
+    }
+}
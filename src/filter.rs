@@ -0,0 +1,472 @@
+use crate::loyalty::LoyaltyOrder;
+use crate::models::CustomerFeedback;
+use chrono::NaiveDate;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    CustomerId,
+    Age,
+    Gender,
+    Country,
+    Income,
+    ProductQuality,
+    ServiceQuality,
+    PurchaseFrequency,
+    FeedbackScore,
+    LoyaltyLevel,
+    SatisfactionScore,
+    Date,
+}
+
+const FIELDS: &[(&str, Field)] = &[
+    ("customer_id", Field::CustomerId),
+    ("age", Field::Age),
+    ("gender", Field::Gender),
+    ("country", Field::Country),
+    ("income", Field::Income),
+    ("product_quality", Field::ProductQuality),
+    ("service_quality", Field::ServiceQuality),
+    ("purchase_frequency", Field::PurchaseFrequency),
+    ("feedback_score", Field::FeedbackScore),
+    ("loyalty_level", Field::LoyaltyLevel),
+    ("satisfaction_score", Field::SatisfactionScore),
+    ("date", Field::Date),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    fn symbol(self) -> &'static str {
+        match self {
+            Comparison::Eq => "=",
+            Comparison::Ne => "!=",
+            Comparison::Lt => "<",
+            Comparison::Le => "<=",
+            Comparison::Gt => ">",
+            Comparison::Ge => ">=",
+        }
+    }
+
+    fn is_equality(self) -> bool {
+        matches!(self, Comparison::Eq | Comparison::Ne)
+    }
+
+    fn holds<T: PartialOrd>(self, left: T, right: T) -> bool {
+        match self {
+            Comparison::Eq => left == right,
+            Comparison::Ne => left != right,
+            Comparison::Lt => left < right,
+            Comparison::Le => left <= right,
+            Comparison::Gt => left > right,
+            Comparison::Ge => left >= right,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Number(f64),
+    Text(String),
+    Date(NaiveDate),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Compare(Field, Comparison, Value),
+}
+
+// Boolean combination of field comparisons, e.g.
+// `(country = US OR country = Canada) AND satisfaction_score < 50`.
+// loyalty_level also takes <, >= etc., ranked by the loyalty order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Filter {
+    expr: Expr,
+    loyalty: LoyaltyOrder,
+}
+
+impl Filter {
+    // Comparisons against a missing value (blank income, no date) or a loyalty
+    // level outside the order are false
+    pub fn matches(&self, customer: &CustomerFeedback) -> bool {
+        self.expr.matches(customer, &self.loyalty)
+    }
+
+    // Rank loyalty levels by `loyalty` instead of the default order. Fails when
+    // an ordering comparison names a level the order doesn't have.
+    pub fn with_loyalty_order(mut self, loyalty: &LoyaltyOrder) -> Result<Self, anyhow::Error> {
+        self.expr.check_loyalty(loyalty)?;
+        self.loyalty = loyalty.clone();
+        Ok(self)
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.expr.fmt(f)
+    }
+}
+
+impl Expr {
+    fn matches(&self, customer: &CustomerFeedback, loyalty: &LoyaltyOrder) -> bool {
+        match self {
+            Expr::And(left, right) => left.matches(customer, loyalty) && right.matches(customer, loyalty),
+            Expr::Or(left, right) => left.matches(customer, loyalty) || right.matches(customer, loyalty),
+            Expr::Not(inner) => !inner.matches(customer, loyalty),
+            Expr::Compare(field, op, Value::Number(value)) => {
+                let actual = match field {
+                    Field::Age => Some(customer.age as f64),
+                    Field::Income => customer.income,
+                    Field::ProductQuality => Some(customer.product_quality as f64),
+                    Field::ServiceQuality => Some(customer.service_quality as f64),
+                    Field::PurchaseFrequency => Some(customer.purchase_frequency as f64),
+                    Field::SatisfactionScore => customer.satisfaction_score,
+                    _ => None,
+                };
+                actual.is_some_and(|actual| op.holds(actual, *value))
+            }
+            Expr::Compare(Field::LoyaltyLevel, op, Value::Text(value)) if !op.is_equality() => {
+                match (loyalty.loyalty_rank(&customer.loyalty_level), loyalty.loyalty_rank(value)) {
+                    (Some(actual), Some(value)) => op.holds(actual, value),
+                    _ => false,
+                }
+            }
+            Expr::Compare(field, op, Value::Text(value)) => {
+                let actual = match field {
+                    Field::CustomerId => &customer.customer_id,
+                    Field::Gender => &customer.gender,
+                    Field::Country => &customer.country,
+                    Field::FeedbackScore => &customer.feedback_score,
+                    Field::LoyaltyLevel => &customer.loyalty_level,
+                    _ => return false,
+                };
+                op.holds(actual.to_lowercase(), value.to_lowercase())
+            }
+            Expr::Compare(_, op, Value::Date(value)) => {
+                customer.parsed_date().is_some_and(|actual| op.holds(actual, *value))
+            }
+        }
+    }
+
+    fn check_loyalty(&self, loyalty: &LoyaltyOrder) -> Result<(), anyhow::Error> {
+        match self {
+            Expr::And(left, right) | Expr::Or(left, right) => {
+                left.check_loyalty(loyalty)?;
+                right.check_loyalty(loyalty)
+            }
+            Expr::Not(inner) => inner.check_loyalty(loyalty),
+            Expr::Compare(Field::LoyaltyLevel, op, Value::Text(level))
+                if !op.is_equality() && loyalty.loyalty_rank(level).is_none() =>
+            {
+                Err(anyhow::anyhow!("--filter ranks loyalty_level against {:?}, which is not in the loyalty order", level))
+            }
+            Expr::Compare(..) => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::And(left, right) => write!(f, "({} AND {})", left, right),
+            Expr::Or(left, right) => write!(f, "({} OR {})", left, right),
+            Expr::Not(inner) => write!(f, "NOT {}", inner),
+            Expr::Compare(field, op, value) => {
+                let name = FIELDS.iter().find(|(_, f)| f == field).map(|(name, _)| *name).unwrap_or("?");
+                match value {
+                    Value::Number(n) => write!(f, "{} {} {}", name, op.symbol(), n),
+                    Value::Text(text) => write!(f, "{} {} {:?}", name, op.symbol(), text),
+                    Value::Date(date) => write!(f, "{} {} {}", name, op.symbol(), date),
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    Op(Comparison),
+    // Bare word or number; keywords are recognized by the parser
+    Word(String),
+    Quoted(String),
+}
+
+fn tokenize(input: &str) -> Result<Vec<(usize, Token)>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = input.char_indices().peekable();
+    while let Some(&(at, c)) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' | ')' => {
+                chars.next();
+                tokens.push((at, if c == '(' { Token::Open } else { Token::Close }));
+            }
+            '=' | '!' | '<' | '>' => {
+                chars.next();
+                let followed_by_eq = chars.next_if(|&(_, next)| next == '=').is_some();
+                let op = match (c, followed_by_eq) {
+                    ('=', _) => Comparison::Eq,
+                    ('!', true) => Comparison::Ne,
+                    ('<', false) => Comparison::Lt,
+                    ('<', true) => Comparison::Le,
+                    ('>', false) => Comparison::Gt,
+                    ('>', true) => Comparison::Ge,
+                    _ => return Err(format!("expected '!=' at position {}", at + 1)),
+                };
+                tokens.push((at, Token::Op(op)));
+            }
+            '"' | '\'' => {
+                chars.next();
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, close)) if close == c => break,
+                        Some((_, other)) => text.push(other),
+                        None => return Err(format!("unterminated string starting at position {}", at + 1)),
+                    }
+                }
+                tokens.push((at, Token::Quoted(text)));
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some((_, next)) = chars.next_if(|&(_, next)| !next.is_whitespace() && !"()=!<>\"'".contains(next)) {
+                    word.push(next);
+                }
+                tokens.push((at, Token::Word(word)));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    position: usize,
+    len: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|(_, token)| token)
+    }
+
+    // 1-based character position of the next token, for error messages
+    fn at(&self) -> usize {
+        self.tokens.get(self.position).map(|(at, _)| at + 1).unwrap_or(self.len + 1)
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        let found = matches!(self.peek(), Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword));
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut left = self.and()?;
+        while self.keyword("or") {
+            left = Expr::Or(Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut left = self.not()?;
+        while self.keyword("and") {
+            left = Expr::And(Box::new(left), Box::new(self.not()?));
+        }
+        Ok(left)
+    }
+
+    fn not(&mut self) -> Result<Expr, String> {
+        if self.keyword("not") {
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        if self.peek() == Some(&Token::Open) {
+            self.position += 1;
+            let inner = self.or()?;
+            if self.peek() != Some(&Token::Close) {
+                return Err(format!("expected ')' at position {}", self.at()));
+            }
+            self.position += 1;
+            return Ok(inner);
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Expr, String> {
+        let at = self.at();
+        let name = match self.tokens.get(self.position) {
+            Some((_, Token::Word(word))) => word.clone(),
+            Some(_) => return Err(format!("expected a field name at position {}", at)),
+            None => return Err("expected a field name at the end of the filter".to_string()),
+        };
+        let field = FIELDS
+            .iter()
+            .find(|(field, _)| field.eq_ignore_ascii_case(&name))
+            .map(|(_, field)| *field)
+            .ok_or_else(|| {
+                format!(
+                    "unknown field '{}' at position {} (expected one of: {})",
+                    name,
+                    at,
+                    FIELDS.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(", ")
+                )
+            })?;
+        self.position += 1;
+
+        let op = match self.peek() {
+            Some(Token::Op(op)) => *op,
+            _ => return Err(format!("expected a comparison (=, !=, <, <=, >, >=) after '{}' at position {}", name, self.at())),
+        };
+        self.position += 1;
+
+        let at = self.at();
+        let raw = match self.tokens.get(self.position) {
+            Some((_, Token::Word(word) | Token::Quoted(word))) => word.clone(),
+            _ => return Err(format!("expected a value after '{} {}' at position {}", name, op.symbol(), at)),
+        };
+        self.position += 1;
+
+        let value = match field {
+            Field::Age
+            | Field::Income
+            | Field::ProductQuality
+            | Field::ServiceQuality
+            | Field::PurchaseFrequency
+            | Field::SatisfactionScore => Value::Number(
+                raw.parse().map_err(|_| format!("{} is numeric, but '{}' at position {} is not a number", name, raw, at))?,
+            ),
+            Field::Date => Value::Date(
+                NaiveDate::parse_from_str(&raw, "%Y-%m-%d")
+                    .map_err(|_| format!("date needs a YYYY-MM-DD value, got '{}' at position {}", raw, at))?,
+            ),
+            Field::LoyaltyLevel => Value::Text(raw),
+            _ => {
+                if !op.is_equality() {
+                    return Err(format!("{} is a text field; only = and != apply, not '{}'", name, op.symbol()));
+                }
+                Value::Text(raw)
+            }
+        };
+        Ok(Expr::Compare(field, op, value))
+    }
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser { tokens: tokenize(s)?, position: 0, len: s.len() };
+        if parser.tokens.is_empty() {
+            return Err("the filter is empty".to_string());
+        }
+        let expr = parser.or()?;
+        if parser.position < parser.tokens.len() {
+            return Err(format!("unexpected input at position {}", parser.at()));
+        }
+        Ok(Filter { expr, loyalty: LoyaltyOrder::default() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn customer(country: &str, satisfaction: Option<f64>, loyalty: &str) -> CustomerFeedback {
+        CustomerFeedback {
+            customer_id: "c-1".to_string(),
+            age: 30,
+            gender: "Female".to_string(),
+            country: country.to_string(),
+            income: Some(50000.0),
+            product_quality: 5,
+            service_quality: 5,
+            purchase_frequency: 4,
+            feedback_score: "Low".to_string(),
+            loyalty_level: loyalty.to_string(),
+            satisfaction_score: satisfaction,
+            date: None,
+            profile_summary: String::new(),
+            currency: Default::default(),
+        }
+    }
+
+    fn parse(filter: &str) -> Filter {
+        filter.parse().unwrap()
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let grouped = parse("(country = US OR country = Canada) AND satisfaction_score < 50");
+        assert_eq!(grouped.to_string(), r#"((country = "US" OR country = "Canada") AND satisfaction_score < 50)"#);
+        assert!(grouped.matches(&customer("canada", Some(40.0), "Gold")));
+        assert!(!grouped.matches(&customer("US", Some(80.0), "Gold")));
+        assert!(!grouped.matches(&customer("UK", Some(40.0), "Gold")));
+
+        let ungrouped = parse("country = US or country = Canada and satisfaction_score < 50");
+        assert_eq!(ungrouped.to_string(), r#"(country = "US" OR (country = "Canada" AND satisfaction_score < 50))"#);
+        assert!(ungrouped.matches(&customer("US", Some(80.0), "Gold")));
+        assert!(!ungrouped.matches(&customer("Canada", Some(80.0), "Gold")));
+    }
+
+    #[test]
+    fn not_negates_the_next_term() {
+        let filter = parse("NOT country = UK AND loyalty_level >= Silver");
+        assert_eq!(filter.to_string(), r#"(NOT country = "UK" AND loyalty_level >= "Silver")"#);
+        assert!(filter.matches(&customer("US", None, "Gold")));
+        assert!(!filter.matches(&customer("UK", None, "Gold")));
+        assert!(!filter.matches(&customer("US", None, "Bronze")));
+
+        let filter = parse("not (satisfaction_score >= 50)");
+        assert!(filter.matches(&customer("US", Some(20.0), "Gold")));
+        // A missing value fails the comparison, so its negation holds
+        assert!(filter.matches(&customer("US", None, "Gold")));
+        assert!(!filter.matches(&customer("US", Some(50.0), "Gold")));
+    }
+
+    #[test]
+    fn unknown_fields_and_bad_operators_are_rejected() {
+        let err = "region = EU".parse::<Filter>().unwrap_err();
+        assert!(err.starts_with("unknown field 'region' at position 1 (expected one of: customer_id, age,"), "{}", err);
+        assert_eq!(
+            "country < US".parse::<Filter>().unwrap_err(),
+            "country is a text field; only = and != apply, not '<'"
+        );
+        assert_eq!("age ! 30".parse::<Filter>().unwrap_err(), "expected '!=' at position 5");
+        assert_eq!(
+            "age 30".parse::<Filter>().unwrap_err(),
+            "expected a comparison (=, !=, <, <=, >, >=) after 'age' at position 5"
+        );
+        assert_eq!(
+            "age > thirty".parse::<Filter>().unwrap_err(),
+            "age is numeric, but 'thirty' at position 7 is not a number"
+        );
+        assert_eq!("".parse::<Filter>().unwrap_err(), "the filter is empty");
+    }
+
+    #[test]
+    fn unbalanced_parentheses_are_rejected() {
+        assert_eq!("(age > 30".parse::<Filter>().unwrap_err(), "expected ')' at position 10");
+        assert_eq!("age > 30)".parse::<Filter>().unwrap_err(), "unexpected input at position 9");
+        assert_eq!("((age > 30)".parse::<Filter>().unwrap_err(), "expected ')' at position 12");
+        assert_eq!("()".parse::<Filter>().unwrap_err(), "expected a field name at position 2");
+    }
+}
@@ -0,0 +1,43 @@
+use crate::models::CustomerFeedback;
+
+// Levenshtein distance over characters
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+
+    for (i, ca) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    previous[b.len()]
+}
+
+// Customers whose id best matches a partial or mistyped id, closest first.
+// Matching is case-insensitive; ids containing the input as a substring rank
+// ahead of the rest, and ties are broken by edit distance and then by id.
+pub fn find_customers<'a>(
+    customers: &'a [CustomerFeedback],
+    partial: &str,
+    limit: usize,
+) -> Vec<(usize, &'a CustomerFeedback)> {
+    let partial = partial.to_lowercase();
+    let mut candidates: Vec<(bool, usize, &CustomerFeedback)> = customers
+        .iter()
+        .map(|customer| {
+            let id = customer.customer_id.to_lowercase();
+            (!id.contains(&partial), edit_distance(&partial, &id), customer)
+        })
+        .collect();
+
+    candidates.sort_by(|a, b| (a.0, a.1, &a.2.customer_id).cmp(&(b.0, b.1, &b.2.customer_id)));
+    candidates.dedup_by(|a, b| a.2.customer_id == b.2.customer_id);
+    candidates.truncate(limit);
+
+    candidates.into_iter().map(|(_, distance, customer)| (distance, customer)).collect()
+}
@@ -0,0 +1,161 @@
+use crate::export::CsvExportOptions;
+use crate::loyalty::LoyaltyOrder;
+use crate::models::CustomerFeedback;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum HealthFactor {
+    Satisfaction,
+    Loyalty,
+    PurchaseFrequency,
+    ProductQuality,
+    ServiceQuality,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum RankOrder {
+    /// Lowest health first, i.e. the customers most at risk of churning
+    AtRisk,
+    /// Highest health first
+    Healthiest,
+}
+
+#[derive(Debug, Clone)]
+pub struct HealthWeight {
+    pub factor: HealthFactor,
+    pub weight: f64,
+}
+
+impl FromStr for HealthWeight {
+    type Err = String;
+
+    // Parses `factor=weight`, e.g. `satisfaction=0.5`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (factor, weight) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected FACTOR=WEIGHT, got '{}'", s))?;
+        let weight: f64 = weight
+            .trim()
+            .parse()
+            .map_err(|_| format!("invalid weight '{}' for {}", weight.trim(), factor.trim()))?;
+        if weight < 0.0 {
+            return Err(format!("weight for {} must not be negative", factor.trim()));
+        }
+        Ok(Self { factor: HealthFactor::from_str(factor.trim(), true)?, weight })
+    }
+}
+
+// Used for any factor not given explicitly
+pub fn default_weight(factor: HealthFactor) -> f64 {
+    match factor {
+        HealthFactor::Satisfaction => 0.35,
+        HealthFactor::Loyalty => 0.2,
+        HealthFactor::PurchaseFrequency => 0.15,
+        HealthFactor::ProductQuality => 0.15,
+        HealthFactor::ServiceQuality => 0.15,
+    }
+}
+
+// Composite health in [0, 1]: each factor is scaled to [0, 1] (purchase
+// frequency relative to the most frequent buyer, loyalty by its place in the
+// loyalty order) and the weighted mean taken over the factors the customer has
+// values for
+pub fn health_scores<'a>(
+    customers: &'a [CustomerFeedback],
+    weights: &[HealthWeight],
+    loyalty: &LoyaltyOrder,
+) -> Vec<(f64, &'a CustomerFeedback)> {
+    let weight = |factor| {
+        weights
+            .iter()
+            .rev()
+            .find(|w| w.factor == factor)
+            .map(|w| w.weight)
+            .unwrap_or_else(|| default_weight(factor))
+    };
+    let factors = [
+        HealthFactor::Satisfaction,
+        HealthFactor::Loyalty,
+        HealthFactor::PurchaseFrequency,
+        HealthFactor::ProductQuality,
+        HealthFactor::ServiceQuality,
+    ]
+    .map(|factor| (factor, weight(factor)));
+    let max_frequency = customers.iter().map(|c| c.purchase_frequency).max().unwrap_or(0).max(1) as f64;
+
+    customers
+        .iter()
+        .map(|customer| {
+            let (total, weights) = factors
+                .iter()
+                .filter_map(|(factor, weight)| {
+                    let value = match factor {
+                        HealthFactor::Satisfaction => customer.satisfaction_score.map(|s| s / 100.0),
+                        HealthFactor::Loyalty => loyalty.fraction(&customer.loyalty_level),
+                        HealthFactor::PurchaseFrequency => Some(customer.purchase_frequency as f64 / max_frequency),
+                        HealthFactor::ProductQuality => Some(customer.product_quality as f64 / 10.0),
+                        HealthFactor::ServiceQuality => Some(customer.service_quality as f64 / 10.0),
+                    }?;
+                    Some((value.clamp(0.0, 1.0) * weight, *weight))
+                })
+                .fold((0.0, 0.0), |(total, weights), (value, weight)| (total + value, weights + weight));
+            let score = if weights > 0.0 { total / weights } else { 0.0 };
+            (score, customer)
+        })
+        .collect()
+}
+
+#[derive(Serialize)]
+struct RankingRow<'a> {
+    #[serde(rename = "Rank")]
+    rank: usize,
+    #[serde(rename = "CustomerID")]
+    customer_id: &'a str,
+    #[serde(rename = "HealthScore")]
+    health_score: f64,
+    #[serde(rename = "SatisfactionScore")]
+    satisfaction_score: Option<f64>,
+    #[serde(rename = "LoyaltyLevel")]
+    loyalty_level: &'a str,
+    #[serde(rename = "PurchaseFrequency")]
+    purchase_frequency: i32,
+    #[serde(rename = "ProductQuality")]
+    product_quality: i32,
+    #[serde(rename = "ServiceQuality")]
+    service_quality: i32,
+}
+
+// Sort by health in the requested order and write one row per customer. Equal
+// scores are ordered by loyalty in the same direction.
+pub fn write_ranking(
+    path: &Path,
+    mut scored: Vec<(f64, &CustomerFeedback)>,
+    order: RankOrder,
+    loyalty: &LoyaltyOrder,
+    options: &CsvExportOptions,
+) -> Result<(), anyhow::Error> {
+    let descending = order == RankOrder::Healthiest;
+    scored.sort_by(|a, b| {
+        let health = if descending { b.0.total_cmp(&a.0) } else { a.0.total_cmp(&b.0) };
+        health.then_with(|| loyalty.compare(&a.1.loyalty_level, &b.1.loyalty_level, descending))
+    });
+
+    let mut writer = options.create(path)?;
+    for (i, (score, customer)) in scored.into_iter().enumerate() {
+        writer.serialize(RankingRow {
+            rank: i + 1,
+            customer_id: &customer.customer_id,
+            health_score: (score * 10000.0).round() / 10000.0,
+            satisfaction_score: customer.satisfaction_score,
+            loyalty_level: &customer.loyalty_level,
+            purchase_frequency: customer.purchase_frequency,
+            product_quality: customer.product_quality,
+            service_quality: customer.service_quality,
+        })?;
+    }
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,648 @@
+use crate::cache::sha256_hex;
+use crate::currency::CurrencyConfig;
+use crate::missing::{self, MissingValuePolicy};
+use crate::models::CustomerFeedback;
+use crate::normalize::{NormalizationMap, Normalizer};
+use crate::progress;
+use crate::remote;
+use crate::schema::ColumnMapping;
+use crate::sqlite;
+use chrono::NaiveDate;
+use rig::loaders::FileLoader;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+// How raw CSV rows become customer records
+pub struct LoadOptions {
+    pub column_mapping: ColumnMapping,
+    pub missing_satisfaction: MissingValuePolicy,
+    pub missing_income: MissingValuePolicy,
+    pub normalization: Option<NormalizationMap>,
+    pub date_range: Option<DateRange>,
+    pub currency: CurrencyConfig,
+    pub metadata: MetadataFilter,
+}
+
+// Exact-attribute filters from --country, --min-income and --loyalty-level,
+// applied before embedding so only matching records reach the store. Text
+// fields match case-insensitively; a record without an income fails
+// --min-income.
+#[derive(Debug, Clone, Default)]
+pub struct MetadataFilter {
+    pub countries: Vec<String>,
+    pub min_income: Option<f64>,
+    pub loyalty_levels: Vec<String>,
+}
+
+impl MetadataFilter {
+    pub fn is_empty(&self) -> bool {
+        self.countries.is_empty() && self.min_income.is_none() && self.loyalty_levels.is_empty()
+    }
+
+    pub fn matches(&self, customer: &CustomerFeedback) -> bool {
+        let listed = |values: &[String], value: &str| {
+            values.is_empty() || values.iter().any(|v| v.trim().eq_ignore_ascii_case(value.trim()))
+        };
+        listed(&self.countries, &customer.country)
+            && listed(&self.loyalty_levels, &customer.loyalty_level)
+            && self.min_income.is_none_or(|min| customer.income.is_some_and(|income| income >= min))
+    }
+
+    // The flags in effect, for messages
+    pub fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if !self.countries.is_empty() {
+            parts.push(format!("country {}", self.countries.join(" or ")));
+        }
+        if let Some(min) = self.min_income {
+            parts.push(format!("income >= {}", min));
+        }
+        if !self.loyalty_levels.is_empty() {
+            parts.push(format!("loyalty level {}", self.loyalty_levels.join(" or ")));
+        }
+        parts.join(", ")
+    }
+}
+
+// Inclusive window on the Date column from --since/--until. Rows without a
+// parseable date fall outside any window.
+#[derive(Debug, Clone, Copy)]
+pub struct DateRange {
+    pub since: Option<NaiveDate>,
+    pub until: Option<NaiveDate>,
+}
+
+impl DateRange {
+    pub fn contains(&self, customer: &CustomerFeedback) -> bool {
+        match customer.parsed_date() {
+            Some(date) => {
+                self.since.is_none_or(|since| date >= since) && self.until.is_none_or(|until| date <= until)
+            }
+            None => false,
+        }
+    }
+}
+
+// A date filter is meaningless without a Date column, so refuse rather than
+// silently dropping every row
+fn check_date_column<R: Read>(rdr: &mut csv::Reader<R>, options: &LoadOptions) -> Result<(), anyhow::Error> {
+    if options.date_range.is_some() && !rdr.headers()?.iter().any(|header| header == "Date") {
+        return Err(anyhow::anyhow!("--since/--until need a Date column, but the data file has none"));
+    }
+    Ok(())
+}
+
+fn in_range(customer: &CustomerFeedback, options: &LoadOptions) -> bool {
+    options.date_range.is_none_or(|range| range.contains(customer))
+}
+
+// What the per-record load steps decided for one parsed row
+enum Admission {
+    OutOfRange,
+    MissingValues,
+    Unmatched,
+    Admitted,
+}
+
+// The per-record steps of `load_customers`, in its order: date range, missing
+// values, normalization, currency scaling and the metadata filter. Used where
+// records are looked at one at a time.
+fn admit(customer: &mut CustomerFeedback, options: &LoadOptions, normalizer: Option<&mut Normalizer>) -> Admission {
+    if !in_range(customer, options) {
+        return Admission::OutOfRange;
+    }
+    if !missing::admits(customer, options.missing_satisfaction, options.missing_income) {
+        return Admission::MissingValues;
+    }
+    if let Some(normalizer) = normalizer {
+        normalizer.apply(customer);
+    }
+    options.currency.apply(customer);
+    if !options.metadata.matches(customer) {
+        return Admission::Unmatched;
+    }
+    Admission::Admitted
+}
+
+// Fail early with a hint when the data file is missing: either the whole
+// directory is absent (fresh clone) or the file name doesn't match what's there
+pub fn check_data_path(data_path: &str) -> Result<(), anyhow::Error> {
+    let path = Path::new(data_path);
+    if path.exists() {
+        return Ok(());
+    }
+
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    if !dir.is_dir() {
+        return Err(anyhow::anyhow!(
+            "Data directory {} does not exist. Create it (mkdir -p {}) and place {} inside.",
+            dir.display(),
+            dir.display(),
+            path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default()
+        ));
+    }
+
+    let mut csvs: Vec<String> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|p| p.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("csv")))
+        .filter_map(|p| p.file_name().map(|name| name.to_string_lossy().into_owned()))
+        .collect();
+    csvs.sort();
+
+    if csvs.is_empty() {
+        Err(anyhow::anyhow!("Data file not found: {} ({} contains no CSV files)", data_path, dir.display()))
+    } else {
+        Err(anyhow::anyhow!(
+            "Data file not found: {}. CSV files in {}: {}",
+            data_path,
+            dir.display(),
+            csvs.join(", ")
+        ))
+    }
+}
+
+// Where the CSV comes from: a local file, an http(s) URL downloaded up front,
+// or the result of a SQLite query, also read up front
+pub enum DataSource {
+    Local(String),
+    Remote { url: String, content: String },
+    Sqlite { path: String, content: String },
+}
+
+impl DataSource {
+    pub async fn open(location: &str) -> Result<Self, anyhow::Error> {
+        if remote::is_url(location) {
+            let content = remote::download(location).await?;
+            Ok(DataSource::Remote { url: location.to_string(), content })
+        } else {
+            check_data_path(location)?;
+            Ok(DataSource::Local(location.to_string()))
+        }
+    }
+
+    pub fn sqlite(path: &str, query: &str) -> Result<Self, anyhow::Error> {
+        let content = sqlite::query_csv(path, query)?;
+        Ok(DataSource::Sqlite { path: path.to_string(), content })
+    }
+
+    pub fn location(&self) -> &str {
+        match self {
+            DataSource::Local(path) => path,
+            DataSource::Remote { url, .. } => url,
+            DataSource::Sqlite { path, .. } => path,
+        }
+    }
+
+    // Hash of the raw input, so a run can be tied to the exact data it read.
+    // Local files are hashed as they are read rather than loaded whole.
+    pub fn content_sha256(&self) -> Result<String, anyhow::Error> {
+        match self {
+            DataSource::Local(path) => {
+                let mut hasher = Sha256::new();
+                std::io::copy(&mut File::open(path)?, &mut hasher)?;
+                Ok(hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect())
+            }
+            DataSource::Remote { content, .. } | DataSource::Sqlite { content, .. } => {
+                Ok(sha256_hex(content.as_bytes()))
+            }
+        }
+    }
+
+    fn csv_reader(&self) -> Result<csv::Reader<Box<dyn Read + '_>>, anyhow::Error> {
+        let reader: Box<dyn Read> = match self {
+            DataSource::Local(path) => Box::new(File::open(path)?),
+            DataSource::Remote { content, .. } | DataSource::Sqlite { content, .. } => Box::new(content.as_bytes()),
+        };
+        Ok(csv::Reader::from_reader(reader))
+    }
+}
+
+// Customer ids drive equality, similarity lookups and history collapsing, so
+// none may be empty. Repeated ids are expected when collapsing histories and
+// reported otherwise.
+pub fn validate_ids(customers: &[CustomerFeedback], expect_repeats: bool) -> Result<(), anyhow::Error> {
+    let empty: Vec<usize> = customers
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| c.customer_id.trim().is_empty())
+        .map(|(i, _)| i + 1)
+        .collect();
+    if !empty.is_empty() {
+        return Err(anyhow::anyhow!(
+            "{} loaded records have an empty customer id (records {}{})",
+            empty.len(),
+            empty.iter().take(10).map(|i| i.to_string()).collect::<Vec<_>>().join(", "),
+            if empty.len() > 10 { ", ..." } else { "" }
+        ));
+    }
+
+    if !expect_repeats {
+        let mut seen = std::collections::HashSet::new();
+        let repeated: std::collections::BTreeSet<&str> = customers
+            .iter()
+            .map(|c| c.customer_id.as_str())
+            .filter(|id| !seen.insert(*id))
+            .collect();
+        if !repeated.is_empty() {
+            tracing::warn!(
+                "{} customer ids appear more than once (e.g. {}); lookups by id use the first match. \
+                 Use --trend-summaries to collapse repeated ids into one profile.",
+                repeated.len(),
+                repeated.iter().take(5).copied().collect::<Vec<_>>().join(", ")
+            );
+        }
+    }
+    Ok(())
+}
+
+// Rows that fail to parse (a non-numeric age, an empty required field, ...)
+// are logged with their line and skipped instead of failing the whole load
+fn warn_malformed(err: &csv::Error) {
+    match err.position() {
+        Some(position) => tracing::warn!("Skipping malformed row at line {}: {}", position.line(), err),
+        None => tracing::warn!("Skipping malformed row: {}", err),
+    }
+}
+
+// Keep only the last row for each customer id (the latest submission, in file
+// order), leaving the survivors in their original order. Returns the records
+// and the number of rows dropped.
+pub fn dedup_keep_last(customers: Vec<CustomerFeedback>) -> (Vec<CustomerFeedback>, usize) {
+    let total = customers.len();
+    let mut seen = std::collections::HashSet::new();
+    let mut kept: Vec<CustomerFeedback> = customers
+        .into_iter()
+        .rev()
+        .filter(|c| seen.insert(c.customer_id.clone()))
+        .collect();
+    kept.reverse();
+    let dropped = total - kept.len();
+    (kept, dropped)
+}
+
+// Read the whole CSV into memory, apply the missing-value policies and field
+// normalization, and generate each record's summary. Returns the records and
+// the number of malformed rows skipped; it only fails on malformed rows if no
+// row parsed at all.
+#[tracing::instrument(name = "load", skip_all, fields(location = source.location(), record_count))]
+pub fn load_customers(source: &DataSource, options: &LoadOptions) -> Result<(Vec<CustomerFeedback>, usize), anyhow::Error> {
+    let file_content = match source {
+        DataSource::Local(data_path) => FileLoader::with_glob(data_path)?
+            .read()
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("Data file not found: {}", data_path))??,
+        DataSource::Remote { content, .. } | DataSource::Sqlite { content, .. } => content.clone(),
+    };
+
+    let mut rdr = csv::Reader::from_reader(file_content.as_bytes());
+    options.column_mapping.apply(&mut rdr)?;
+    check_date_column(&mut rdr, options)?;
+    let mut customers: Vec<CustomerFeedback> = Vec::new();
+    let mut malformed = 0;
+    let mut first_error = None;
+    for record in rdr.deserialize() {
+        match record {
+            Ok(customer) => customers.push(customer),
+            Err(e) => {
+                warn_malformed(&e);
+                malformed += 1;
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+    if let (true, Some(e)) = (customers.is_empty(), first_error) {
+        return Err(anyhow::anyhow!("None of the {} rows could be parsed; the first error was: {}", malformed, e));
+    }
+
+    let customers = match options.date_range {
+        Some(_) => {
+            let total = customers.len();
+            let customers: Vec<CustomerFeedback> = customers.into_iter().filter(|c| in_range(c, options)).collect();
+            println!("Filtered out {} of {} rows outside the date range", total - customers.len(), total);
+            customers
+        }
+        None => customers,
+    };
+
+    let mut normalizer = options.normalization.as_ref().map(Normalizer::new);
+    let customers: Vec<CustomerFeedback> =
+        missing::apply_missing_value_policies(customers, options.missing_satisfaction, options.missing_income)
+            .into_iter()
+            .map(|mut c| {
+                if let Some(normalizer) = &mut normalizer {
+                    normalizer.apply(&mut c);
+                }
+                options.currency.apply(&mut c);
+                c.generate_summary();
+                c
+            })
+            .collect();
+
+    // Income is compared in whole currency units, so this runs after scaling
+    let customers = if options.metadata.is_empty() {
+        customers
+    } else {
+        let total = customers.len();
+        let customers: Vec<CustomerFeedback> = customers.into_iter().filter(|c| options.metadata.matches(c)).collect();
+        if customers.is_empty() {
+            return Err(anyhow::anyhow!("None of the {} records match {}", total, options.metadata.describe()));
+        }
+        println!("{} of {} records match {}", customers.len(), total, options.metadata.describe());
+        customers
+    };
+    tracing::Span::current().record("record_count", customers.len());
+    Ok((customers, malformed))
+}
+
+// Reads the CSV record by record and yields chunks as they fill, so only one
+// chunk of parsed records is held at a time. Only per-record missing-value
+// policies (skip/unknown) can be applied in this mode.
+pub struct StreamingChunks<'a> {
+    records: csv::DeserializeRecordsIntoIter<File, CustomerFeedback>,
+    chunk_size: usize,
+    options: &'a LoadOptions,
+    normalizer: Option<Normalizer<'a>>,
+    loaded: usize,
+    skipped: usize,
+    malformed: usize,
+    out_of_range: usize,
+    // Records that fail the metadata filter
+    unmatched: usize,
+    // Row number of the last admitted row for each customer id when
+    // duplicates are collapsed, and the rows dropped for it
+    last_rows: Option<HashMap<String, usize>>,
+    row: usize,
+    collapsed: usize,
+    finished: bool,
+}
+
+impl<'a> StreamingChunks<'a> {
+    // With `dedup`, only the last row for each customer id is yielded, as
+    // `dedup_keep_last` does for loads held in memory. Which row is last isn't
+    // known until the end of the file, so the file is read once up front for
+    // the ids alone.
+    pub fn open(data_path: &str, options: &'a LoadOptions, chunk_size: usize, dedup: bool) -> Result<Self, anyhow::Error> {
+        if [options.missing_satisfaction, options.missing_income].contains(&MissingValuePolicy::ImputeMean) {
+            return Err(anyhow::anyhow!(
+                "impute-mean needs the whole dataset and cannot be used with streaming loads"
+            ));
+        }
+
+        let mut rdr = csv::Reader::from_path(data_path)?;
+        options.column_mapping.apply(&mut rdr)?;
+        check_date_column(&mut rdr, options)?;
+
+        let mut normalizer = options.normalization.as_ref().map(Normalizer::new);
+        let last_rows = if dedup {
+            let mut ids = csv::Reader::from_path(data_path)?;
+            options.column_mapping.apply(&mut ids)?;
+            let mut last_rows = HashMap::new();
+            for (row, record) in ids.into_deserialize::<CustomerFeedback>().enumerate() {
+                if let Ok(mut customer) = record {
+                    if let Admission::Admitted = admit(&mut customer, options, normalizer.as_mut()) {
+                        last_rows.insert(customer.customer_id, row);
+                    }
+                }
+            }
+            Some(last_rows)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            records: rdr.into_deserialize(),
+            chunk_size,
+            options,
+            normalizer,
+            loaded: 0,
+            skipped: 0,
+            malformed: 0,
+            out_of_range: 0,
+            unmatched: 0,
+            last_rows,
+            row: 0,
+            collapsed: 0,
+            finished: false,
+        })
+    }
+}
+
+impl Iterator for StreamingChunks<'_> {
+    type Item = Result<Vec<CustomerFeedback>, anyhow::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+
+        let mut chunk = Vec::with_capacity(self.chunk_size);
+        while chunk.len() < self.chunk_size {
+            let record = self.records.next();
+            let row = self.row;
+            self.row += 1;
+            match record {
+                Some(Ok(customer)) if customer.customer_id.trim().is_empty() => {
+                    return Some(Err(anyhow::anyhow!("Record {} has an empty customer id", row + 1)));
+                }
+                Some(Ok(mut customer)) => match admit(&mut customer, self.options, self.normalizer.as_mut()) {
+                    Admission::OutOfRange => self.out_of_range += 1,
+                    Admission::MissingValues => self.skipped += 1,
+                    Admission::Unmatched => self.unmatched += 1,
+                    Admission::Admitted => {
+                        if let Some(last_rows) = &self.last_rows {
+                            if last_rows.get(&customer.customer_id) != Some(&row) {
+                                self.collapsed += 1;
+                                continue;
+                            }
+                        }
+                        customer.generate_summary();
+                        self.loaded += 1;
+                        chunk.push(customer);
+                    }
+                },
+                Some(Err(e)) => {
+                    warn_malformed(&e);
+                    self.malformed += 1;
+                }
+                None => {
+                    self.finished = true;
+                    if self.loaded == 0 && self.malformed > 0 {
+                        return Some(Err(anyhow::anyhow!("None of the {} rows could be parsed", self.malformed)));
+                    }
+                    // The chunk bar is still drawn while the last chunk is read
+                    progress::println(format!(
+                        "Streamed {} customer records ({} skipped for missing values, {} malformed rows skipped)",
+                        self.loaded, self.skipped, self.malformed
+                    ));
+                    if self.options.date_range.is_some() {
+                        progress::println(format!("Filtered out {} rows outside the date range", self.out_of_range));
+                    }
+                    if !self.options.metadata.is_empty() {
+                        if self.loaded == 0 {
+                            return Some(Err(anyhow::anyhow!(
+                                "None of the {} records match {}",
+                                self.unmatched,
+                                self.options.metadata.describe()
+                            )));
+                        }
+                        progress::println(format!(
+                            "{} of {} records match {}",
+                            self.loaded + self.collapsed,
+                            self.loaded + self.collapsed + self.unmatched,
+                            self.options.metadata.describe()
+                        ));
+                    }
+                    if self.collapsed > 0 {
+                        progress::println(format!(
+                            "Collapsed {} duplicate rows, keeping the last row for each customer id",
+                            self.collapsed
+                        ));
+                    }
+                    break;
+                }
+            }
+        }
+
+        (!chunk.is_empty()).then_some(Ok(chunk))
+    }
+}
+
+pub struct RecordCounts {
+    pub total: usize,
+    pub valid: usize,
+    pub rejected: usize,
+    pub out_of_range: usize,
+    // Records that fail the metadata filter
+    pub unmatched: usize,
+    // Repeated customer ids that are collapsed before embedding
+    pub duplicates: usize,
+}
+
+// Validate every row without keeping it, through the same per-record steps as
+// a real load: rows that fail to parse or that the missing-value policies
+// would drop count as rejected, parseable rows outside the date range as out
+// of range, and those failing the metadata filter as unmatched. With `dedup`,
+// repeats of a customer id are counted as duplicates, so `valid` is the
+// number of records that would be embedded.
+pub fn count_records(source: &DataSource, options: &LoadOptions, dedup: bool) -> Result<RecordCounts, anyhow::Error> {
+    let mut rdr = source.csv_reader()?;
+    options.column_mapping.apply(&mut rdr)?;
+    check_date_column(&mut rdr, options)?;
+
+    let mut counts = RecordCounts { total: 0, valid: 0, rejected: 0, out_of_range: 0, unmatched: 0, duplicates: 0 };
+    let mut normalizer = options.normalization.as_ref().map(Normalizer::new);
+    let mut ids = std::collections::HashSet::new();
+    for record in rdr.deserialize::<CustomerFeedback>() {
+        counts.total += 1;
+        let Ok(mut customer) = record else {
+            counts.rejected += 1;
+            continue;
+        };
+        match admit(&mut customer, options, normalizer.as_mut()) {
+            Admission::OutOfRange => counts.out_of_range += 1,
+            Admission::MissingValues => counts.rejected += 1,
+            Admission::Unmatched => counts.unmatched += 1,
+            Admission::Admitted if dedup && !ids.insert(customer.customer_id) => counts.duplicates += 1,
+            Admission::Admitted => counts.valid += 1,
+        }
+    }
+
+    Ok(counts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options() -> LoadOptions {
+        LoadOptions {
+            column_mapping: ColumnMapping::default(),
+            missing_satisfaction: MissingValuePolicy::Unknown,
+            missing_income: MissingValuePolicy::Unknown,
+            normalization: None,
+            date_range: None,
+            currency: CurrencyConfig { currency: Default::default(), scale: 1.0 },
+            metadata: MetadataFilter::default(),
+        }
+    }
+
+    #[test]
+    fn streamed_loads_keep_the_last_row_per_customer() {
+        let csv = "\
+CustomerID,Age,Gender,Country,Income,ProductQuality,ServiceQuality,PurchaseFrequency,FeedbackScore,LoyaltyLevel,SatisfactionScore
+a,30,Male,UK,1000,5,5,1,Low,Bronze,50
+b,31,Male,UK,1000,5,5,2,Low,Bronze,50
+a,32,Male,UK,1000,5,5,3,Low,Bronze,50
+c,33,Male,UK,1000,5,5,4,Low,Bronze,50
+b,34,Male,UK,1000,5,5,5,Low,Bronze,50
+";
+        let path = std::env::temp_dir().join(format!("streaming-dedup-{}.csv", std::process::id()));
+        std::fs::write(&path, csv).unwrap();
+        let options = options();
+
+        let (in_memory, _) = load_customers(&DataSource::Local(path.display().to_string()), &options).unwrap();
+        let (in_memory, collapsed) = dedup_keep_last(in_memory);
+        assert_eq!(collapsed, 2);
+
+        let streamed: Vec<CustomerFeedback> = StreamingChunks::open(&path.display().to_string(), &options, 2, true)
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap()
+            .concat();
+        let all: usize = StreamingChunks::open(&path.display().to_string(), &options, 2, false)
+            .unwrap()
+            .map(|chunk| chunk.unwrap().len())
+            .sum();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(streamed, in_memory);
+        let ages: Vec<i32> = streamed.iter().map(|c| c.age).collect();
+        assert_eq!(ages, [32, 33, 34]);
+        assert_eq!(all, 5);
+    }
+
+    #[test]
+    fn sqlite_queries_are_validated() {
+        let err = DataSource::sqlite("customers.db", " .shell echo hi").err().unwrap();
+        assert!(err.to_string().contains("dot-commands are not allowed"), "{}", err);
+
+        // The remaining checks need the sqlite3 shell
+        if std::process::Command::new("sqlite3").arg("-version").output().is_err() {
+            return;
+        }
+        let path = std::env::temp_dir().join(format!("sqlite-columns-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let created = std::process::Command::new("sqlite3")
+            .arg(&path)
+            .arg(
+                "CREATE TABLE feedback (CustomerID, Age, Gender, Country, Income, ProductQuality, ServiceQuality, \
+                 PurchaseFrequency, FeedbackScore, LoyaltyLevel, SatisfactionScore); \
+                 INSERT INTO feedback VALUES ('a', 30, 'Male', 'UK', 1000, 5, 5, 1, 'Low', 'Bronze', 50);",
+            )
+            .status()
+            .unwrap();
+        assert!(created.success());
+        let database = path.display().to_string();
+
+        let source = DataSource::sqlite(&database, "SELECT * FROM feedback").unwrap();
+        let (customers, _) = load_customers(&source, &options()).unwrap();
+        assert_eq!(customers.len(), 1);
+
+        let source = DataSource::sqlite(&database, "SELECT CustomerID, Age, Gender, Country FROM feedback").unwrap();
+        let err = load_customers(&source, &options()).err().unwrap();
+        assert!(
+            err.to_string().contains("Missing required column(s) in data file: Income, ProductQuality"),
+            "{}",
+            err
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,73 @@
+use crate::models::CustomerFeedback;
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+
+const DEFAULT_LEVELS: &[&str] = &["Bronze", "Silver", "Gold", "Platinum"];
+
+// Business order of the LoyaltyLevel values, lowest first. Levels are matched
+// case-insensitively; anything not listed has no rank and sorts last.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoyaltyOrder {
+    levels: Vec<String>,
+}
+
+impl Default for LoyaltyOrder {
+    fn default() -> Self {
+        Self { levels: DEFAULT_LEVELS.iter().map(|level| level.to_lowercase()).collect() }
+    }
+}
+
+impl LoyaltyOrder {
+    // From --loyalty-order, lowest level first
+    pub fn new(levels: &[String]) -> Result<Self, anyhow::Error> {
+        let mut seen = BTreeSet::new();
+        let levels: Vec<String> = levels.iter().map(|level| level.trim().to_lowercase()).collect();
+        for level in &levels {
+            if level.is_empty() {
+                return Err(anyhow::anyhow!("--loyalty-order has an empty level"));
+            }
+            if !seen.insert(level) {
+                return Err(anyhow::anyhow!("--loyalty-order lists {} twice", level));
+            }
+        }
+        if levels.len() > u8::MAX as usize {
+            return Err(anyhow::anyhow!("--loyalty-order supports at most {} levels", u8::MAX));
+        }
+        Ok(Self { levels })
+    }
+
+    // 0 for the lowest level
+    pub fn loyalty_rank(&self, level: &str) -> Option<u8> {
+        let level = level.trim().to_lowercase();
+        self.levels.iter().position(|known| *known == level).map(|rank| rank as u8)
+    }
+
+    // Rank scaled to [0, 1], lowest level 0 and highest 1
+    pub fn fraction(&self, level: &str) -> Option<f64> {
+        let top = self.levels.len().saturating_sub(1).max(1) as f64;
+        self.loyalty_rank(level).map(|rank| rank as f64 / top)
+    }
+
+    // Business order, ascending or descending; unknown levels come last either way
+    pub fn compare(&self, a: &str, b: &str, descending: bool) -> Ordering {
+        match (self.loyalty_rank(a), self.loyalty_rank(b)) {
+            (Some(a), Some(b)) if descending => b.cmp(&a),
+            (Some(a), Some(b)) => a.cmp(&b),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        }
+    }
+
+    // Warn once per level the order doesn't know
+    pub fn warn_unknown<'a>(&self, customers: impl IntoIterator<Item = &'a CustomerFeedback>) {
+        let unknown: BTreeSet<&str> = customers
+            .into_iter()
+            .map(|customer| customer.loyalty_level.as_str())
+            .filter(|level| self.loyalty_rank(level).is_none())
+            .collect();
+        for level in unknown {
+            tracing::warn!("Loyalty level {:?} is not in the loyalty order; it sorts last and fails every ordering comparison", level);
+        }
+    }
+}
@@ -1,191 +1,2139 @@
+mod aspects;
+mod broaden;
+mod budget;
+mod cache;
+mod centrality;
+mod citations;
+mod cli;
+mod cluster;
+mod compare;
+mod confidence;
+mod currency;
+mod daemon;
+mod degenerate;
+mod embed;
+mod enrich;
+mod errors;
+mod explain;
+mod external;
+mod features;
+mod field_match;
+mod filter;
+mod health;
+mod export;
+mod fuzzy;
+mod metrics;
+mod missing;
+mod loader;
+mod loyalty;
+mod manifest;
+mod matrix;
 mod models;
-use models::CustomerFeedback;
+mod normalize;
+mod persist;
+mod postprocess;
+mod prefix;
+mod progress;
+mod projection;
+mod prompt;
+mod providers;
+mod queries;
+mod record;
+mod redact;
+mod refusal;
+mod remote;
+mod report;
+mod retry;
+mod rules;
+mod run_manifest;
+mod sampling;
+mod schema;
+mod score_display;
+mod search;
+mod segments;
+mod sheet;
+mod sqlite;
+mod stats;
+mod stop;
+mod store_file;
+mod stratify;
+mod streaming;
+mod summarize;
+mod telemetry;
+mod tokens;
+mod transcript;
+mod trends;
+mod variants;
+use budget::EmbeddingBudget;
+use cache::SummaryCache;
+use embed::{EmbedOptions, MissingEmbeddingPolicy};
+use clap::Parser;
+use cli::{Args, Command, DaemonAction};
+use daemon::DaemonRequest;
+use errors::{describe_error, PipelineError};
+use chrono::Utc;
+use loader::LoadOptions;
+use metrics::{RunMetrics, UsageLine};
+use models::{AnalysisRequest, AnalysisResult, CustomerFeedback};
+use normalize::NormalizationMap;
+use prefix::PrefixedEmbedding;
+use projection::ProjectedEmbedding;
+use providers::{EmbeddingRole, ProviderClient, ProviderCompletion};
+use prompt::PromptOptions;
+use record::EmbeddableRecord;
+use redact::Redaction;
+use retry::{RetryPolicy, RetryingPrompt};
+use schema::ColumnMapping;
+use transcript::TranscriptWriter;
 use rig::{
-    embeddings::{EmbeddingsBuilder, Embedding},
+    embeddings::{Embedding, EmbeddingModel},
     parallel,
-    pipeline::{self, agent_ops::lookup, passthrough, Op},
-    providers::openai::{Client, TEXT_EMBEDDING_ADA_002},
+    pipeline::{self, passthrough, Op},
     vector_store::in_memory_store::InMemoryVectorStore,
-    loaders::FileLoader,
     OneOrMany,
 };
-use std::time::Duration;
+use futures::{stream, StreamExt};
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::AsyncBufReadExt;
 use tokio::time::sleep;
 
-const CHUNK_SIZE: usize = 1000;  // Process 1000 records at a time
+const EMBEDDING_DELAY: Duration = Duration::from_millis(200);  // Pause after each embedding batch
+const QUERY_DELAY: Duration = Duration::from_secs(2);  // Pause between batch queries
+const STREAMING_THRESHOLD_BYTES: u64 = 512 * 1024 * 1024;  // Stream files of 512 MiB or more
 
-async fn process_chunk(
-    chunk: Vec<CustomerFeedback>,
-    embedding_model: &rig::providers::openai::EmbeddingModel,
+const ANALYST_PREAMBLE: &str = r#"
+    You are an expert customer insights analyst. You will be provided with:
+    1. A specific analysis query
+    2. Several relevant customer profiles with detailed metrics including:
+       - Demographics (age, gender, country)
+       - Income level
+       - Product and service quality ratings
+       - Purchase frequency
+       - Feedback scores
+       - Loyalty level
+       - Satisfaction scores
+
+    Analyze the provided profiles in relation to the query and provide:
+    1. Key behavioral patterns and trends from the specific profiles shown
+    2. Risk factors or concerns based on the actual data
+    3. Specific, actionable recommendations
+    4. Opportunities for improving customer satisfaction
+
+    Always reference specific data points from the provided profiles to support your analysis.
+    Be concise but insightful.
+"#;
+
+// Added with --structured, mapping the four parts above onto AnalysisResult
+const STRUCTURED_INSTRUCTIONS: &str = "\n\nSubmit the four parts of your analysis as key_patterns, risk_factors, recommendations and opportunities, one finding per list item.";
+
+// A chunk's embedded records and how many were dropped without a vector
+type EmbeddedChunk<T> = (Vec<(T, OneOrMany<Embedding>)>, usize);
+
+#[tracing::instrument(
+    name = "embed_chunk",
+    skip(chunk, embedding_model, cache),
+    fields(record_count = chunk.len(), model = %metrics.embedding.model)
+)]
+async fn process_chunk<M: EmbeddingModel, T: EmbeddableRecord>(
+    chunk: Vec<T>,
+    embedding_model: &M,
     chunk_num: usize,
-) -> Result<Vec<(CustomerFeedback, OneOrMany<Embedding>)>, anyhow::Error> {
-    println!("Processing chunk {} ({} records)...", chunk_num, chunk.len());
-    
-    let embeddings = EmbeddingsBuilder::new(embedding_model.clone())
-        .documents(chunk)?
-        .build()
-        .await?;
+    cache: Option<&SummaryCache>,
+    metrics: &mut RunMetrics,
+    options: &EmbedOptions,
+) -> Result<EmbeddedChunk<T>, anyhow::Error> {
+    progress::println(format!("Processing chunk {} ({} records)...", chunk_num, chunk.len()));
+
+    // Serve unchanged summaries from the cache and only embed the rest
+    let mut embeddings = Vec::with_capacity(chunk.len());
+    let mut uncached = Vec::new();
+    // Records left without a vector by --missing-embeddings
+    let mut dropped = 0;
+    for record in chunk {
+        match cache.and_then(|cache| cache.get(record.summary())) {
+            Some(vec) => {
+                let embedding = Embedding { document: record.summary().to_string(), vec };
+                embeddings.push((record, OneOrMany::one(embedding)));
+            }
+            None => uncached.push(record),
+        }
+    }
+
+    // Embed in batches of `batch_size` documents, independent of the chunk size,
+    // with up to `max_in_flight` batches outstanding
+    let mut batches = stream::iter(uncached.chunks(options.batch_size))
+        .map(|batch| async move {
+            let result = embed::embed_with_retry(embedding_model, batch, &options.retry, chunk_num).await;
+            // Add a small delay to respect rate limits
+            sleep(options.delay).await;
+            (batch, result)
+        })
+        .buffered(options.max_in_flight);
+    while let Some((batch, result)) = batches.next().await {
+        let (mut generated, mut missing) = result?;
+        metrics.record_embedding(
+            batch.len().div_ceil(M::MAX_DOCUMENTS),
+            &batch.iter().map(EmbeddableRecord::summary).collect::<Vec<_>>(),
+        );
 
-    println!("Completed chunk {} with {} embeddings", chunk_num, embeddings.len());
-    
-    // Add a small delay to respect rate limits
-    sleep(Duration::from_millis(200)).await;
-    
-    Ok(embeddings)
+        if !missing.is_empty() {
+            tracing::warn!(
+                "Embedding response for chunk {} was short: {} of {} records got no vector",
+                chunk_num,
+                missing.len(),
+                batch.len()
+            );
+            if options.missing == MissingEmbeddingPolicy::Retry {
+                let (recovered, still_missing) = embed::embed_with_retry(embedding_model, &missing, &options.retry, chunk_num).await?;
+                metrics.record_embedding(
+                    missing.len().div_ceil(M::MAX_DOCUMENTS),
+                    &missing.iter().map(EmbeddableRecord::summary).collect::<Vec<_>>(),
+                );
+                generated.extend(recovered);
+                missing = still_missing;
+            }
+            if !missing.is_empty() {
+                tracing::warn!(
+                    "Dropping {} records without an embedding: {}",
+                    missing.len(),
+                    missing.iter().map(EmbeddableRecord::record_id).collect::<Vec<_>>().join(", ")
+                );
+                dropped += missing.len();
+            }
+        }
+
+        if let Some(cache) = cache {
+            for (record, embedding) in &generated {
+                cache.insert(record.summary(), embedding.first().vec.clone());
+            }
+        }
+        embeddings.extend(generated);
+    }
+
+    progress::println(format!("Completed chunk {} with {} embeddings", chunk_num, embeddings.len()));
+
+    Ok((embeddings, dropped))
 }
 
 #[tokio::main]
 async fn main() -> Result<(), anyhow::Error> {
-    // Setup logging
-    tracing_subscriber::fmt()
-        .with_max_level(tracing::Level::INFO)
-        .init();
+    let args = Args::parse();
+    let analysis_model = args.chat_model.as_deref().unwrap_or(args.provider.default_chat_model());
+    let embedding_model_name = args.embedding_model.as_deref().unwrap_or(args.provider.default_embedding_model());
+    let verbose_errors = args.verbose_errors;
+    if verbose_errors {
+        // Make anyhow capture backtraces for errors reported from here on
+        std::env::set_var("RUST_LIB_BACKTRACE", "1");
+    }
+
+    // Setup logging (and span export with the `otel` feature)
+    let _telemetry = telemetry::init()?;
+
+    // Checking a queries file needs no data or API access
+    if let Some(Command::LintQueries { file, max_chars }) = &args.command {
+        let content = std::fs::read_to_string(file)
+            .map_err(|e| anyhow::anyhow!("Cannot read queries file {}: {}", file.display(), e))?;
+        let issues = queries::lint(&content, *max_chars);
+        for issue in &issues {
+            let severity = if issue.error { "error" } else { "warning" };
+            println!("{}:{}: {}: {}", file.display(), issue.line, severity, issue.message);
+        }
+        let errors = issues.iter().filter(|issue| issue.error).count();
+        println!(
+            "{} queries, {} errors, {} warnings",
+            queries::parse(&content).len(),
+            errors,
+            issues.len() - errors
+        );
+        if errors > 0 {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    let provider_config = match &args.provider_config {
+        Some(path) => providers::ProviderConfig::from_file(path)?,
+        None => providers::ProviderConfig::default(),
+    };
+    // Attempts are numbered from 0 up to the retry limit, so a fallback after
+    // more failures than that would never be tried
+    let analysis_retries = provider_config.completion.retries(args.agent_max_retries);
+    if args.fallback_model.is_some() && args.fallback_after > analysis_retries {
+        return Err(anyhow::anyhow!(
+            "--fallback-after {} is more than the {} analysis retries, so --fallback-model would never be used",
+            args.fallback_after,
+            analysis_retries
+        ));
+    }
+
+    // Comparing persisted runs needs neither the dataset nor embeddings
+    if let Some(Command::CompareRuns { runs }) = &args.command {
+        let comparer = ProviderClient::new(&provider_config.endpoint(args.provider)?).agent(analysis_model)
+            .preamble(compare::COMPARE_PREAMBLE)
+            .build();
+        for (query, report) in compare::compare_runs(&comparer, runs).await? {
+            println!("\n=== Query: {} ===\n{}", query, report);
+        }
+        return Ok(());
+    }
+
+    // Daemon lifecycle commands and queries answered by a running daemon
+    // return before any data is loaded
+    match &args.command {
+        Some(Command::Daemon { action: DaemonAction::Start }) => return daemon::start().await,
+        Some(Command::Daemon { action: DaemonAction::Stop }) => {
+            match daemon::send(&DaemonRequest::Stop).await? {
+                Some(_) => println!("Daemon stopped"),
+                None => println!("No daemon running"),
+            }
+            return Ok(());
+        }
+        Some(Command::Daemon { action: DaemonAction::Status }) => {
+            println!("Daemon {}", daemon::status().await?);
+            return Ok(());
+        }
+        Some(Command::Query { text }) => {
+            if let Some(response) = daemon::send(&DaemonRequest::Query { query: text.clone() }).await? {
+                print!("{}", response.output);
+                if !response.ok {
+                    return Err(anyhow::anyhow!("Daemon failed to answer the query"));
+                }
+                return Ok(());
+            }
+        }
+        _ => {}
+    }
+
+    // Invalid sizes fail before any data is downloaded or API called
+    if args.chunk_size == 0 {
+        return Err(anyhow::anyhow!("--chunk-size must be at least 1"));
+    }
+    if args.top_k == 0 {
+        return Err(anyhow::anyhow!("--top-k must be at least 1"));
+    }
+    if args.chunk_concurrency == 0 {
+        return Err(anyhow::anyhow!("--chunk-concurrency must be at least 1"));
+    }
+    let embedding_dims = args.provider.embedding_dims(embedding_model_name).ok_or_else(|| {
+        anyhow::anyhow!(
+            "Unknown {:?} embedding model {}; use one of {}",
+            args.provider,
+            embedding_model_name,
+            args.provider.embedding_models().join(", ")
+        )
+    })?;
+
+    // Read the batch up front so a bad queries file fails before any embedding
+    let file_queries = match &args.queries_file {
+        Some(path) => Some(queries::read(path)?),
+        None => None,
+    };
 
     // Check for data file
-    let data_path = "data/customer_feedback_satisfaction.csv";
-    if !std::path::Path::new(data_path).exists() {
-        return Err(anyhow::anyhow!("Data file not found: {}", data_path));
+    let data_source = match (&args.sqlite, &args.sql) {
+        (Some(database), Some(query)) => loader::DataSource::sqlite(database, query)?,
+        _ => loader::DataSource::open(&args.data_path).await?,
+    };
+    let data_path = data_source.location();
+
+    let embed_batch_size = args.embed_batch_size.unwrap_or(args.chunk_size);
+    if embed_batch_size == 0 {
+        return Err(anyhow::anyhow!("--embed-batch-size must be at least 1"));
     }
+    // Documents per embedding request: our batch size, capped by the provider limit
+    let docs_per_request = embed_batch_size.min(args.provider.max_documents());
 
-    // Initialize OpenAI client
-    let openai_client = Client::from_env();
-    let embedding_model = openai_client.embedding_model(TEXT_EMBEDDING_ADA_002);
+    if args.cluster == Some(0) {
+        return Err(anyhow::anyhow!("--cluster must be at least 1"));
+    }
+    if args.target_store_size == Some(0) {
+        return Err(anyhow::anyhow!("--target-store-size must be at least 1"));
+    }
+    if args.knn_centrality == Some(0) {
+        return Err(anyhow::anyhow!("--knn-centrality must be at least 1"));
+    }
+    if let Some(Command::DiscoverSegments { k: 0, .. }) = &args.command {
+        return Err(anyhow::anyhow!("discover-segments needs at least one segment (--k)"));
+    }
+
+    let normalization = match (&args.normalization_map, args.normalize_fields) {
+        (Some(path), _) => Some(NormalizationMap::with_defaults().merge_file(path)?),
+        (None, true) => Some(NormalizationMap::with_defaults()),
+        (None, false) => None,
+    };
+    let mut column_mapping = match &args.column_map {
+        Some(path) => ColumnMapping::from_file(path)?,
+        None => ColumnMapping::default(),
+    };
+    if let Some(id_column) = &args.id_column {
+        column_mapping.set("CustomerID", id_column)?;
+    }
+    let date_range = match (args.since, args.until) {
+        (None, None) => None,
+        (Some(since), Some(until)) if since > until => {
+            return Err(anyhow::anyhow!("--since {} is after --until {}", since, until));
+        }
+        (since, until) => Some(loader::DateRange { since, until }),
+    };
+    if !(args.income_scale > 0.0 && args.income_scale.is_finite()) {
+        return Err(anyhow::anyhow!("--income-scale must be a positive number"));
+    }
+    let load_options = LoadOptions {
+        column_mapping,
+        missing_satisfaction: args.missing_satisfaction,
+        missing_income: args.missing_income,
+        normalization,
+        date_range,
+        currency: currency::CurrencyConfig { currency: args.income_currency, scale: args.income_scale },
+        metadata: loader::MetadataFilter {
+            countries: args.country.clone(),
+            min_income: args.min_income,
+            loyalty_levels: args.loyalty_level.clone(),
+        },
+    };
+
+    let loyalty_order = loyalty::LoyaltyOrder::new(&args.loyalty_order)?;
+    let filter = args.filter.clone().map(|filter| filter.with_loyalty_order(&loyalty_order)).transpose()?;
+
+    // Quick offline sanity check: validate and count, then exit before any API use
+    if args.count_only {
+        let counts = loader::count_records(&data_source, &load_options, args.trend_summaries || !args.keep_duplicates)?;
+        let chunks = counts.valid.div_ceil(args.chunk_size);
+        let api_calls: usize = (0..chunks)
+            .map(|i| (counts.valid - i * args.chunk_size).min(args.chunk_size).div_ceil(docs_per_request))
+            .sum();
+        println!("records={}", counts.total);
+        println!("valid={}", counts.valid);
+        println!("rejected={}", counts.rejected);
+        if load_options.date_range.is_some() {
+            println!("out_of_range={}", counts.out_of_range);
+        }
+        if !load_options.metadata.is_empty() {
+            println!("unmatched={}", counts.unmatched);
+        }
+        if counts.duplicates > 0 {
+            println!("duplicates={}", counts.duplicates);
+        }
+        println!("chunks={}", chunks);
+        println!("estimated_api_calls={}", api_calls);
+        return Ok(());
+    }
+
+    // Large files are streamed record by record instead of being read into memory
+    let streaming = match &data_source {
+        loader::DataSource::Local(path) => {
+            args.streaming || std::fs::metadata(path)?.len() >= STREAMING_THRESHOLD_BYTES
+        }
+        loader::DataSource::Remote { .. } | loader::DataSource::Sqlite { .. } if args.streaming => {
+            return Err(anyhow::anyhow!("--streaming reads from a local file and cannot be used with a URL or --sqlite"));
+        }
+        loader::DataSource::Remote { .. } | loader::DataSource::Sqlite { .. } => false,
+    };
+    if streaming && args.target_store_size.is_some() {
+        return Err(anyhow::anyhow!(
+            "--target-store-size samples across the whole dataset and cannot be used with streaming loads"
+        ));
+    }
+    if streaming
+        && (args.trend_summaries
+            || matches!(
+                args.command,
+                Some(
+                    Command::Summarize { .. }
+                        | Command::FindCustomer { .. }
+                        | Command::PreviewSummaries { .. }
+                        | Command::InspectStore { .. }
+                        | Command::RankCustomers { .. }
+                )
+            ))
+    {
+        return Err(anyhow::anyhow!(
+            "--trend-summaries, summarize, find-customer, preview-summaries, inspect-store and rank-customers need the whole dataset in memory and cannot be used with streaming loads"
+        ));
+    }
 
     // Load and parse customer data
-    let file_content = FileLoader::with_glob(data_path)?
-        .read()
-        .into_iter()
-        .next()
-        .unwrap()?;
-
-    let mut rdr = csv::Reader::from_reader(file_content.as_bytes());
-    let customers: Vec<CustomerFeedback> = rdr.deserialize()
-        .collect::<Result<Vec<CustomerFeedback>, _>>()?
-        .into_iter()
-        .map(|mut c| {
-            c.generate_summary();
-            c
-        })
-        .collect();
+    let customers = if streaming {
+        Vec::new()
+    } else {
+        let (customers, malformed) = loader::load_customers(&data_source, &load_options)?;
+        if malformed > 0 {
+            println!("Loaded {} customer records, skipped {} malformed rows", customers.len(), malformed);
+        } else {
+            println!("Loaded {} customer records", customers.len());
+        }
+        // Repeated ids are expected when they are about to be collapsed
+        loader::validate_ids(&customers, args.trend_summaries || !args.keep_duplicates)?;
+        loyalty_order.warn_unknown(&customers);
+
+        if args.trend_summaries {
+            trends::collapse_histories(customers)
+        } else if args.keep_duplicates {
+            customers
+        } else {
+            let (customers, collapsed) = loader::dedup_keep_last(customers);
+            if collapsed > 0 {
+                println!("Collapsed {} duplicate rows, keeping the last row for each customer id", collapsed);
+            }
+            customers
+        }
+    };
+    // Population statistics for the analyst preamble, taken before any sampling;
+    // streamed loads compute them from the embedded records instead
+    let dataset_stats = (!streaming && !args.no_dataset_stats).then(|| stats::compute_stats(&customers));
+
+    // Show generated summaries without embedding anything, spread evenly over the file
+    if let Some(Command::PreviewSummaries { sample }) = &args.command {
+        let step = customers.len().div_ceil((*sample).max(1)).max(1);
+        for customer in customers.iter().step_by(step).take(*sample) {
+            println!("{}: {}", customer.customer_id, customer.profile_summary);
+        }
+        return Ok(());
+    }
+
+    // Composite health ranking from the numeric fields alone
+    if let Some(Command::RankCustomers { output, weights, order }) = &args.command {
+        let csv_options = export::CsvExportOptions { bom: args.csv_bom, line_ending: args.csv_line_ending };
+        let scored = health::health_scores(&customers, weights, &loyalty_order);
+        health::write_ranking(output, scored, *order, &loyalty_order, &csv_options)?;
+        println!("Wrote {} ranked customers to {}", customers.len(), output.display());
+        return Ok(());
+    }
+
+    // Vectors projected to fewer dimensions are different vectors, so the matrix
+    // is part of the summary cache's model identity
+    let projection = match args.reduce_dims {
+        Some(dims) => Some(Arc::new(projection::Projection::load_or_create(&args.projection_file, embedding_dims, dims)?)),
+        None => None,
+    };
+    let mut cache_identity = prefix::cache_identity(embedding_model_name, &args.document_prefix);
+    if let Some(projection) = &projection {
+        cache_identity.push_str(&format!(" projected by {}", projection.fingerprint()));
+    }
+
+    // Manifest of what the index would hold, read from the summary cache
+    if let Some(Command::InspectStore { format, output }) = &args.command {
+        let cache_path = args.summary_cache.as_ref().ok_or_else(|| {
+            anyhow::anyhow!("inspect-store reads embeddings from --summary-cache; pass the cache file used to build the store")
+        })?;
+        let cache = SummaryCache::load(cache_path, &cache_identity, args.store_format)?;
+        let entries = manifest::entries(&customers, &cache);
+        match output {
+            Some(path) => manifest::write(&entries, *format, &mut std::fs::File::create(path)?)?,
+            None => manifest::write(&entries, *format, &mut std::io::stdout().lock())?,
+        }
+        let (hits, lookups) = cache.stats();
+        if hits < lookups {
+            tracing::warn!("{} of {} profiles have no cached embedding and would be embedded on the next run", lookups - hits, lookups);
+        }
+        return Ok(());
+    }
+
+    // Id lookup over the loaded records; only --similar needs embeddings
+    let mut similar_to = match &args.command {
+        Some(Command::Similar { customer_id, top_k }) => Some((customer_id.clone(), *top_k)),
+        _ => None,
+    };
+    if let Some(Command::FindCustomer { partial, limit, similar }) = &args.command {
+        let candidates = fuzzy::find_customers(&customers, partial, *limit);
+        if candidates.is_empty() {
+            return Err(anyhow::anyhow!("No customers loaded to search"));
+        }
+        println!("Customers matching '{}':", partial);
+        for (i, (distance, customer)) in candidates.iter().enumerate() {
+            println!("{}. {} (edit distance {}): {}", i + 1, customer.customer_id, distance, customer.profile_summary);
+        }
+        match similar {
+            Some(top_k) => similar_to = Some((candidates[0].1.customer_id.clone(), *top_k)),
+            None => return Ok(()),
+        }
+    }
+
+    let summary_cache = match &args.summary_cache {
+        Some(path) if args.no_cache => {
+            println!("Ignoring the summary cache at {}; every record will be re-embedded", path.display());
+            Some(SummaryCache::empty(path, &cache_identity, args.store_format))
+        }
+        Some(path) => Some(SummaryCache::load(path, &cache_identity, args.store_format)?),
+        None => None,
+    };
+
+    // A saved store from the same data and settings replaces the embedding
+    // step. Without --store-file it is kept in the default cache directory,
+    // named by that identity. Precomputed vectors need no cache, and a sample
+    // drawn without a seed differs on every run.
+    let use_default_cache = args.embeddings_file.is_none() && (args.target_store_size.is_none() || args.sample_seed.is_some());
+    let store_identity = if args.store_file.is_some() || use_default_cache {
+        let settings = format!(
+            "{:?}",
+            (
+                (&args.missing_income, &args.missing_satisfaction, &args.income_currency, args.income_scale),
+                (&args.since, &args.until, args.keep_duplicates, args.trend_summaries),
+                (&args.country, args.min_income, &args.loyalty_level),
+                (args.target_store_size, &args.sample_stratify_by, args.sample_seed),
+                (&args.numeric_features, args.numeric_weight, &args.zero_norm),
+                (
+                    load_options.normalization.as_ref().map(NormalizationMap::fingerprint),
+                    load_options.column_mapping.fingerprint(),
+                    args.embeddings_file.as_deref().map(store_file::file_sha256).transpose()?,
+                ),
+            )
+        );
+        Some(store_file::store_identity(&cache_identity, &data_source.content_sha256()?, &settings))
+    } else {
+        None
+    };
+    let store_path = match (&args.store_file, &store_identity) {
+        (Some(path), _) => Some(path.clone()),
+        (None, Some(identity)) => Some(args.cache_dir.join(format!("{}.store", identity))),
+        (None, None) => None,
+    };
+    let reuse_store = !args.rebuild_store && !args.no_cache;
+    // The default cache file is named by the identity, so one that exists matches
+    let cache_hit = args.store_file.is_none() && reuse_store && store_path.as_ref().is_some_and(|path| path.exists());
+
+    // Estimated embedding spend for the records not already in the summary
+    // cache, shown before any API call. A streamed file is only read ahead
+    // for this under --dry-run. Precomputed vectors and a run served from the
+    // embedding cache need no estimate.
+    let mut streamed_chunks = 0;
+    let estimate = match (args.embeddings_file.is_none() && !cache_hit, streaming) {
+        (false, _) => None,
+        (true, false) => {
+            let uncached = customers
+                .iter()
+                .filter(|c| !summary_cache.as_ref().is_some_and(|cache| cache.contains(&c.profile_summary)))
+                .take(args.target_store_size.unwrap_or(usize::MAX));
+            Some(metrics::estimate_cost(uncached, embedding_model_name, docs_per_request))
+        }
+        (true, true) if args.dry_run => {
+            let mut estimate = UsageLine::new(embedding_model_name);
+            for chunk in loader::StreamingChunks::open(data_path, &load_options, args.chunk_size, !args.keep_duplicates)? {
+                let chunk = chunk?;
+                streamed_chunks += 1;
+                let uncached = chunk
+                    .iter()
+                    .filter(|c| !summary_cache.as_ref().is_some_and(|cache| cache.contains(&c.profile_summary)));
+                estimate.add(&metrics::estimate_cost(uncached, embedding_model_name, docs_per_request));
+            }
+            Some(estimate)
+        }
+        (true, true) => None,
+    };
+    if let Some(estimate) = &estimate {
+        println!(
+            "Estimated embedding cost: ~{} tokens in ~{} requests to {}, {}",
+            estimate.input_tokens,
+            estimate.requests,
+            embedding_model_name,
+            match estimate.cost() {
+                Some(cost) => format!("~${:.4}", cost),
+                None => "unknown (no price on file for this model)".to_string(),
+            }
+        );
+    }
+    if args.dry_run {
+        let chunks = match streaming {
+            true => estimate.is_some().then_some(streamed_chunks),
+            false => Some(args.target_store_size.unwrap_or(usize::MAX).min(customers.len()).div_ceil(args.chunk_size)),
+        };
+        if let Some(chunks) = chunks {
+            println!("Chunks: {} of up to {} records", chunks, args.chunk_size);
+        }
+        if args.embeddings_file.is_some() {
+            println!("Vectors come from --embeddings-file; nothing would be embedded");
+        }
+        if let Some(path) = store_path.as_ref().filter(|path| path.exists() && reuse_store) {
+            println!("{} exists; if it matches this run's data and settings, nothing will be embedded", path.display());
+        }
+        println!("Dry run: no API calls made");
+        return Ok(());
+    }
+
+    // Initialize the provider's client
+    let endpoint = provider_config.endpoint(args.provider)?;
+    let client = ProviderClient::new(&endpoint);
+    // Profiles and queries are embedded with their own prefixes (empty for ada-002)
+    // and projected by the same matrix
+    let document_model = ProjectedEmbedding::new(
+        PrefixedEmbedding::new(client.embedding_model(embedding_model_name, EmbeddingRole::Document), &args.document_prefix),
+        projection.clone(),
+    );
+    let embedding_model = ProjectedEmbedding::new(
+        PrefixedEmbedding::new(client.embedding_model(embedding_model_name, EmbeddingRole::Query), &args.query_prefix),
+        projection.clone(),
+    );
+
+    if let Some(Command::Summarize { max_chunk_tokens, show_chunks }) = &args.command {
+        let summarizer = client.agent(analysis_model)
+            .preamble(summarize::SUMMARIZER_PREAMBLE)
+            .build();
+        let summary = summarize::summarize_dataset(&summarizer, &customers, *max_chunk_tokens).await?;
+        if *show_chunks {
+            for (i, chunk_summary) in summary.chunk_summaries.iter().enumerate() {
+                println!("\n=== Chunk {} Summary ===\n{}", i + 1, chunk_summary);
+            }
+        }
+        println!("\n=== Executive Summary ===\n{}", summary.final_summary);
+        return Ok(());
+    }
+
+    let run_manifest = match &args.run_manifest {
+        Some(path) => {
+            let input = run_manifest::InputInfo { location: data_path.to_string(), sha256: data_source.content_sha256()? };
+            let mut manifest = run_manifest::RunManifest::new(format!("{:?}", args), input, embedding_model_name, analysis_model);
+            manifest.records.loaded = (!streaming).then_some(customers.len());
+            let writer = run_manifest::RunManifestWriter::new(path.clone(), manifest);
+            writer.write_on_interrupt();
+            Some(writer)
+        }
+        None => None,
+    };
+
+    // Put records in sample order so the loop below can stop at the target
+    // size with a store drawn from the whole file
+    let sample_method = match args.sample_stratify_by {
+        Some(field) => sampling::SampleMethod::Stratified(field),
+        None => sampling::SampleMethod::Random,
+    };
+    let customers = match args.target_store_size {
+        Some(target) => {
+            let seed = args.sample_seed.unwrap_or_else(|| {
+                std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map(|elapsed| elapsed.as_nanos() as u64)
+                    .unwrap_or(0)
+            });
+            println!(
+                "Sampling up to {} of {} records ({}, seed {})",
+                target,
+                customers.len(),
+                sample_method,
+                seed
+            );
+            sampling::sample_order(customers, sample_method, seed)
+        }
+        None => customers,
+    };
+
+    let saved_store = match (&store_path, &store_identity) {
+        (Some(path), Some(identity)) if path.exists() && reuse_store => match store_file::load_store(path, identity) {
+            Ok(documents) => documents,
+            Err(e) if args.store_file.is_some() => return Err(e),
+            // An unreadable default cache entry is rebuilt (and overwritten) like a miss
+            Err(e) => {
+                tracing::warn!("Ignoring unreadable embedding cache: {}", describe_error(e, verbose_errors));
+                None
+            }
+        },
+        _ => None,
+    };
+    if let (Some(documents), Some(path)) = (&saved_store, &store_path) {
+        println!("Loaded {} embedded profiles from {}; skipping embedding", documents.len(), path.display());
+    }
 
-    println!("Loaded {} customer records", customers.len());
-    
     // Process in chunks
-    let chunks: Vec<Vec<CustomerFeedback>> = customers
-        .chunks(CHUNK_SIZE)
-        .map(|chunk| chunk.to_vec())
-        .collect();
+    let chunks: Box<dyn Iterator<Item = Result<Vec<CustomerFeedback>, anyhow::Error>> + '_> = if saved_store.is_some() {
+        Box::new(std::iter::empty())
+    } else if streaming {
+        println!("Streaming records from {} in chunks of size {}", data_path, args.chunk_size);
+        Box::new(loader::StreamingChunks::open(data_path, &load_options, args.chunk_size, !args.keep_duplicates)?)
+    } else {
+        println!("Split into {} chunks of size {}", customers.len().div_ceil(args.chunk_size), args.chunk_size);
+        Box::new(customers.chunks(args.chunk_size).map(|chunk| Ok(chunk.to_vec())))
+    };
+    let total_chunks = (!streaming).then(|| customers.len().div_ceil(args.chunk_size));
 
-    println!("Split into {} chunks of size {}", chunks.len(), CHUNK_SIZE);
+    let mut metrics = RunMetrics::new(embedding_model_name, analysis_model);
+    let embed_options = EmbedOptions {
+        batch_size: embed_batch_size,
+        missing: args.missing_embeddings,
+        retry: RetryPolicy {
+            max_retries: provider_config.embedding.retries(args.embedding_max_retries),
+            base_delay: EMBEDDING_DELAY,
+            timeout: provider_config.embedding.timeout(),
+        },
+        max_in_flight: provider_config.embedding.max_in_flight(),
+        delay: provider_config.embedding.delay(EMBEDDING_DELAY),
+    };
+
+    // Precomputed vectors replace the embedding step entirely
+    let mut external_embeddings = match &args.embeddings_file {
+        Some(path) if saved_store.is_none() => {
+            // Files hold full-size vectors; they are projected as they are attached
+            let dims = projection.as_ref().map_or_else(|| embedding_model.ndims(), |projection| projection.input_dims());
+            let external = external::ExternalEmbeddings::load(path, dims)?;
+            println!("Loaded {} precomputed embeddings ({} dimensions) from {}", external.len(), external.dims(), path.display());
+            Some(external)
+        }
+        _ => None,
+    };
 
-    // Process all chunks
+    // Process all chunks, up to --chunk-concurrency at a time, stopping early if
+    // the embedding budget runs out or the store reaches --target-store-size.
+    // Chunks finish in any order, which the store doesn't care about.
+    let mut budget = EmbeddingBudget::new(args.max_embedding_calls, args.max_embedding_tokens);
+    let mut unprocessed_records = 0;
+    let mut failed_chunks = 0;
+    let mut dropped_records = 0;
     let mut all_embeddings = Vec::new();
-    for (chunk_num, chunk) in chunks.into_iter().enumerate() {
-        match process_chunk(chunk, &embedding_model, chunk_num + 1).await {
-            Ok(embeddings) => all_embeddings.extend(embeddings),
-            Err(e) => {
-                eprintln!("Error processing chunk {}: {}", chunk_num + 1, e);
+    let mut chunks = chunks.into_iter().enumerate();
+    let mut unsampled_records = 0;
+    let mut in_flight = stream::FuturesUnordered::new();
+    let mut in_flight_records = 0;
+    let mut exhausted = false;
+    let chunk_bar = progress::chunk_bar(total_chunks);
+    loop {
+        chunk_bar.set_message(format!("{} records embedded", all_embeddings.len()));
+        while !exhausted && in_flight.len() < args.chunk_concurrency {
+            // Records in flight count toward the target until they fail
+            let remaining = args
+                .target_store_size
+                .map(|target| target.saturating_sub(all_embeddings.len() + in_flight_records));
+            if remaining == Some(0) {
+                if in_flight.is_empty() {
+                    unsampled_records += chunks.by_ref().map(|(_, c)| c.map(|c| c.len()).unwrap_or(0)).sum::<usize>();
+                    exhausted = true;
+                }
+                break;
+            }
+            let Some((chunk_num, chunk)) = chunks.next() else {
+                exhausted = true;
+                break;
+            };
+            let mut chunk = chunk?;
+            // Only embed what is still needed; failures are made up from later chunks
+            if let Some(remaining) = remaining.filter(|remaining| chunk.len() > *remaining) {
+                unsampled_records += chunk.len() - remaining;
+                chunk.truncate(remaining);
+            }
+            if let Some(external) = &mut external_embeddings {
+                let mut attached = external.attach(chunk)?;
+                if let Some(projection) = &projection {
+                    for (_, embeddings) in &mut attached {
+                        for embedding in embeddings.iter_mut() {
+                            embedding.vec = projection.project(&embedding.vec);
+                        }
+                    }
+                }
+                all_embeddings.extend(attached);
+                chunk_bar.inc(1);
                 continue;
             }
+            if !budget.try_reserve(&chunk, docs_per_request) {
+                unprocessed_records = chunk.len()
+                    + chunks.by_ref().map(|(_, c)| c.map(|c| c.len()).unwrap_or(0)).sum::<usize>();
+                exhausted = true;
+                break;
+            }
+            let records = chunk.len();
+            in_flight_records += records;
+            let (document_model, summary_cache, embed_options) = (&document_model, summary_cache.as_ref(), &embed_options);
+            in_flight.push(async move {
+                let mut chunk_metrics = RunMetrics::new(embedding_model_name, analysis_model);
+                let result = process_chunk(chunk, document_model, chunk_num + 1, summary_cache, &mut chunk_metrics, embed_options).await;
+                (chunk_num, records, chunk_metrics, result)
+            });
+        }
+
+        let Some((chunk_num, records, chunk_metrics, result)) = in_flight.next().await else {
+            break;
+        };
+        in_flight_records -= records;
+        metrics.embedding.add(&chunk_metrics.embedding);
+        chunk_bar.inc(1);
+        match result {
+            Ok((embeddings, dropped)) => {
+                all_embeddings.extend(embeddings);
+                dropped_records += dropped;
+            }
+            Err(e) => {
+                progress::eprintln(format!("Error processing chunk {}: {}", chunk_num + 1, describe_error(e, verbose_errors)));
+                failed_chunks += 1;
+            }
         }
     }
+    chunk_bar.finish_and_clear();
 
-    println!("Generated {} embeddings with dimension {}", 
-        all_embeddings.len(),
-        all_embeddings.first().map(|(_, e)| e.first().vec.len()).unwrap_or(0)
-    );
+    if let Some(external) = &external_embeddings {
+        if external.unused() > 0 {
+            tracing::warn!("{} vectors in the embeddings file match no loaded customer", external.unused());
+        }
+    }
+
+    // Every embedded profile now lives in `all_embeddings`
+    drop(chunks);
+    drop(customers);
+    // A saved store already had the zero-norm policy and numeric features applied
+    let loaded_store = saved_store.is_some();
+    let all_embeddings = match saved_store {
+        Some(documents) => documents,
+        None => {
+            let all_embeddings = degenerate::apply_zero_norm_policy(all_embeddings, args.zero_norm);
+            let all_embeddings = match args.numeric_features {
+                Some(normalization) => {
+                    let customers: Vec<&CustomerFeedback> = all_embeddings.iter().map(|(c, _)| c).collect();
+                    let scaler = features::FeatureScaler::fit(&customers, normalization, args.numeric_weight);
+                    println!("Appending {} numeric feature components ({:?}, weight {})", scaler.dims(), normalization, args.numeric_weight);
+                    scaler.extend(all_embeddings)
+                }
+                None => all_embeddings,
+            };
+            println!("Generated {} embeddings with dimension {}",
+                all_embeddings.len(),
+                all_embeddings.first().map(|(_, e)| e.first().vec.len()).unwrap_or(0)
+            );
+            all_embeddings
+        }
+    };
+    let dataset_stats = match dataset_stats {
+        None if streaming && !args.no_dataset_stats => Some(stats::stats_of(all_embeddings.iter().map(|(c, _)| c))),
+        stats => stats,
+    };
+
+    if let (Some(cache), false) = (&summary_cache, loaded_store) {
+        let (hits, lookups) = cache.stats();
+        println!(
+            "Summary cache: {} of {} summaries served from cache ({:.1}% hit rate)",
+            hits,
+            lookups,
+            if lookups > 0 { hits as f64 / lookups as f64 * 100.0 } else { 0.0 }
+        );
+        cache.save()?;
+    }
+
+    // Querying an empty store would only send context-free prompts to the agent
+    let dims = all_embeddings.first().map(|(_, e)| e.first().vec.len()).unwrap_or(0);
+    if all_embeddings.is_empty() || dims == 0 {
+        let cause = if failed_chunks > 0 {
+            format!("{} chunks failed to embed; see the errors above", failed_chunks)
+        } else if unprocessed_records > 0 {
+            "the embedding budget ran out before the first chunk".to_string()
+        } else if all_embeddings.is_empty() {
+            "no records were loaded or every embedding was dropped".to_string()
+        } else {
+            "the embedding model returned zero-dimension vectors".to_string()
+        };
+        return Err(anyhow::anyhow!("The vector store has no usable profiles ({}); not running any queries", cause));
+    }
+
+    if let Some(run_manifest) = &run_manifest {
+        run_manifest.update(|manifest| {
+            manifest.records.embedded = all_embeddings.len();
+            manifest.records.not_embedded = unprocessed_records + unsampled_records;
+            manifest.embedding_dimensions = dims;
+            manifest.record_usage(&metrics);
+        });
+    }
+
+    if let Some(target) = args.target_store_size {
+        let strata = match sample_method {
+            sampling::SampleMethod::Stratified(field) => format!(
+                "; {}",
+                sampling::strata_counts(all_embeddings.iter().map(|(c, _)| c), field)
+                    .iter()
+                    .map(|(value, count)| format!("{} {}", value, count))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            sampling::SampleMethod::Random => String::new(),
+        };
+        println!(
+            "Sampled store: {} of {} target profiles ({}), {} records not embedded{}",
+            all_embeddings.len(),
+            target,
+            sample_method,
+            unsampled_records,
+            strata
+        );
+    }
+
+    if unprocessed_records > 0 {
+        println!(
+            "Warning: run truncated by embedding budget after {} API calls (~{} tokens); {} records were not embedded",
+            budget.calls_used(),
+            budget.tokens_used(),
+            unprocessed_records
+        );
+    }
+
+    let mut post_processors = postprocess::PostProcessChain::default();
+    if let Some(format) = args.analysis_format {
+        post_processors.push(format.enforcer());
+    }
+    if !args.post_process.is_empty() {
+        let customer_ids: HashSet<String> = all_embeddings.iter().map(|(c, _)| c.customer_id.clone()).collect();
+        for kind in &args.post_process {
+            post_processors.push(postprocess::builtin(*kind, &customer_ids));
+        }
+    }
+
+    let clustering = args.cluster.map(|k| {
+        let clustering = cluster::Clustering::fit(&all_embeddings, k);
+        println!("Clustered profiles into {} clusters with sizes {:?}", clustering.sizes().len(), clustering.sizes());
+        clustering
+    });
+
+    let centrality = args.knn_centrality.map(|k| {
+        let centrality = centrality::Centrality::compute(&all_embeddings, k);
+        let central = centrality.most_central(5);
+        println!(
+            "Computed the {}-nearest-neighbor graph over {} profiles; most central: {}",
+            centrality.k(),
+            all_embeddings.len(),
+            central.iter().map(|(id, degree)| format!("{} ({})", id, degree)).collect::<Vec<_>>().join(", ")
+        );
+        Arc::new(centrality)
+    });
+
+    // Unsupervised segmentation: cluster, then let the agent name each cluster
+    if let Some(Command::DiscoverSegments { k, sample }) = &args.command {
+        let clustering = cluster::Clustering::fit(&all_embeddings, *k);
+        let labeler = client.agent(analysis_model)
+            .preamble(segments::SEGMENT_PREAMBLE)
+            .build();
+        let redaction = Redaction::new(args.redact_fields.clone(), args.redact_mode);
+        let segments = segments::label_segments(&labeler, &all_embeddings, &clustering, *sample, &redaction).await;
+        let empty = clustering.sizes().iter().filter(|&&size| size == 0).count();
+        println!("Found {} segments{}", segments.len(), if empty > 0 { format!(" ({} empty clusters skipped)", empty) } else { String::new() });
+        print!("{}", segments::report(&segments, all_embeddings.len()));
+        return Ok(());
+    }
+
+    if let Some(projection) = &projection {
+        let vectors: usize = all_embeddings.iter().map(|(_, embeddings)| embeddings.len()).sum();
+        let saved = vectors * (projection.input_dims() - projection.output_dims()) * std::mem::size_of::<f64>();
+        println!(
+            "Embeddings reduced from {} to {} dimensions: about {:.1} MiB saved across {} vectors",
+            projection.input_dims(),
+            projection.output_dims(),
+            saved as f64 / (1024.0 * 1024.0),
+            vectors
+        );
+    }
+
+    let newest_date = all_embeddings.iter().filter_map(|(c, _)| c.parsed_date()).max();
+
+    // A store missing records that failed, were dropped without a vector or
+    // ran out of budget would be reused as if it were complete
+    if let (Some(path), Some(identity), false) = (&store_path, &store_identity, loaded_store) {
+        if failed_chunks > 0 || dropped_records > 0 || unprocessed_records > 0 {
+            tracing::warn!("Not saving {}: some records were not embedded", path.display());
+        } else if args.store_file.is_some() {
+            store_file::save_store(&all_embeddings, path, identity, args.store_format)?;
+            println!("Saved {} embedded profiles to {}", all_embeddings.len(), path.display());
+        } else {
+            // The default cache is a convenience; failing to write it doesn't fail the run
+            let saved = std::fs::create_dir_all(&args.cache_dir)
+                .map_err(anyhow::Error::from)
+                .and_then(|_| store_file::save_store(&all_embeddings, path, identity, args.store_format));
+            match saved {
+                Ok(()) => println!("Cached {} embedded profiles in {}", all_embeddings.len(), path.display()),
+                Err(e) => tracing::warn!("Could not write the embedding cache: {}", describe_error(e, verbose_errors)),
+            }
+        }
+    }
 
     // Create vector store with embeddings
-    let vector_store = InMemoryVectorStore::from_documents(all_embeddings);
-    let index = vector_store.index(embedding_model);
-
-    // Create the analysis agent
-    let agent = openai_client.agent("gpt-4")
-        .preamble(r#"
-            You are an expert customer insights analyst. You will be provided with:
-            1. A specific analysis query
-            2. Several relevant customer profiles with detailed metrics including:
-               - Demographics (age, gender, country)
-               - Income level
-               - Product and service quality ratings
-               - Purchase frequency
-               - Feedback scores
-               - Loyalty level
-               - Satisfaction scores
-
-            Analyze the provided profiles in relation to the query and provide:
-            1. Key behavioral patterns and trends from the specific profiles shown
-            2. Risk factors or concerns based on the actual data
-            3. Specific, actionable recommendations
-            4. Opportunities for improving customer satisfaction
-
-            Always reference specific data points from the provided profiles to support your analysis.
-            Be concise but insightful.
-        "#)
-        .build();
+    let index = tracing::info_span!("build_store", record_count = all_embeddings.len()).in_scope(|| {
+        InMemoryVectorStore::from_documents(all_embeddings).index(embedding_model.clone())
+    });
+
+    let scores = score_display::ScoreFormatter {
+        display: args.score_display,
+        transform: args.percent_transform,
+        midpoint: args.low_confidence_below,
+    };
+
+    if let Some(Command::RawSearch { query, top_k }) = &args.command {
+        for (score, vector) in search::raw_search(&index, &embedding_model, query, *top_k).await? {
+            println!("{}", serde_json::json!({ "score": score, "vector": vector }));
+        }
+        return Ok(());
+    }
+
+    if let Some((customer_id, top_k)) = &similar_to {
+        let neighbors = search::similar_customers(&index, customer_id, *top_k)?;
+        println!("Customers most similar to {}:", customer_id);
+        for (i, (score, profile)) in neighbors.iter().enumerate() {
+            println!("{}. {} (similarity {}): {}", i + 1, profile.customer_id, scores.format(*score), profile.profile_summary);
+        }
+        return Ok(());
+    }
+
+    if let Some(Command::SimilarityMatrix { customers, filters, output, max_customers }) = &args.command {
+        let subset = matrix::select(&index, customers, filters, *max_customers)?;
+        let csv_options = export::CsvExportOptions { bom: args.csv_bom, line_ending: args.csv_line_ending };
+        let cohesion = matrix::write(output, &subset, &csv_options)?;
+        println!("Wrote the {}x{} similarity matrix to {}", subset.len(), subset.len(), output.display());
+        if let Some((mean, min)) = cohesion {
+            println!("Pairwise similarity: mean {:.4}, minimum {:.4}", mean, min);
+        }
+        return Ok(());
+    }
+
+    // Create the analysis agent, plus an optional cheaper fallback. Preamble
+    // variants get the same additions and fallback.
+    let full_preamble = |base: &str| {
+        let mut preamble = base.to_string();
+        if args.hedge_low_confidence {
+            preamble.push_str(confidence::HEDGE_INSTRUCTIONS);
+        }
+        if let Some(format) = args.analysis_format {
+            preamble.push_str(format.directive());
+        }
+        if let Some(stats) = &dataset_stats {
+            preamble.push_str(&stats.preamble_section(&loyalty_order));
+        }
+        if args.structured {
+            preamble.push_str(STRUCTURED_INSTRUCTIONS);
+        }
+        preamble
+    };
+    let retry_policy = RetryPolicy {
+        max_retries: provider_config.completion.retries(args.agent_max_retries),
+        base_delay: Duration::from_secs(1),
+        timeout: provider_config.completion.timeout(),
+    };
+    let analyst = |preamble: &str| {
+        // The extractor keeps its own submit instructions and appends the preamble
+        let build = |model: &str| -> retry::Analyst<ProviderCompletion> {
+            if args.structured {
+                client.extractor::<AnalysisResult>(model).preamble(preamble).build().into()
+            } else {
+                client.agent(model).preamble(preamble).build().into()
+            }
+        };
+        let mut analysis_op = RetryingPrompt::new(analysis_model, build(analysis_model), retry_policy);
+        if let Some(fallback_model) = &args.fallback_model {
+            analysis_op = analysis_op.with_fallback(fallback_model, build(fallback_model), args.fallback_after);
+        }
+        analysis_op
+    };
+    let preamble = full_preamble(ANALYST_PREAMBLE);
+    let analysis_op = analyst(&preamble);
+    let streaming_op = match args.stream {
+        true => Some(streaming::StreamingPrompt::new(&endpoint, analysis_model, &preamble, retry_policy)),
+        false => None,
+    };
+
+    // Per-customer analysis written back as a CSV column
+    if let Some(Command::Enrich { output, instruction, customers, limit, neighbors }) = &args.command {
+        let analyst = RetryingPrompt::new(
+            analysis_model,
+            client.agent(analysis_model).preamble(enrich::ENRICH_PREAMBLE).build(),
+            retry_policy,
+        );
+        let mut selected: Vec<&CustomerFeedback> = index
+            .iter()
+            .map(|(_, (doc, _))| doc)
+            .filter(|doc| customers.is_empty() || customers.contains(&doc.customer_id))
+            .collect();
+        selected.sort();
+        if let Some(unknown) = customers.iter().find(|id| !selected.iter().any(|doc| &doc.customer_id == *id)) {
+            return Err(anyhow::anyhow!("Customer {} is not in the vector store", unknown));
+        }
+        selected.truncate(limit.unwrap_or(usize::MAX));
+        let redaction = Redaction::new(args.redact_fields.clone(), args.redact_mode);
+        let csv_options = export::CsvExportOptions { bom: args.csv_bom, line_ending: args.csv_line_ending };
+        let options = enrich::EnrichOptions {
+            instruction: instruction.as_deref().unwrap_or(enrich::DEFAULT_INSTRUCTION),
+            neighbors: *neighbors,
+            max_in_flight: provider_config.completion.max_in_flight(),
+            redaction: &redaction,
+            csv: &csv_options,
+        };
+        let (written, skipped, failed) = enrich::enrich(&analyst, &index, &selected, output, &options).await?;
+        if skipped > 0 {
+            println!("Skipped {} customers already in {}", skipped, output.display());
+        }
+        println!("Wrote {} enriched rows to {}", written, output.display());
+        if failed > 0 {
+            println!("{} customers failed; rerun the same command to retry them", failed);
+        }
+        return Ok(());
+    }
+
+    // Semantic lookup with optional score adjustments applied before top-k selection
+    let field_matcher = (!args.match_bonus.is_empty()).then(|| {
+        let stored: Vec<&CustomerFeedback> = index.iter().map(|(_, (doc, _))| doc).collect();
+        field_match::FieldMatcher::new(&args.match_bonus, &stored)
+    });
+    if let Some(filter) = &filter {
+        let passing = index.iter().filter(|(_, (doc, _))| filter.matches(doc)).count();
+        match passing {
+            0 => tracing::warn!("No stored profiles match --filter {}; every query will retrieve nothing", filter),
+            _ => println!("Filter {} keeps {} of {} profiles", filter, passing, index.iter().count()),
+        }
+    }
+    let mut retriever = search::Retriever::new(index, embedding_model.clone(), args.top_k);
+    if let Some(matcher) = field_matcher {
+        retriever = retriever.with_field_matcher(matcher);
+    }
+    if let Some(filter) = filter {
+        retriever = retriever.with_filter(filter);
+    }
+    for kind in &args.rescore {
+        retriever = retriever.with_adjustment(format!("{:?} rules", kind), rules::rule_set(*kind));
+    }
+    if let (Some(centrality), Some(weight)) = (&centrality, args.centrality_boost) {
+        retriever = retriever.with_adjustment(
+            format!("centrality boost up to {} (k = {})", weight, centrality.k()),
+            centrality.clone().boost(weight),
+        );
+    }
+    if let Some(attempts) = args.retry_empty {
+        let broadener = broaden::llm_broadener(
+            client.agent(analysis_model)
+                .preamble(broaden::BROADENER_PREAMBLE)
+                .build(),
+        );
+        retriever = retriever.with_empty_retry(broadener, attempts, args.retry_empty_below);
+    }
+    if let Some(field) = args.stratify_by {
+        retriever = retriever.with_stratification(field, args.stratify_within);
+    }
+    if let Some(threshold) = args.min_score {
+        retriever = retriever.with_min_score(threshold);
+    }
+    if let Some(half_life_days) = args.recency_half_life_days {
+        match newest_date {
+            Some(reference) => {
+                println!("Weighting similarity by recency (half-life {} days, relative to {})", half_life_days, reference);
+                retriever = retriever.with_adjustment(
+                    format!("recency weighting, half-life {} days", half_life_days),
+                    search::recency_weight(reference, half_life_days),
+                );
+            }
+            None => println!("No dated records found; --recency-half-life-days has no effect"),
+        }
+    }
+
+    let aspect_log = args.aspects.map(|strategy| {
+        let splitter = match strategy {
+            aspects::AspectStrategy::Semicolon => aspects::semicolon_splitter(),
+            aspects::AspectStrategy::Llm => aspects::llm_splitter(
+                client.agent(analysis_model)
+                    .preamble(aspects::DECOMPOSER_PREAMBLE)
+                    .build(),
+            ),
+        };
+        retriever.with_aspects(splitter)
+    });
+    let retrieval_cache = (!args.no_retrieval_cache).then(|| retriever.with_cache(args.retrieval_cache_size));
+
+    if args.explain_pipeline {
+        println!("Pipeline:");
+        println!("1. parallel");
+        println!("   a. passthrough: the query text");
+        println!("   b. lookup (embedding model {}):", embedding_model_name);
+        for step in retriever.describe() {
+            println!("      - {}", step);
+        }
+        println!(
+            "2. map: format profiles as {:?}, within {} context tokens{}{}",
+            args.prompt_format,
+            args.max_context_tokens,
+            if args.redact_fields.is_empty() {
+                String::new()
+            } else {
+                format!(", redacting {:?} ({:?})", args.redact_fields, args.redact_mode)
+            },
+            if args.annotate_confidence || args.hedge_low_confidence {
+                format!(", confidence threshold {}", args.low_confidence_below)
+            } else {
+                String::new()
+            }
+        );
+        println!(
+            "3. prompt: {}{}",
+            streaming_op.as_ref().map_or_else(|| analysis_op.describe(), |op| op.describe()),
+            if args.hedge_low_confidence { ", hedging on low confidence" } else { "" }
+        );
+        let steps: Vec<String> = args
+            .analysis_format
+            .map(|format| format!("enforce {:?} format", format))
+            .into_iter()
+            .chain(args.post_process.iter().map(|kind| format!("{:?}", kind)))
+            .collect();
+        if !steps.is_empty() {
+            println!("4. post-process: {}", steps.join(", "));
+        }
+    }
 
     // Build the analysis pipeline
-    let chain = pipeline::new()
+    let analysis_op = &analysis_op;
+    let streaming_op = streaming_op.as_ref();
+    let prompt_options = PromptOptions {
+        max_context_tokens: args.max_context_tokens,
+        redaction: Redaction::new(args.redact_fields.clone(), args.redact_mode),
+        format: args.prompt_format,
+        confidence: (args.annotate_confidence || args.hedge_low_confidence)
+            .then_some((args.low_confidence_below, args.top_k)),
+        hedge_low_confidence: args.hedge_low_confidence,
+    };
+    let prompt_options = &prompt_options;
+    let retrieval = pipeline::new()
         .chain(parallel!(
-            passthrough::<&str>(),
-            lookup::<_, _, CustomerFeedback>(index, 5),
+            passthrough::<String>(),
+            retriever,
         ))
-        .map(|(query, maybe_profiles)| match maybe_profiles {
-            Ok(profiles) => {
-                if profiles.is_empty() {
-                    format!("Analysis Query: {}\n\nWarning: No relevant customer profiles found.", query)
-                } else {
-                    format!(
-                        "Analysis Query: {}\n\nRelevant Customer Profiles ({} found):\n{}",
-                        query,
-                        profiles.len(),
-                        profiles.into_iter()
-                            .enumerate()
-                            .map(|(i, (score, _, profile))| format!(
-                                "Profile {}:\n* Similarity Score: {:.3}\n* Customer ID: {}\n* Demographics: {} year old {} from {}\n* Income: ${:.2}\n* Satisfaction: {:.1}%\n* Loyalty Level: {}\n* Purchase Frequency: {} purchases/year\n* Product Quality: {}/10\n* Service Quality: {}/10\n* Feedback Score: {}\n",
-                                i + 1,
-                                score,
-                                profile.customer_id,
-                                profile.age,
-                                profile.gender,
-                                profile.country,
-                                profile.income,
-                                profile.satisfaction_score,
-                                profile.loyalty_level,
-                                profile.purchase_frequency,
-                                profile.product_quality,
-                                profile.service_quality,
-                                profile.feedback_score
-                            ))
-                            .collect::<String>()
-                    )
+        .map(move |(query, maybe_profiles)| {
+            let request = prompt::build_analysis_request(&query, maybe_profiles, prompt_options);
+            (query, request)
+        });
+    let retrieval = &retrieval;
+    let chain = retrieval
+        // `Op::then` infers the wrong input type in this rig version, so chain the free fn
+        .chain(pipeline::then(move |(query, request): (String, Result<AnalysisRequest, PipelineError>)| async move {
+            // A failed lookup ends the query here, before anything is sent to the model
+            let request = match request {
+                Ok(request) => request,
+                Err(err) => return (AnalysisRequest::unsent(query), Err(err)),
+            };
+            let response = match streaming_op {
+                // The header goes out first so the streamed text lands under it
+                Some(op) => {
+                    println!("\n=== Query: {} ===\n\nAnalysis:", request.query);
+                    op.call(&request.prompt).await
                 }
-            },
-            Err(err) => {
-                eprintln!("Error retrieving similar profiles: {}", err);
-                format!("Analysis Query: {}\n\nError: Failed to retrieve relevant customer profiles.", query)
-            }
-        })
-        .prompt(agent);
+                None => analysis_op.call(request.prompt.clone()).await,
+            };
+            (request, response.map_err(PipelineError::from))
+        }));
 
     // Example queries to test the pipeline
-    let example_queries = vec![
+    let example_queries = [
         "What patterns do you see in high-income customers with low satisfaction scores?",
         "Analyze the relationship between purchase frequency and loyalty levels.",
         "What characteristics define our most satisfied customers?",
         "Identify potential churn risks based on customer patterns.",
         "Find patterns in service quality ratings across different countries.",
     ];
+    // Without a subcommand, queries are typed at a prompt unless a batch is given
+    let interactive = args.command.is_none() && !args.batch && file_queries.is_none();
+    let mut queries: Vec<String> = match (&args.command, file_queries) {
+        (Some(Command::Query { text }), _) => vec![text.clone()],
+        (_, Some(queries)) => queries,
+        _ if interactive => Vec::new(),
+        _ => example_queries.iter().map(|query| query.to_string()).collect(),
+    };
+    let serving = matches!(args.command, Some(Command::Daemon { action: DaemonAction::Serve }));
+    if serving && args.stream {
+        return Err(anyhow::anyhow!("--stream prints to the terminal and cannot be used with the daemon"));
+    }
+
+    // Same queries and retrievals under each preamble, answers grouped by query
+    if !args.preamble_variants.is_empty() {
+        if serving || interactive {
+            return Err(anyhow::anyhow!(
+                "--preamble-variant runs a batch experiment and cannot be used with the daemon or interactive mode; add --batch or --queries-file"
+            ));
+        }
+        let mut variants = Vec::new();
+        for variant in &args.preamble_variants {
+            if variants.iter().any(|v: &variants::Variant<_>| v.name == variant.name) {
+                return Err(anyhow::anyhow!("Two preamble variants are named {}", variant.name));
+            }
+            let preamble = full_preamble(&variant.read(ANALYST_PREAMBLE)?);
+            variants.push(variants::Variant { name: variant.name.clone(), analyst: analyst(&preamble), preamble });
+        }
+        let usage = variants::run_experiment(
+            retrieval,
+            &variants,
+            &queries,
+            analysis_model,
+            &post_processors,
+            provider_config.completion.max_in_flight(),
+            verbose_errors,
+        )
+        .await?;
+        println!("\n=== Usage by variant ===\n{}", variants::usage_table(&variants, &usage));
+        return Ok(());
+    }
+
+    let mut transcript = match &args.transcript_file {
+        Some(path) => Some(TranscriptWriter::open(path)?),
+        None => None,
+    };
+    let fingerprint = report::queries_fingerprint(&queries);
+    let mut report = match (&args.continue_from, &args.report_file) {
+        (Some(path), _) => {
+            let (writer, done) = report::ReportWriter::resume(path, fingerprint)?;
+            let total = queries.len();
+            queries.retain(|query| !done.contains(query));
+            println!(
+                "Resuming {}: {} of {} queries already have results, running the remaining {}",
+                path.display(),
+                total - queries.len(),
+                total,
+                queries.len()
+            );
+            Some(writer)
+        }
+        (None, Some(path)) => Some(report::ReportWriter::create(path, args.report_format, fingerprint)?),
+        (None, None) => None,
+    }
+    .map(|writer| writer.with_score_display(scores));
+    let csv_options = export::CsvExportOptions { bom: args.csv_bom, line_ending: args.csv_line_ending };
+    let mut profile_export = match &args.export_profiles {
+        Some(path) => Some(export::ProfileExport::create(path, &csv_options)?.with_score_display(scores)),
+        None => None,
+    };
+
+    // A cold first request pays for connection setup and empty caches, so the
+    // daemon takes that hit before it accepts traffic
+    if serving && !args.no_warmup {
+        let warmup_queries = match args.warmup_queries.as_slice() {
+            [] => vec![example_queries[0].to_string()],
+            configured => configured.to_vec(),
+        };
+        let warmup_started = Instant::now();
+        for query in warmup_queries {
+            let started = Instant::now();
+            match chain.call(query.clone()).await {
+                (_, Ok(_)) => println!("Warmup query '{}' took {:.2?}", query, started.elapsed()),
+                (_, Err(e)) => tracing::warn!("Warmup query '{}' failed: {}", query, describe_error(e, verbose_errors)),
+            }
+        }
+        println!("Warmup finished in {:.2?}", warmup_started.elapsed());
+    }
+
+    let mut sheet_export = match &args.export_sheet {
+        Some(path) => Some(sheet::SheetExport::create(path, args.sheet_format, &csv_options)?.with_score_display(scores)),
+        None => None,
+    };
+
+    let mut query_scores: Vec<(String, Option<f64>)> = Vec::new();
+    let mut listener = if serving { Some(daemon::DaemonListener::bind()?) } else { None };
+    if listener.is_some() {
+        println!("Daemon listening on {} (pid {})", daemon::SOCKET_PATH, std::process::id());
+    }
+    let total_queries = queries.len();
+    if let Some(run_manifest) = &run_manifest {
+        run_manifest.update(|manifest| manifest.queries.planned = total_queries);
+    }
+    // Batch queries are analyzed up to `max_in_flight` at a time, in order;
+    // the ones ahead make progress whenever the loop waits for the next result
+    let chain = &chain;
+    let mut pending = stream::iter(queries)
+        .map(|query| async move {
+            let started_at = Utc::now();
+            let (request, result) = chain.call(query).await;
+            (started_at, request, result)
+        })
+        .buffered(if args.stream { 1 } else { provider_config.completion.max_in_flight() });
+    let query_delay = provider_config.completion.delay(QUERY_DELAY);
+    let mut stop = stop::StopTracker::new(stop::StopConditions {
+        on_error: args.stop_on_error,
+        low_confidence_streak: args.stop_after,
+        max_duration: args.max_duration,
+    });
+    let mut stopped_early = None;
+    let mut typed_queries = tokio::io::BufReader::new(tokio::io::stdin()).lines();
+    if interactive {
+        println!("\nEnter a query per line; an empty line or end of input exits.");
+    }
+    loop {
+        // Batch mode runs the listed queries; the daemon serves queries until stopped
+        let ((started_at, request, mut result), connection) = match &mut listener {
+            Some(listener) => match listener.next().await {
+                // SIGINT or SIGTERM between requests; the lifecycle files go with the listener
+                None => break,
+                Some((DaemonRequest::Query { query }, connection)) => {
+                    let started_at = Utc::now();
+                    let (request, result) = chain.call(query).await;
+                    ((started_at, request, result), Some(connection))
+                }
+                Some((DaemonRequest::Status, connection)) => {
+                    let status = format!(
+                        "running (pid {}, {} queries served, socket {})",
+                        std::process::id(),
+                        query_scores.len(),
+                        daemon::SOCKET_PATH
+                    );
+                    connection.respond(true, status).await;
+                    continue;
+                }
+                Some((DaemonRequest::Stop, connection)) => {
+                    connection.respond(true, "stopping".to_string()).await;
+                    break;
+                }
+            },
+            // A failed query is reported like any other and the prompt returns
+            None if interactive => {
+                print!("\nQuery> ");
+                std::io::Write::flush(&mut std::io::stdout())?;
+                match typed_queries.next_line().await? {
+                    Some(line) if !line.trim().is_empty() => {
+                        let started_at = Utc::now();
+                        let (request, result) = chain.call(line.trim().to_string()).await;
+                        ((started_at, request, result), None)
+                    }
+                    _ => break,
+                }
+            }
+            None => {
+                if let Some(reason) = stop.out_of_time() {
+                    stopped_early = Some(reason);
+                    break;
+                }
+                // Streamed text is its own progress indicator
+                let spinner = (!args.stream).then(|| {
+                    progress::query_spinner(format!("Analyzing query {} of {}", query_scores.len() + 1, total_queries))
+                });
+                let analysis = pending.next().await;
+                if let Some(spinner) = spinner {
+                    spinner.finish_and_clear();
+                }
+                match analysis {
+                    Some(analysis) => (analysis, None),
+                    None => break,
+                }
+            }
+        };
+
+        let mut out = String::new();
+        let mut ok = true;
+        let sent = result.as_ref().err().is_none_or(PipelineError::reached_model);
+        // A streamed analysis has already been printed under its query header
+        let mut streamed = args.stream && sent;
+        if !streamed {
+            writeln!(out, "\n=== Query: {} ===\n", request.query)?;
+        }
+        let mut refusal = result.as_ref().ok().and_then(|analysis| refusal::detect_refusal(analysis));
+        if let Some(reason) = refusal {
+            tracing::warn!("Analysis for '{}' looks like a non-answer ({})", request.query, reason);
+            if args.retry_refusals {
+                let retried = analysis_op.call(refusal::rephrase(&request.prompt)).await.map_err(PipelineError::from);
+                refusal = retried.as_ref().ok().and_then(|analysis| refusal::detect_refusal(analysis));
+                if refusal.is_none() {
+                    writeln!(out, "Rephrased prompt produced an analysis")?;
+                }
+                result = retried;
+                streamed = false;
+            }
+        }
+        if refusal.is_some() {
+            metrics.refused_queries.push(request.query.clone());
+        }
+        if let Some(transcript) = &mut transcript {
+            if let Err(e) = transcript.record(&request, &result, analysis_model, started_at) {
+                eprintln!("Error writing transcript: {}", describe_error(e, verbose_errors));
+            }
+        }
+        if let Some(profile_export) = &mut profile_export {
+            if let Err(e) = profile_export.record(&request) {
+                eprintln!("Error exporting profiles: {}", describe_error(e, verbose_errors));
+            }
+        }
+        writeln!(out, "Profiles sent to agent: {}", request.profiles.len())?;
+        query_scores.push((
+            request.query.clone(),
+            confidence::retrieval_score(&request.profiles, args.confidence_metric),
+        ));
+        if sent {
+            metrics.record_completion(
+                &format!("{}{}", preamble, request.prompt),
+                result.as_ref().ok().map(String::as_str),
+            );
+        }
+        if let Some(run_manifest) = &run_manifest {
+            run_manifest.update(|manifest| {
+                manifest.queries.completed = query_scores.len();
+                manifest.record_usage(&metrics);
+            });
+        }
+        if let (true, Some(confidence)) = (args.annotate_confidence, &request.confidence) {
+            writeln!(out, "Confidence: {}", confidence)?;
+        }
+        if let Some(min_gap) = args.min_score_gap {
+            let ambiguities = confidence::ambiguous_pairs(&request.profiles, &request.documents, min_gap);
+            for ambiguity in &ambiguities {
+                writeln!(
+                    out,
+                    "Ambiguous retrieval: ranks {} and {} differ by {:.4} but disagree on {}",
+                    ambiguity.rank,
+                    ambiguity.rank + 1,
+                    ambiguity.gap,
+                    ambiguity.differing.join(", ")
+                )?;
+            }
+            if !ambiguities.is_empty() {
+                metrics.ambiguous_queries.push(request.query.clone());
+            }
+        }
+        let outcome = match result {
+            Ok(analysis) => match refusal {
+                Some(reason) if streamed => {
+                    writeln!(out, "Analysis above was REFUSED: {}\n", reason)?;
+                    Ok(analysis)
+                }
+                Some(reason) => {
+                    writeln!(out, "Analysis (REFUSED: {}):\n{}\n", reason, analysis)?;
+                    Ok(analysis)
+                }
+                None => {
+                    let analysis = post_processors.apply(analysis);
+                    if !streamed {
+                        writeln!(out, "Analysis:\n{}\n", analysis)?;
+                    }
+                    if args.verify_citations {
+                        let check = citations::verify(&analysis, &request.documents);
+                        match check.ratio() {
+                            Some(ratio) => writeln!(
+                                out,
+                                "Citations: {} of {} figures found in the retrieved profiles ({:.0}%)",
+                                check.supported,
+                                check.cited,
+                                ratio * 100.0
+                            )?,
+                            None => writeln!(out, "Citations: the analysis cites no figures")?,
+                        }
+                        if !check.unsupported.is_empty() {
+                            writeln!(out, "Unsupported figures: {}", check.unsupported.join(", "))?;
+                        }
+                        metrics.citation_support.push((request.query.clone(), check.cited, check.supported));
+                    }
+                    Ok(analysis)
+                }
+            },
+            Err(e) => {
+                ok = false;
+                let message = describe_error(e, verbose_errors);
+                eprintln!("Error analyzing query: {}", message);
+                if connection.is_some() {
+                    writeln!(out, "Error analyzing query: {}", message)?;
+                }
+                Err(message)
+            }
+        };
+        if let Some(report) = &mut report {
+            if let Err(e) = report.record(&request, outcome.as_deref().map_err(String::as_str), refusal, started_at) {
+                eprintln!("Error writing report: {}", describe_error(e, verbose_errors));
+            }
+        }
+        if let Some(sheet_export) = &mut sheet_export {
+            if let Err(e) = sheet_export.record(&request, outcome.as_deref().map_err(String::as_str)) {
+                eprintln!("Error exporting sheet row: {}", describe_error(e, verbose_errors));
+            }
+        }
+        if let Some(clustering) = &clustering {
+            match embedding_model.embed_text(&request.query).await {
+                Ok(query_embedding) => {
+                    let query_cluster = clustering.nearest(&query_embedding.vec);
+                    let mut membership: Vec<(usize, usize)> = Vec::new();
+                    for profile in &request.profiles {
+                        if let Some(cluster) = clustering.cluster_of(&profile.customer_id) {
+                            match membership.iter_mut().find(|(c, _)| *c == cluster) {
+                                Some((_, count)) => *count += 1,
+                                None => membership.push((cluster, 1)),
+                            }
+                        }
+                    }
+                    membership.sort();
+                    writeln!(out, 
+                        "Query falls into cluster {} ({} profiles). Retrieved profiles by cluster: {}",
+                        query_cluster,
+                        clustering.sizes().get(query_cluster).copied().unwrap_or(0),
+                        membership
+                            .iter()
+                            .map(|(cluster, count)| format!("cluster {}: {}", cluster, count))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    )?;
+                }
+                Err(e) => eprintln!("Error embedding query for clustering: {}", describe_error(e, verbose_errors)),
+            }
+        }
+        if args.explain && !request.profiles.is_empty() {
+            let profiles: Vec<&CustomerFeedback> = request.documents.iter().collect();
+            match explain::explain_matches(&embedding_model, &document_model, &request.query, &profiles).await {
+                Ok(explanations) => {
+                    writeln!(out, "Explanation:")?;
+                    for (profile, contributions) in request.profiles.iter().zip(explanations) {
+                        let central = match centrality.as_ref().and_then(|c| c.in_degree(&profile.customer_id)) {
+                            Some(degree) => format!("; nearest neighbor of {} profiles", degree),
+                            None => String::new(),
+                        };
+                        writeln!(out, 
+                            "* {} (score {}): {}{}",
+                            profile.customer_id,
+                            scores.format(profile.score),
+                            explain::describe_contributions(&contributions),
+                            central
+                        )?;
+                    }
+                }
+                Err(e) => eprintln!("Error explaining matches: {}", describe_error(e, verbose_errors)),
+            }
+            if let Some(breakdown) = aspect_log.as_ref().and_then(|log| log.lock().unwrap().get(&request.query).cloned()) {
+                writeln!(out, "Aspects: {}", breakdown.aspects.iter().map(|a| format!("\"{}\"", a)).collect::<Vec<_>>().join(", "))?;
+                for profile in &request.profiles {
+                    let contributions = breakdown.contributions.get(&profile.customer_id).cloned().unwrap_or_default();
+                    writeln!(out, 
+                        "* {} (fused {:.4}): {}",
+                        profile.customer_id,
+                        profile.score,
+                        breakdown
+                            .aspects
+                            .iter()
+                            .zip(contributions)
+                            .map(|(aspect, (rank, contribution))| format!("\"{}\" rank {} (+{:.4})", aspect, rank, contribution))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    )?;
+                }
+            }
+        }
 
-    for query in example_queries {
-        println!("\n=== Query: {} ===\n", query);
-        match chain.call(query).await {
-            Ok(analysis) => println!("Analysis:\n{}\n", analysis),
-            Err(e) => eprintln!("Error analyzing query: {}", e),
+        match connection {
+            Some(connection) => connection.respond(ok, out).await,
+            None => {
+                print!("{}", out);
+                let low_confidence = query_scores
+                    .last()
+                    .and_then(|(_, score)| *score)
+                    .is_none_or(|score| score < args.low_confidence_below);
+                if let Some(reason) = stop.record(!ok, low_confidence) {
+                    stopped_early = Some(reason);
+                    break;
+                }
+                // Add a small delay between batch queries; a person typing paces
+                // interactive ones
+                if !interactive {
+                    sleep(query_delay).await;
+                }
+            }
         }
-        // Add a small delay between queries
-        sleep(Duration::from_secs(2)).await;
+    }
+
+    if let Some(report) = report {
+        if let Err(e) = report.finish() {
+            eprintln!("Error writing report: {}", describe_error(e, verbose_errors));
+        }
+    }
+
+    if let Some((hits, lookups)) = retrieval_cache.map(|cache| cache.stats()).filter(|(_, lookups)| *lookups > 0) {
+        println!(
+            "\nRetrieval cache: {} of {} lookups served from cache ({:.1}% hit rate)",
+            hits,
+            lookups,
+            hits as f64 / lookups as f64 * 100.0
+        );
+    }
+
+    if let Some(reason) = &stopped_early {
+        println!("\nStopped early after {} of {} queries: {}", query_scores.len(), total_queries, reason);
+    }
+
+    if !metrics.refused_queries.is_empty() {
+        println!(
+            "\n{} of {} queries got no usable analysis (refusal or non-answer): {}",
+            metrics.refused_queries.len(),
+            query_scores.len(),
+            metrics.refused_queries.join(" | ")
+        );
+    }
+
+    if args.min_score_gap.is_some() {
+        println!(
+            "\n{} of {} queries had ambiguous retrievals{}",
+            metrics.ambiguous_queries.len(),
+            query_scores.len(),
+            if metrics.ambiguous_queries.is_empty() {
+                String::new()
+            } else {
+                format!(": {}", metrics.ambiguous_queries.join(" | "))
+            }
+        );
+    }
+
+    if args.verify_citations && !metrics.citation_support.is_empty() {
+        println!("\n=== Citation support ===\n{}", metrics.citation_table());
+    }
+
+    if args.top_queries {
+        println!(
+            "\n=== Queries by retrieval confidence ({:?}, LOW below {}) ===\n{}",
+            args.confidence_metric,
+            args.low_confidence_below,
+            confidence::top_queries_table(&query_scores, args.low_confidence_below)
+        );
+    }
+
+    if args.explain_cost {
+        println!("\n=== Cost breakdown ===\n{}", metrics.cost_table());
+    }
+
+    if let Some(run_manifest) = &run_manifest {
+        run_manifest.update(|manifest| manifest.record_usage(&metrics));
+        run_manifest.write(run_manifest::RunStatus::Complete)?;
+        println!("\nWrote the run manifest to {}", run_manifest.path().display());
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rig::{
+        agent::AgentBuilder,
+        completion::{CompletionError, CompletionModel, CompletionRequest, CompletionResponse, ModelChoice},
+        embeddings::EmbeddingError,
+        Embed,
+    };
+    use std::sync::{Arc, Mutex};
+
+    // Deterministic embeddings derived from the text, so no API is needed
+    #[derive(Clone)]
+    struct MockEmbedding;
+
+    impl EmbeddingModel for MockEmbedding {
+        const MAX_DOCUMENTS: usize = 16;
+
+        fn ndims(&self) -> usize {
+            3
+        }
+
+        async fn embed_texts(
+            &self,
+            texts: impl IntoIterator<Item = String> + Send,
+        ) -> Result<Vec<Embedding>, EmbeddingError> {
+            Ok(texts
+                .into_iter()
+                .map(|text| {
+                    let vec = vec![
+                        text.len() as f64,
+                        text.matches('a').count() as f64 + 1.0,
+                        text.split_whitespace().count() as f64,
+                    ];
+                    Embedding { document: text, vec }
+                })
+                .collect())
+        }
+    }
+
+    // Like MockEmbedding, but drops the last text of any multi-text request,
+    // as a provider returning a partial response would
+    #[derive(Clone)]
+    struct ShortEmbedding;
+
+    impl EmbeddingModel for ShortEmbedding {
+        const MAX_DOCUMENTS: usize = 16;
+
+        fn ndims(&self) -> usize {
+            3
+        }
+
+        async fn embed_texts(
+            &self,
+            texts: impl IntoIterator<Item = String> + Send,
+        ) -> Result<Vec<Embedding>, EmbeddingError> {
+            let mut embeddings = MockEmbedding.embed_texts(texts).await?;
+            if embeddings.len() > 1 {
+                embeddings.pop();
+            }
+            Ok(embeddings)
+        }
+    }
+
+    // Like a rig provider client given a short response: any request for a
+    // text containing "unembeddable" fails with OpenAI's length mismatch error
+    #[derive(Clone)]
+    struct RejectingEmbedding;
+
+    impl EmbeddingModel for RejectingEmbedding {
+        const MAX_DOCUMENTS: usize = 16;
+
+        fn ndims(&self) -> usize {
+            3
+        }
+
+        async fn embed_texts(
+            &self,
+            texts: impl IntoIterator<Item = String> + Send,
+        ) -> Result<Vec<Embedding>, EmbeddingError> {
+            let texts: Vec<String> = texts.into_iter().collect();
+            if texts.iter().any(|text| text.contains("unembeddable")) {
+                return Err(EmbeddingError::ResponseError("Response data length does not match input length".into()));
+            }
+            MockEmbedding.embed_texts(texts).await
+        }
+    }
+
+    // Records every prompt it receives and answers with a fixed message
+    #[derive(Clone, Default)]
+    struct MockCompletion {
+        prompts: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl CompletionModel for MockCompletion {
+        type Response = ();
+
+        async fn completion(&self, request: CompletionRequest) -> Result<CompletionResponse<()>, CompletionError> {
+            self.prompts.lock().unwrap().push(request.prompt);
+            Ok(CompletionResponse { choice: ModelChoice::Message("mock analysis".to_string()), raw_response: () })
+        }
+    }
+
+    fn customer(id: &str, country: &str, loyalty: &str) -> CustomerFeedback {
+        let mut customer = CustomerFeedback {
+            customer_id: id.to_string(),
+            age: 40,
+            gender: "Female".to_string(),
+            country: country.to_string(),
+            income: Some(50000.0),
+            product_quality: 7,
+            service_quality: 6,
+            purchase_frequency: 12,
+            feedback_score: "Medium".to_string(),
+            loyalty_level: loyalty.to_string(),
+            satisfaction_score: Some(80.0),
+            date: None,
+            profile_summary: String::new(),
+            currency: Default::default(),
+        };
+        customer.generate_summary();
+        customer
+    }
+
+    fn embed_options(missing: MissingEmbeddingPolicy) -> EmbedOptions {
+        EmbedOptions {
+            batch_size: 16,
+            missing,
+            retry: RetryPolicy { max_retries: 0, base_delay: Duration::ZERO, timeout: None },
+            max_in_flight: 1,
+            delay: Duration::ZERO,
+        }
+    }
+
+    #[tokio::test]
+    async fn short_embedding_response_is_detected() {
+        let chunk = vec![customer("a-1", "UK", "Gold"), customer("b-2", "USA", "Bronze"), customer("c-3", "France", "Silver")];
+
+        let mut metrics = RunMetrics::new("mock", "mock");
+        let (dropped, dropped_count) = process_chunk(chunk.clone(), &ShortEmbedding, 1, None, &mut metrics, &embed_options(MissingEmbeddingPolicy::Drop))
+            .await
+            .unwrap();
+        assert_eq!(dropped_count, 1);
+        let ids: Vec<&str> = dropped.iter().map(|(c, _)| c.customer_id.as_str()).collect();
+        assert_eq!(ids, ["a-1", "b-2"]);
+        for (customer, embedding) in &dropped {
+            assert_eq!(embedding.first().document, customer.profile_summary);
+        }
+
+        let (retried, dropped_count) = process_chunk(chunk, &ShortEmbedding, 1, None, &mut metrics, &embed_options(MissingEmbeddingPolicy::Retry))
+            .await
+            .unwrap();
+        assert_eq!(dropped_count, 0);
+        let ids: Vec<&str> = retried.iter().map(|(c, _)| c.customer_id.as_str()).collect();
+        assert_eq!(ids, ["a-1", "b-2", "c-3"]);
+        for (customer, embedding) in &retried {
+            assert_eq!(embedding.first().document, customer.profile_summary);
+        }
+    }
+
+    #[tokio::test]
+    async fn rejected_short_response_is_embedded_per_record() {
+        let mut unembeddable = customer("b-2", "USA", "Bronze");
+        unembeddable.profile_summary.push_str(" unembeddable");
+        let chunk = vec![customer("a-1", "UK", "Gold"), unembeddable, customer("c-3", "France", "Silver")];
+
+        let mut metrics = RunMetrics::new("mock", "mock");
+        for policy in [MissingEmbeddingPolicy::Drop, MissingEmbeddingPolicy::Retry] {
+            let (embedded, dropped) = process_chunk(chunk.clone(), &RejectingEmbedding, 1, None, &mut metrics, &embed_options(policy))
+                .await
+                .unwrap();
+            assert_eq!(dropped, 1);
+            let ids: Vec<&str> = embedded.iter().map(|(c, _)| c.customer_id.as_str()).collect();
+            assert_eq!(ids, ["a-1", "c-3"]);
+            for (customer, embedding) in &embedded {
+                assert_eq!(embedding.first().document, customer.profile_summary);
+            }
+        }
+    }
+
+    // A record with nothing in common with CustomerFeedback
+    #[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize, Embed)]
+    struct Ticket {
+        id: String,
+        #[embed]
+        text: String,
+    }
+
+    impl EmbeddableRecord for Ticket {
+        fn record_id(&self) -> &str {
+            &self.id
+        }
+
+        fn summary(&self) -> &str {
+            &self.text
+        }
+
+        fn format_for_prompt(&self, score: f64, idx: usize, _redaction: &Redaction) -> String {
+            format!("\n{}. Ticket {} ({:.2}): {}\n", idx, self.id, score, self.text)
+        }
+
+        fn prompt_fields(&self, _redaction: &Redaction) -> Vec<(&'static str, String)> {
+            vec![("Ticket", self.id.clone()), ("Text", self.text.clone())]
+        }
+    }
+
+    #[tokio::test]
+    async fn other_record_types_can_be_embedded_and_prompted() {
+        let tickets = vec![
+            Ticket { id: "t-1".to_string(), text: "Parcel arrived damaged".to_string() },
+            Ticket { id: "t-2".to_string(), text: "Refund has not arrived".to_string() },
+        ];
+
+        let mut metrics = RunMetrics::new("mock", "mock");
+        let (embedded, _) = process_chunk(tickets.clone(), &ShortEmbedding, 1, None, &mut metrics, &embed_options(MissingEmbeddingPolicy::Retry))
+            .await
+            .unwrap();
+        let ids: Vec<&str> = embedded.iter().map(|(t, _)| t.record_id()).collect();
+        assert_eq!(ids, ["t-1", "t-2"]);
+        for (ticket, embedding) in &embedded {
+            assert_eq!(embedding.first().document, ticket.text);
+        }
+
+        let profiles: Vec<(f64, &Ticket)> = embedded.iter().map(|(t, _)| (0.5, t)).collect();
+        let prompt = prompt::block_prompt("Delivery issues?", &profiles, &Redaction::default());
+        assert_eq!(
+            prompt,
+            "Analysis Query: Delivery issues?\n\nRelevant Customer Profiles (2 found):\n\
+             \n1. Ticket t-1 (0.50): Parcel arrived damaged\n\
+             \n2. Ticket t-2 (0.50): Refund has not arrived\n"
+        );
+
+        // The same retriever and prompt chain the binary builds for customers
+        let index = InMemoryVectorStore::from_documents(embedded).index(MockEmbedding);
+        let retriever: search::Retriever<_, String, Ticket> = search::Retriever::new(index, MockEmbedding, 1);
+        let completion = MockCompletion::default();
+        let analysis_op = RetryingPrompt::new(
+            "mock",
+            AgentBuilder::new(completion.clone()).preamble(ANALYST_PREAMBLE).build(),
+            RetryPolicy { max_retries: 0, base_delay: Duration::ZERO, timeout: None },
+        );
+        let analysis_op = &analysis_op;
+        let chain = pipeline::new()
+            .chain(parallel!(passthrough::<String>(), retriever))
+            .map(|(query, maybe_profiles)| {
+                let options = PromptOptions {
+                    max_context_tokens: 6000,
+                    redaction: Redaction::default(),
+                    format: prompt::PromptFormat::Table,
+                    confidence: None,
+                    hedge_low_confidence: false,
+                };
+                prompt::build_analysis_request(&query, maybe_profiles, &options).unwrap()
+            })
+            .chain(pipeline::then(move |request: AnalysisRequest<Ticket>| async move {
+                let response = analysis_op.call(request.prompt.clone()).await;
+                (request, response)
+            }));
+
+        let (request, response) = chain.call("Refund has not arrived".to_string()).await;
+        assert_eq!(request.documents, [tickets[1].clone()]);
+        assert_eq!(request.profiles[0].customer_id, "t-2");
+        assert!(request.prompt.ends_with("| Score | Ticket | Text |\n| --- | --- | --- |\n| 1.000 | t-2 | Refund has not arrived |\n"), "{}", request.prompt);
+        assert_eq!(response.unwrap(), "mock analysis");
+        assert_eq!(completion.prompts.lock().unwrap().last(), Some(&request.prompt));
+    }
+
+    #[tokio::test]
+    async fn equal_scores_are_ordered_by_customer_id() {
+        // Same fields, so the same summary and embedding: every lookup ties
+        let ids = ["c-3", "a-1", "d-4", "b-2"];
+        let customers: Vec<CustomerFeedback> = ids.iter().map(|id| customer(id, "UK", "Gold")).collect();
+        let summaries: Vec<String> = customers.iter().map(|c| c.profile_summary.clone()).collect();
+        let embeddings = MockEmbedding.embed_texts(summaries).await.unwrap();
+        let documents: Vec<(CustomerFeedback, OneOrMany<Embedding>)> =
+            customers.into_iter().zip(embeddings.into_iter().map(OneOrMany::one)).collect();
+
+        for _ in 0..5 {
+            let index = InMemoryVectorStore::from_documents(documents.clone()).index(MockEmbedding);
+            let retriever: search::Retriever<_, String> = search::Retriever::new(index, MockEmbedding, 2);
+            let results = retriever.call("Gold customers in the UK".to_string()).await.unwrap();
+            assert_eq!(results[0].0, results[1].0);
+            let ids: Vec<&str> = results.iter().map(|(_, _, doc)| doc.customer_id.as_str()).collect();
+            assert_eq!(ids, ["a-1", "b-2"]);
+        }
+    }
+
+    #[test]
+    fn failed_lookups_build_no_prompt() {
+        let options = PromptOptions {
+            max_context_tokens: 6000,
+            redaction: Redaction::default(),
+            format: prompt::PromptFormat::Blocks,
+            confidence: None,
+            hedge_low_confidence: false,
+        };
+        let lookup = Err(rig::vector_store::VectorStoreError::MissingIdError("c-1".to_string()));
+        let err = prompt::build_analysis_request::<CustomerFeedback>("Gold customers?", lookup, &options).unwrap_err();
+        assert!(matches!(err, PipelineError::Lookup(_)));
+        assert!(!err.reached_model());
+        assert_eq!(err.to_string(), "Failed to retrieve relevant customer profiles: Missing Id: c-1");
+
+        // Nothing left after filtering is not a failure; the agent is told so
+        let request = prompt::build_analysis_request::<CustomerFeedback>("Gold customers?", Ok(Vec::new()), &options).unwrap();
+        assert!(request.prompt.ends_with("Warning: No relevant customer profiles found."));
+    }
+
+    #[tokio::test]
+    async fn passthrough_preserves_query() {
+        let documents: Vec<(CustomerFeedback, OneOrMany<Embedding>)> = {
+            let customers = vec![customer("a-1", "UK", "Gold"), customer("b-2", "USA", "Bronze")];
+            let summaries: Vec<String> = customers.iter().map(|c| c.profile_summary.clone()).collect();
+            let embeddings = MockEmbedding.embed_texts(summaries).await.unwrap();
+            customers.into_iter().zip(embeddings.into_iter().map(OneOrMany::one)).collect()
+        };
+        let index = InMemoryVectorStore::from_documents(documents).index(MockEmbedding);
+        let retriever = search::Retriever::new(index, MockEmbedding, 2);
+
+        let completion = MockCompletion::default();
+        let agent = AgentBuilder::new(completion.clone()).preamble(ANALYST_PREAMBLE).build();
+        let analysis_op = RetryingPrompt::new(
+            "mock",
+            agent,
+            RetryPolicy { max_retries: 0, base_delay: Duration::ZERO, timeout: None },
+        );
+        let analysis_op = &analysis_op;
+
+        let chain = pipeline::new()
+            .chain(parallel!(passthrough::<String>(), retriever))
+            .map(|(query, maybe_profiles)| {
+                let options = PromptOptions {
+                    max_context_tokens: 6000,
+                    redaction: Redaction::default(),
+                    format: prompt::PromptFormat::Blocks,
+                    confidence: None,
+                    hedge_low_confidence: false,
+                };
+                prompt::build_analysis_request(&query, maybe_profiles, &options).unwrap()
+            })
+            .chain(pipeline::then(move |request: AnalysisRequest| async move {
+                let response = analysis_op.call(request.prompt.clone()).await;
+                (request, response)
+            }));
+
+        let queries = [
+            "Which Gold customers are at risk?",
+            "  leading and trailing whitespace\t",
+            "Acentuación: ¿clientes en España?",
+        ];
+        for query in queries {
+            let (request, response) = chain.call(query.to_string()).await;
+            assert_eq!(request.query.as_bytes(), query.as_bytes());
+            assert!(request.prompt.starts_with(&format!("Analysis Query: {}\n", query)));
+            assert_eq!(request.profiles.len(), 2);
+            assert_eq!(response.unwrap(), "mock analysis");
+            assert_eq!(completion.prompts.lock().unwrap().last(), Some(&request.prompt));
+        }
+    }
+}
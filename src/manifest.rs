@@ -0,0 +1,62 @@
+use crate::cache::SummaryCache;
+use crate::models::CustomerFeedback;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::io::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ManifestFormat {
+    Jsonl,
+    Table,
+}
+
+// One indexed profile as it would be stored; dimension and norm are absent when
+// the summary has no cached embedding
+#[derive(Debug, Serialize)]
+pub struct ManifestEntry<'a> {
+    pub customer_id: &'a str,
+    pub dims: Option<usize>,
+    pub norm: Option<f64>,
+    pub summary: &'a str,
+}
+
+// Pair each profile with its cached embedding, without calling the API
+pub fn entries<'a>(customers: &'a [CustomerFeedback], cache: &SummaryCache) -> Vec<ManifestEntry<'a>> {
+    customers
+        .iter()
+        .map(|customer| {
+            let vector = cache.get(&customer.profile_summary);
+            ManifestEntry {
+                customer_id: &customer.customer_id,
+                dims: vector.as_ref().map(Vec::len),
+                norm: vector.map(|v| v.iter().map(|x| x * x).sum::<f64>().sqrt()),
+                summary: &customer.profile_summary,
+            }
+        })
+        .collect()
+}
+
+pub fn write(entries: &[ManifestEntry], format: ManifestFormat, out: &mut dyn Write) -> Result<(), anyhow::Error> {
+    match format {
+        ManifestFormat::Jsonl => {
+            for entry in entries {
+                writeln!(out, "{}", serde_json::to_string(entry)?)?;
+            }
+        }
+        ManifestFormat::Table => {
+            let id_width = entries.iter().map(|e| e.customer_id.len()).chain([11]).max().unwrap_or(11);
+            writeln!(out, "{:<id_width$}  {:>5}  {:>7}  Summary", "Customer ID", "Dims", "Norm")?;
+            for entry in entries {
+                writeln!(
+                    out,
+                    "{:<id_width$}  {:>5}  {:>7}  {}",
+                    entry.customer_id,
+                    entry.dims.map(|d| d.to_string()).unwrap_or_else(|| "-".to_string()),
+                    entry.norm.map(|n| format!("{:.4}", n)).unwrap_or_else(|| "-".to_string()),
+                    entry.summary
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,89 @@
+use crate::export::CsvExportOptions;
+use crate::models::CustomerFeedback;
+use crate::stratify::StratifyField;
+use clap::ValueEnum;
+use rig::{
+    embeddings::{distance::VectorDistance, Embedding, EmbeddingModel},
+    vector_store::in_memory_store::InMemoryVectorIndex,
+};
+use std::path::Path;
+use std::str::FromStr;
+
+// `field=value` restriction on the customers included, e.g. `country=UK`
+#[derive(Debug, Clone)]
+pub struct SubsetFilter {
+    pub field: StratifyField,
+    pub value: String,
+}
+
+impl FromStr for SubsetFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (field, value) = s.split_once('=').ok_or_else(|| format!("expected FIELD=VALUE, got '{}'", s))?;
+        Ok(Self { field: StratifyField::from_str(field.trim(), true)?, value: value.trim().to_string() })
+    }
+}
+
+// Stored customers with the given ids (all when empty) that pass every filter,
+// ordered by id. Fails on unknown ids, an empty subset or one over `max`.
+pub fn select<'a, M: EmbeddingModel>(
+    index: &'a InMemoryVectorIndex<M, CustomerFeedback>,
+    ids: &[String],
+    filters: &[SubsetFilter],
+    max: usize,
+) -> Result<Vec<(&'a CustomerFeedback, Embedding)>, anyhow::Error> {
+    if let Some(unknown) = ids.iter().find(|id| !index.iter().any(|(_, (doc, _))| &doc.customer_id == *id)) {
+        return Err(anyhow::anyhow!("Customer {} is not in the vector store", unknown));
+    }
+    let mut subset: Vec<(&CustomerFeedback, Embedding)> = index
+        .iter()
+        .map(|(_, (doc, embeddings))| (doc, embeddings.first()))
+        .filter(|(doc, _)| ids.is_empty() || ids.contains(&doc.customer_id))
+        .filter(|(doc, _)| filters.iter().all(|filter| filter.field.value(doc).eq_ignore_ascii_case(&filter.value)))
+        .collect();
+    subset.sort_by(|a, b| a.0.cmp(b.0));
+
+    match subset.len() {
+        0 => Err(anyhow::anyhow!("No stored customers match the given ids and filters")),
+        n if n > max => Err(anyhow::anyhow!(
+            "{} customers match, but the matrix is capped at {} ({} cells); narrow the subset or raise --max-customers",
+            n,
+            max,
+            max * max
+        )),
+        _ => Ok(subset),
+    }
+}
+
+// Write the pairwise cosine similarities as a square CSV with customer ids as
+// the header row and first column. Returns the mean and minimum similarity
+// between distinct customers, as a quick read of how cohesive the subset is.
+pub fn write(
+    path: &Path,
+    subset: &[(&CustomerFeedback, Embedding)],
+    options: &CsvExportOptions,
+) -> Result<Option<(f64, f64)>, anyhow::Error> {
+    let mut writer = options.create(path)?;
+    writer.write_record(
+        std::iter::once("CustomerID").chain(subset.iter().map(|(doc, _)| doc.customer_id.as_str())),
+    )?;
+
+    let (mut sum, mut min, mut pairs) = (0.0, f64::INFINITY, 0usize);
+    for (i, (doc, embedding)) in subset.iter().enumerate() {
+        let mut row = vec![doc.customer_id.clone()];
+        for (j, (_, other)) in subset.iter().enumerate() {
+            let similarity = embedding.cosine_similarity(other, false);
+            if i != j {
+                sum += similarity;
+                min = min.min(similarity);
+                pairs += 1;
+            }
+            row.push(format!("{:.4}", similarity));
+        }
+        writer.write_record(&row)?;
+    }
+    writer.flush()?;
+
+    Ok((pairs > 0).then(|| (sum / pairs as f64, min)))
+}
@@ -0,0 +1,156 @@
+use crate::models::CustomerFeedback;
+use crate::tokens::estimate_tokens;
+
+// USD per 1K tokens as (input, output) for the models this tool uses by
+// default. Unknown models are reported without a cost.
+fn price_per_1k(model: &str) -> Option<(f64, f64)> {
+    match model {
+        "text-embedding-ada-002" => Some((0.0001, 0.0)),
+        "text-embedding-3-small" => Some((0.00002, 0.0)),
+        "text-embedding-3-large" => Some((0.00013, 0.0)),
+        "embed-english-v3.0" | "embed-multilingual-v3.0" => Some((0.0001, 0.0)),
+        "command-r" => Some((0.00015, 0.0006)),
+        "gpt-4" => Some((0.03, 0.06)),
+        "gpt-4-turbo" => Some((0.01, 0.03)),
+        "gpt-4o" => Some((0.0025, 0.01)),
+        "gpt-4o-mini" => Some((0.00015, 0.0006)),
+        "gpt-3.5-turbo" => Some((0.0005, 0.0015)),
+        _ => None,
+    }
+}
+
+// Requests and tokens for one kind of API usage. rig doesn't surface the
+// provider's usage figures, so token counts here are always estimates.
+#[derive(Debug, Default)]
+pub struct UsageLine {
+    pub model: String,
+    pub requests: usize,
+    pub input_tokens: usize,
+    pub output_tokens: usize,
+}
+
+impl UsageLine {
+    pub fn new(model: &str) -> Self {
+        Self { model: model.to_string(), ..Default::default() }
+    }
+
+    pub fn record_completion(&mut self, prompt: &str, response: Option<&str>) {
+        self.requests += 1;
+        self.input_tokens += estimate_tokens(prompt);
+        self.output_tokens += response.map(estimate_tokens).unwrap_or(0);
+    }
+
+    // Fold in usage recorded separately, e.g. by a concurrently embedded chunk
+    pub fn add(&mut self, other: &UsageLine) {
+        self.requests += other.requests;
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+    }
+
+    pub fn cost(&self) -> Option<f64> {
+        price_per_1k(&self.model).map(|(input, output)| {
+            (self.input_tokens as f64 * input + self.output_tokens as f64 * output) / 1000.0
+        })
+    }
+}
+
+// Usage the embedding step would incur for these records, from their summary
+// text, before anything is sent
+pub fn estimate_cost<'a>(
+    customers: impl IntoIterator<Item = &'a CustomerFeedback>,
+    model: &str,
+    docs_per_request: usize,
+) -> UsageLine {
+    let mut estimate = UsageLine::new(model);
+    let mut records: usize = 0;
+    for customer in customers {
+        records += 1;
+        estimate.input_tokens += estimate_tokens(&customer.profile_summary);
+    }
+    estimate.requests = records.div_ceil(docs_per_request.max(1));
+    estimate
+}
+
+// API usage over a run, split into ingestion (embeddings) and analysis
+// (completions), plus queries whose analysis was a refusal or non-answer and
+// queries whose retrieval was ambiguous. With --verify-citations, also each
+// query's (figures cited, figures found in its profiles).
+#[derive(Debug)]
+pub struct RunMetrics {
+    pub embedding: UsageLine,
+    pub completion: UsageLine,
+    pub refused_queries: Vec<String>,
+    pub ambiguous_queries: Vec<String>,
+    pub citation_support: Vec<(String, usize, usize)>,
+}
+
+impl RunMetrics {
+    pub fn new(embedding_model: &str, completion_model: &str) -> Self {
+        Self {
+            embedding: UsageLine::new(embedding_model),
+            completion: UsageLine::new(completion_model),
+            refused_queries: Vec::new(),
+            ambiguous_queries: Vec::new(),
+            citation_support: Vec::new(),
+        }
+    }
+
+    pub fn record_embedding(&mut self, requests: usize, texts: &[&str]) {
+        self.embedding.requests += requests;
+        self.embedding.input_tokens += texts.iter().map(|text| estimate_tokens(text)).sum::<usize>();
+    }
+
+    pub fn record_completion(&mut self, prompt: &str, response: Option<&str>) {
+        self.completion.record_completion(prompt, response);
+    }
+
+    // Per-query support ratios and the run's overall ratio
+    pub fn citation_table(&self) -> String {
+        let ratio = |cited: usize, supported: usize| match cited {
+            0 => "n/a".to_string(),
+            _ => format!("{:.0}%", supported as f64 / cited as f64 * 100.0),
+        };
+        let mut table = format!("{:>8} {:>10} {:>8}  Query\n", "Figures", "Supported", "Ratio");
+        for (query, cited, supported) in &self.citation_support {
+            table.push_str(&format!("{:>8} {:>10} {:>8}  {}\n", cited, supported, ratio(*cited, *supported), query));
+        }
+        let cited: usize = self.citation_support.iter().map(|(_, cited, _)| cited).sum();
+        let supported: usize = self.citation_support.iter().map(|(_, _, supported)| supported).sum();
+        table.push_str(&format!("{:>8} {:>10} {:>8}  (all queries)", cited, supported, ratio(cited, supported)));
+        table
+    }
+
+    // Breakdown table; `~` marks figures estimated locally rather than reported by the API
+    pub fn cost_table(&self) -> String {
+        let format_cost = |line: &UsageLine| match line.cost() {
+            Some(cost) => format!("~${:.4}", cost),
+            None => "unknown".to_string(),
+        };
+        let row = |stage: &str, line: &UsageLine| {
+            format!(
+                "{:<10} {:<24} {:>8} {:>14} {:>14} {:>12}\n",
+                stage,
+                line.model,
+                line.requests,
+                format!("~{}", line.input_tokens),
+                format!("~{}", line.output_tokens),
+                format_cost(line),
+            )
+        };
+
+        let mut table = format!(
+            "{:<10} {:<24} {:>8} {:>14} {:>14} {:>12}\n",
+            "Stage", "Model", "Requests", "Input tokens", "Output tokens", "Cost (USD)"
+        );
+        table.push_str(&row("Embedding", &self.embedding));
+        table.push_str(&row("Completion", &self.completion));
+        match (self.embedding.cost(), self.completion.cost()) {
+            (Some(embedding), Some(completion)) => {
+                table.push_str(&format!("{:>86}\n", format!("Total ~${:.4}", embedding + completion)))
+            }
+            _ => table.push_str("Total unknown: no price on file for one of the models\n"),
+        }
+        table.push_str("~ = estimated locally (about 4 characters per token); the API's usage figures are not available");
+        table
+    }
+}
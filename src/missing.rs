@@ -0,0 +1,81 @@
+use crate::models::CustomerFeedback;
+use clap::ValueEnum;
+
+// How to treat a record whose optional numeric field is blank in the CSV
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum MissingValuePolicy {
+    /// Drop the record
+    Skip,
+    /// Fill in the mean of the records that have a value
+    ImputeMean,
+    /// Keep the record and describe the value as unknown
+    Unknown,
+}
+
+fn apply_policy(
+    customers: Vec<CustomerFeedback>,
+    field: &str,
+    policy: MissingValuePolicy,
+    get: fn(&CustomerFeedback) -> Option<f64>,
+    set: fn(&mut CustomerFeedback, f64),
+) -> Vec<CustomerFeedback> {
+    let missing = customers.iter().filter(|c| get(c).is_none()).count();
+    if missing == 0 {
+        return customers;
+    }
+
+    match policy {
+        MissingValuePolicy::Skip => {
+            println!("Skipping {} records with missing {}", missing, field);
+            customers.into_iter().filter(|c| get(c).is_some()).collect()
+        }
+        MissingValuePolicy::ImputeMean => {
+            let present: Vec<f64> = customers.iter().filter_map(get).collect();
+            if present.is_empty() {
+                println!("Cannot impute {}: no records have a value, leaving {} as unknown", field, missing);
+                return customers;
+            }
+            let mean = present.iter().sum::<f64>() / present.len() as f64;
+            println!("Imputing mean {} ({:.2}) for {} records", field, mean, missing);
+            customers
+                .into_iter()
+                .map(|mut c| {
+                    if get(&c).is_none() {
+                        set(&mut c, mean);
+                    }
+                    c
+                })
+                .collect()
+        }
+        MissingValuePolicy::Unknown => {
+            println!("Keeping {} records with unknown {}", missing, field);
+            customers
+        }
+    }
+}
+
+pub fn apply_missing_value_policies(
+    customers: Vec<CustomerFeedback>,
+    satisfaction: MissingValuePolicy,
+    income: MissingValuePolicy,
+) -> Vec<CustomerFeedback> {
+    let customers = apply_policy(
+        customers,
+        "satisfaction score",
+        satisfaction,
+        |c| c.satisfaction_score,
+        |c, v| c.satisfaction_score = Some(v),
+    );
+    apply_policy(customers, "income", income, |c| c.income, |c, v| c.income = Some(v))
+}
+
+// Whether a single record should be kept under the given per-record policies.
+// Used by streaming loads, where the mean of a field isn't known up front.
+pub fn admits(
+    customer: &CustomerFeedback,
+    satisfaction: MissingValuePolicy,
+    income: MissingValuePolicy,
+) -> bool {
+    !(satisfaction == MissingValuePolicy::Skip && customer.satisfaction_score.is_none()
+        || income == MissingValuePolicy::Skip && customer.income.is_none())
+}
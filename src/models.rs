@@ -1,4 +1,8 @@
+use crate::confidence::Confidence;
+use crate::currency::Currency;
+use chrono::NaiveDate;
 use rig::Embed;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
 
@@ -14,7 +18,7 @@ pub struct CustomerFeedback {
     #[serde(rename = "Country")]
     pub country: String,
     #[serde(rename = "Income")]
-    pub income: f64,
+    pub income: Option<f64>,
     #[serde(rename = "ProductQuality")]
     pub product_quality: i32,
     #[serde(rename = "ServiceQuality")]
@@ -26,11 +30,54 @@ pub struct CustomerFeedback {
     #[serde(rename = "LoyaltyLevel")]
     pub loyalty_level: String,
     #[serde(rename = "SatisfactionScore")]
-    pub satisfaction_score: f64,
+    pub satisfaction_score: Option<f64>,
+    // Optional feedback date, used to order historical rows for a customer
+    #[serde(rename = "Date", default)]
+    pub date: Option<String>,
     // Field that will be used for embeddings
     #[embed]
     #[serde(skip)]
     pub profile_summary: String,
+    // Set from --income-currency at load time
+    #[serde(skip)]
+    pub currency: Currency,
+}
+
+// A profile returned by the similarity lookup, kept alongside the formatted prompt
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetrievedProfile {
+    pub customer_id: String,
+    pub score: f64,
+}
+
+// The prompt assembled for a query together with what was retrieved to build it
+#[derive(Debug, Clone)]
+pub struct AnalysisRequest<T = CustomerFeedback> {
+    pub query: String,
+    pub prompt: String,
+    pub profiles: Vec<RetrievedProfile>,
+    // The retrieved records themselves, in the same order as `profiles`
+    pub documents: Vec<T>,
+    // Set when confidence annotation or hedging is enabled
+    pub confidence: Option<Confidence>,
+}
+
+impl<T> AnalysisRequest<T> {
+    // Stands in for the request of a query that failed before a prompt was
+    // built, so it is still reported and recorded under its query
+    pub fn unsent(query: String) -> Self {
+        Self { query, prompt: String::new(), profiles: Vec::new(), documents: Vec::new(), confidence: None }
+    }
+}
+
+// The four parts of an analysis as typed fields, filled in by the extractor
+// when --structured is set
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct AnalysisResult {
+    pub key_patterns: Vec<String>,
+    pub risk_factors: Vec<String>,
+    pub recommendations: Vec<String>,
+    pub opportunities: Vec<String>,
 }
 
 // Implement Eq manually, using only the customer_id for equality comparison
@@ -52,14 +99,169 @@ impl CustomerFeedback {
     // Generate a text summary for embedding
     pub fn generate_summary(&mut self) {
         self.profile_summary = format!(
-            "Customer Profile: {} year old {} from {} with income ${:.2}. \
+            "Customer Profile: {} year old {} from {} with income {}. \
              Product Quality Rating: {}/10, Service Quality: {}/10. \
              Purchases {} times per year. Feedback Score: {}. \
-             Loyalty Level: {}. Satisfaction Score: {:.1}%",
-            self.age, self.gender, self.country, self.income,
+             Loyalty Level: {}. Satisfaction Score: {}",
+            self.age, self.gender, self.country, self.income_display(),
             self.product_quality, self.service_quality,
             self.purchase_frequency, self.feedback_score,
-            self.loyalty_level, self.satisfaction_score
+            self.loyalty_level, self.satisfaction_display()
+        );
+    }
+
+    // Income formatted for summaries and prompts, or "unknown" when missing
+    pub fn income_display(&self) -> String {
+        match self.income {
+            Some(income) => self.currency.format(income),
+            None => "unknown".to_string(),
+        }
+    }
+
+    // Satisfaction formatted for summaries and prompts, or "unknown" when missing
+    pub fn satisfaction_display(&self) -> String {
+        match self.satisfaction_score {
+            Some(score) => format!("{:.1}%", score),
+            None => "unknown".to_string(),
+        }
+    }
+
+    // Parse the optional Date column, accepting ISO dates, RFC 3339 timestamps
+    // and US-style month/day/year
+    pub fn parsed_date(&self) -> Option<NaiveDate> {
+        let date = self.date.as_deref()?.trim();
+        NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .or_else(|_| NaiveDate::parse_from_str(date, "%m/%d/%Y"))
+            .ok()
+            .or_else(|| chrono::DateTime::parse_from_rfc3339(date).ok().map(|dt| dt.date_naive()))
+            .or_else(|| {
+                chrono::NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S")
+                    .ok()
+                    .map(|dt| dt.date())
+            })
+    }
+
+    // Generate a summary for the latest record of a customer that also describes
+    // how their metrics changed across `history` (oldest first, including self)
+    pub fn generate_trend_summary(&mut self, history: &[CustomerFeedback]) {
+        self.generate_summary();
+
+        let (Some(first), Some(last)) = (history.first(), history.last()) else {
+            return;
+        };
+        if history.len() < 2 {
+            return;
+        }
+
+        fn describe(label: &str, from: f64, to: f64, unit: &str) -> String {
+            let direction = if to > from {
+                "rose"
+            } else if to < from {
+                "fell"
+            } else {
+                return format!("{} held steady at {:.1}{}", label, to, unit);
+            };
+            format!("{} {} from {:.1}{} to {:.1}{}", label, direction, from, unit, to, unit)
+        }
+
+        let mut trends = Vec::new();
+        if let (Some(from), Some(to)) = (first.satisfaction_score, last.satisfaction_score) {
+            trends.push(describe("satisfaction", from, to, "%"));
+        }
+        trends.extend([
+            describe("purchase frequency", first.purchase_frequency as f64, last.purchase_frequency as f64, ""),
+            describe("product quality", first.product_quality as f64, last.product_quality as f64, "/10"),
+            describe("service quality", first.service_quality as f64, last.service_quality as f64, "/10"),
+        ]);
+        if first.loyalty_level != last.loyalty_level {
+            trends.push(format!("loyalty level changed from {} to {}", first.loyalty_level, last.loyalty_level));
+        }
+
+        self.profile_summary.push_str(&format!(
+            " Trend over {} records: {}.",
+            history.len(),
+            trends.join(", ")
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CSV: &str = "\
+CustomerID,Age,Gender,Country,Income,ProductQuality,ServiceQuality,PurchaseFrequency,FeedbackScore,LoyaltyLevel,SatisfactionScore
+c-2,34,Female,UK,52000.456,8,7,12,High,Gold,87.25
+c-10,61,Male,France,,3,4,2,Low,Bronze,
+c-1,29,Male,USA,31000,6,9,5,Medium,Silver,64.04
+";
+
+    fn read(csv: &str) -> Vec<CustomerFeedback> {
+        csv::Reader::from_reader(csv.as_bytes())
+            .deserialize()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn csv_columns_map_to_fields() {
+        let customers = read(CSV);
+        assert_eq!(customers.len(), 3);
+
+        let first = &customers[0];
+        assert_eq!(first.customer_id, "c-2");
+        assert_eq!(first.age, 34);
+        assert_eq!(first.gender, "Female");
+        assert_eq!(first.country, "UK");
+        assert_eq!(first.income, Some(52000.456));
+        assert_eq!((first.product_quality, first.service_quality), (8, 7));
+        assert_eq!(first.purchase_frequency, 12);
+        assert_eq!(first.feedback_score, "High");
+        assert_eq!(first.loyalty_level, "Gold");
+        assert_eq!(first.satisfaction_score, Some(87.25));
+        // Absent and skipped columns take their defaults
+        assert_eq!(first.date, None);
+        assert_eq!(first.profile_summary, "");
+        assert_eq!(first.currency, Currency::Usd);
+
+        // Blank cells are missing values
+        assert_eq!(customers[1].income, None);
+        assert_eq!(customers[1].satisfaction_score, None);
+    }
+
+    #[test]
+    fn generate_summary_formats_every_field() {
+        let mut customers = read(CSV);
+        customers[0].generate_summary();
+        assert_eq!(
+            customers[0].profile_summary,
+            "Customer Profile: 34 year old Female from UK with income $52000.46. \
+             Product Quality Rating: 8/10, Service Quality: 7/10. \
+             Purchases 12 times per year. Feedback Score: High. \
+             Loyalty Level: Gold. Satisfaction Score: 87.2%"
         );
+
+        customers[1].generate_summary();
+        assert!(customers[1].profile_summary.contains("with income unknown."));
+        assert!(customers[1].profile_summary.ends_with("Satisfaction Score: unknown"));
+
+        customers[2].generate_summary();
+        assert!(customers[2].profile_summary.contains("with income $31000.00."));
+        assert!(customers[2].profile_summary.ends_with("Satisfaction Score: 64.0%"));
+    }
+
+    #[test]
+    fn records_sort_by_customer_id_alone() {
+        let mut customers = read(CSV);
+        customers.sort();
+        let ids: Vec<&str> = customers.iter().map(|c| c.customer_id.as_str()).collect();
+        // String order, not numeric
+        assert_eq!(ids, ["c-1", "c-10", "c-2"]);
+
+        let mut same_id = customers[0].clone();
+        same_id.age += 1;
+        same_id.generate_summary();
+        assert_eq!(same_id.cmp(&customers[0]), Ordering::Equal);
+        assert_eq!(customers.binary_search(&same_id), Ok(0));
     }
 }
@@ -0,0 +1,119 @@
+use crate::models::CustomerFeedback;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+const DEFAULT_GENDERS: &[(&str, &str)] = &[
+    ("m", "Male"),
+    ("male", "Male"),
+    ("man", "Male"),
+    ("f", "Female"),
+    ("female", "Female"),
+    ("woman", "Female"),
+    ("other", "Other"),
+    ("non-binary", "Other"),
+    ("nonbinary", "Other"),
+];
+
+const DEFAULT_COUNTRIES: &[(&str, &str)] = &[
+    ("us", "USA"),
+    ("usa", "USA"),
+    ("u.s.", "USA"),
+    ("u.s.a.", "USA"),
+    ("united states", "USA"),
+    ("united states of america", "USA"),
+    ("america", "USA"),
+    ("uk", "UK"),
+    ("u.k.", "UK"),
+    ("united kingdom", "UK"),
+    ("great britain", "UK"),
+    ("england", "UK"),
+    ("ca", "Canada"),
+    ("canada", "Canada"),
+    ("de", "Germany"),
+    ("germany", "Germany"),
+    ("fr", "France"),
+    ("france", "France"),
+    ("au", "Australia"),
+    ("australia", "Australia"),
+];
+
+// Canonical spellings for the free-text Gender and Country fields. Keys are
+// matched case-insensitively after trimming.
+#[derive(Debug, Default, Deserialize)]
+pub struct NormalizationMap {
+    #[serde(default)]
+    gender: HashMap<String, String>,
+    #[serde(default)]
+    country: HashMap<String, String>,
+}
+
+impl NormalizationMap {
+    pub fn with_defaults() -> Self {
+        let to_map = |pairs: &[(&str, &str)]| {
+            pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+        };
+        Self {
+            gender: to_map(DEFAULT_GENDERS),
+            country: to_map(DEFAULT_COUNTRIES),
+        }
+    }
+
+    // Layer `[gender]` / `[country]` tables from a TOML or JSON file over this map
+    pub fn merge_file(mut self, path: &Path) -> Result<Self, anyhow::Error> {
+        let content = std::fs::read_to_string(path)?;
+        let overrides: NormalizationMap = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => serde_json::from_str(&content)?,
+            _ => toml::from_str(&content)?,
+        };
+        let lower = |map: HashMap<String, String>| {
+            map.into_iter().map(|(k, v)| (k.trim().to_lowercase(), v))
+        };
+        self.gender.extend(lower(overrides.gender));
+        self.country.extend(lower(overrides.country));
+        Ok(self)
+    }
+
+    // Every mapping in a stable order, so saved stores built with another map
+    // are not reused
+    pub fn fingerprint(&self) -> String {
+        let sorted = |map: &HashMap<String, String>| {
+            let mut pairs: Vec<String> = map.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+            pairs.sort();
+            pairs.join(",")
+        };
+        format!("gender[{}] country[{}]", sorted(&self.gender), sorted(&self.country))
+    }
+}
+
+// Applies a normalization map, warning once per value it has no mapping for
+pub struct Normalizer<'a> {
+    map: &'a NormalizationMap,
+    canonical: HashSet<&'a str>,
+    warned: HashSet<String>,
+}
+
+impl<'a> Normalizer<'a> {
+    pub fn new(map: &'a NormalizationMap) -> Self {
+        let canonical = map.gender.values().chain(map.country.values()).map(String::as_str).collect();
+        Self { map, canonical, warned: HashSet::new() }
+    }
+
+    fn canonicalize(&mut self, field: &str, table: &HashMap<String, String>, value: &mut String) {
+        match table.get(&value.trim().to_lowercase()) {
+            Some(canonical) => *value = canonical.clone(),
+            None if self.canonical.contains(value.as_str()) => {}
+            None => {
+                if self.warned.insert(format!("{}:{}", field, value)) {
+                    tracing::warn!("No normalization mapping for {} value '{}'", field, value);
+                }
+            }
+        }
+    }
+
+    pub fn apply(&mut self, customer: &mut CustomerFeedback) {
+        let map = self.map;
+        self.canonicalize("gender", &map.gender, &mut customer.gender);
+        self.canonicalize("country", &map.country, &mut customer.country);
+    }
+}
@@ -0,0 +1,266 @@
+use clap::ValueEnum;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::HashMap;
+
+// Leading bytes of a binary file, followed by a format version byte
+const BINARY_MAGIC: &[u8; 8] = b"RIGSTORE";
+const BINARY_VERSION: u8 = 1;
+// Same layout for document files, whose JSON header precedes the vectors
+const DOCUMENTS_MAGIC: &[u8; 8] = b"RIGDOCS\0";
+
+// Encoding of persisted embedding files. Both are self-identifying (a magic
+// header or a `format` field), so reading never needs to be told the format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum StoreFormat {
+    /// Compact little-endian encoding; about a quarter of the JSON size and
+    /// much faster to read
+    Binary,
+    /// Human-readable JSON, for inspection
+    Json,
+}
+
+#[derive(Serialize, Deserialize)]
+struct JsonFile {
+    format: String,
+    version: u8,
+    entries: HashMap<String, Vec<f64>>,
+}
+
+pub fn encode(entries: &HashMap<String, Vec<f64>>, format: StoreFormat) -> Result<Vec<u8>, anyhow::Error> {
+    match format {
+        StoreFormat::Json => Ok(serde_json::to_vec(&JsonFile {
+            format: "json".to_string(),
+            version: 1,
+            entries: entries.clone(),
+        })?),
+        StoreFormat::Binary => {
+            let mut out = Vec::with_capacity(
+                BINARY_MAGIC.len() + 9 + entries.iter().map(|(k, v)| 8 + k.len() + v.len() * 8).sum::<usize>(),
+            );
+            out.extend_from_slice(BINARY_MAGIC);
+            out.push(BINARY_VERSION);
+            out.extend_from_slice(&(entries.len() as u64).to_le_bytes());
+            for (key, vector) in entries {
+                out.extend_from_slice(&(key.len() as u32).to_le_bytes());
+                out.extend_from_slice(key.as_bytes());
+                out.extend_from_slice(&(vector.len() as u32).to_le_bytes());
+                for value in vector {
+                    out.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+            Ok(out)
+        }
+    }
+}
+
+// Read a file in either format, reporting which one it was. JSON files from
+// before the format header (a bare map of entries) are still accepted.
+pub fn decode(bytes: &[u8]) -> Result<(StoreFormat, HashMap<String, Vec<f64>>), anyhow::Error> {
+    let Some(rest) = bytes.strip_prefix(BINARY_MAGIC.as_slice()) else {
+        let value: serde_json::Value = serde_json::from_slice(bytes)?;
+        let entries = match value.get("format") {
+            Some(format) if format == "json" => serde_json::from_value::<JsonFile>(value)?.entries,
+            Some(format) => return Err(anyhow::anyhow!("Unsupported store format {}", format)),
+            None => serde_json::from_value(value)?,
+        };
+        return Ok((StoreFormat::Json, entries));
+    };
+
+    let mut reader = BinaryReader { bytes: rest };
+    let version = reader.take(1)?[0];
+    if version != BINARY_VERSION {
+        return Err(anyhow::anyhow!("Unsupported binary store version {} (expected {})", version, BINARY_VERSION));
+    }
+    let count = u64::from_le_bytes(reader.take(8)?.try_into()?);
+    let mut entries = HashMap::new();
+    for _ in 0..count {
+        let key_len = reader.u32()? as usize;
+        let key = String::from_utf8(reader.take(key_len)?.to_vec())?;
+        let dims = reader.u32()? as usize;
+        let vector = reader
+            .take(dims * 8)?
+            .chunks_exact(8)
+            .map(|chunk| f64::from_le_bytes(chunk.try_into().unwrap()))
+            .collect();
+        entries.insert(key, vector);
+    }
+    Ok((StoreFormat::Binary, entries))
+}
+
+#[derive(Serialize, Deserialize)]
+struct JsonDocuments<T> {
+    format: String,
+    version: u8,
+    header: T,
+    vectors: Vec<Vec<f64>>,
+}
+
+// A file of documents: a serializable header describing them and their vectors
+// in order. Binary files keep the header as JSON, but the vectors, which are
+// nearly all of the size, use the compact encoding.
+pub fn encode_documents<T: Serialize>(header: &T, vectors: &[Vec<f64>], format: StoreFormat) -> Result<Vec<u8>, anyhow::Error> {
+    match format {
+        StoreFormat::Json => Ok(serde_json::to_vec(&JsonDocuments {
+            format: "json".to_string(),
+            version: 1,
+            header,
+            vectors: vectors.to_vec(),
+        })?),
+        StoreFormat::Binary => {
+            let header = serde_json::to_vec(header)?;
+            let mut out = Vec::with_capacity(
+                DOCUMENTS_MAGIC.len() + 13 + header.len() + vectors.iter().map(|v| 4 + v.len() * 8).sum::<usize>(),
+            );
+            out.extend_from_slice(DOCUMENTS_MAGIC);
+            out.push(BINARY_VERSION);
+            out.extend_from_slice(&(header.len() as u32).to_le_bytes());
+            out.extend_from_slice(&header);
+            out.extend_from_slice(&(vectors.len() as u64).to_le_bytes());
+            for vector in vectors {
+                out.extend_from_slice(&(vector.len() as u32).to_le_bytes());
+                for value in vector {
+                    out.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+            Ok(out)
+        }
+    }
+}
+
+// Read a document file in either format
+pub fn decode_documents<T: DeserializeOwned>(bytes: &[u8]) -> Result<(T, Vec<Vec<f64>>), anyhow::Error> {
+    let Some(rest) = bytes.strip_prefix(DOCUMENTS_MAGIC.as_slice()) else {
+        let file: JsonDocuments<T> = serde_json::from_slice(bytes)?;
+        if file.format != "json" {
+            return Err(anyhow::anyhow!("Unsupported store format {}", file.format));
+        }
+        return Ok((file.header, file.vectors));
+    };
+
+    let mut reader = BinaryReader { bytes: rest };
+    let version = reader.take(1)?[0];
+    if version != BINARY_VERSION {
+        return Err(anyhow::anyhow!("Unsupported binary store version {} (expected {})", version, BINARY_VERSION));
+    }
+    let header_len = reader.u32()? as usize;
+    let header = serde_json::from_slice(reader.take(header_len)?)?;
+    let count = u64::from_le_bytes(reader.take(8)?.try_into()?);
+    let mut vectors = Vec::new();
+    for _ in 0..count {
+        let dims = reader.u32()? as usize;
+        vectors.push(
+            reader
+                .take(dims * 8)?
+                .chunks_exact(8)
+                .map(|chunk| f64::from_le_bytes(chunk.try_into().unwrap()))
+                .collect(),
+        );
+    }
+    Ok((header, vectors))
+}
+
+struct BinaryReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> BinaryReader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], anyhow::Error> {
+        if self.bytes.len() < n {
+            return Err(anyhow::anyhow!("Binary store file is truncated"));
+        }
+        let (taken, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u32(&mut self) -> Result<u32, anyhow::Error> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries() -> HashMap<String, Vec<f64>> {
+        HashMap::from([
+            ("first summary".to_string(), vec![0.25, -1.5, 3.0e-12]),
+            ("second, with ünïcode".to_string(), vec![f64::MAX, 0.0, -0.0]),
+            ("empty".to_string(), vec![]),
+        ])
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Header {
+        identity: String,
+        ids: Vec<String>,
+    }
+
+    fn documents() -> (Header, Vec<Vec<f64>>) {
+        let header = Header { identity: "abc123".to_string(), ids: vec!["a-1".to_string(), "b-2".to_string()] };
+        (header, vec![vec![0.1, 0.2, 0.3], vec![-4.0, 5.5, 1.0e300]])
+    }
+
+    #[test]
+    fn entries_round_trip_in_both_formats() {
+        for format in [StoreFormat::Binary, StoreFormat::Json] {
+            let bytes = encode(&entries(), format).unwrap();
+            let (read_format, decoded) = decode(&bytes).unwrap();
+            assert_eq!(read_format, format);
+            assert_eq!(decoded, entries());
+        }
+    }
+
+    #[test]
+    fn documents_round_trip_in_both_formats() {
+        let (header, vectors) = documents();
+        for format in [StoreFormat::Binary, StoreFormat::Json] {
+            let bytes = encode_documents(&header, &vectors, format).unwrap();
+            let (decoded_header, decoded_vectors): (Header, _) = decode_documents(&bytes).unwrap();
+            assert_eq!(decoded_header, header);
+            assert_eq!(decoded_vectors, vectors);
+        }
+    }
+
+    #[test]
+    fn legacy_bare_map_json_is_read() {
+        let bytes = serde_json::to_vec(&entries()).unwrap();
+        let (format, decoded) = decode(&bytes).unwrap();
+        assert_eq!(format, StoreFormat::Json);
+        assert_eq!(decoded, entries());
+    }
+
+    #[test]
+    fn truncated_files_are_rejected() {
+        let bytes = encode(&entries(), StoreFormat::Binary).unwrap();
+        for len in [BINARY_MAGIC.len(), BINARY_MAGIC.len() + 5, bytes.len() - 1] {
+            let err = decode(&bytes[..len]).unwrap_err();
+            assert_eq!(err.to_string(), "Binary store file is truncated");
+        }
+
+        let (header, vectors) = documents();
+        let bytes = encode_documents(&header, &vectors, StoreFormat::Binary).unwrap();
+        let err = decode_documents::<Header>(&bytes[..bytes.len() - 3]).unwrap_err();
+        assert_eq!(err.to_string(), "Binary store file is truncated");
+
+        let bytes = encode_documents(&header, &vectors, StoreFormat::Json).unwrap();
+        assert!(decode_documents::<Header>(&bytes[..bytes.len() / 2]).is_err());
+    }
+
+    #[test]
+    fn other_versions_are_rejected() {
+        let mut bytes = encode(&entries(), StoreFormat::Binary).unwrap();
+        bytes[BINARY_MAGIC.len()] = BINARY_VERSION + 1;
+        let err = decode(&bytes).unwrap_err();
+        assert_eq!(err.to_string(), "Unsupported binary store version 2 (expected 1)");
+
+        let (header, vectors) = documents();
+        let mut bytes = encode_documents(&header, &vectors, StoreFormat::Binary).unwrap();
+        bytes[DOCUMENTS_MAGIC.len()] = 9;
+        let err = decode_documents::<Header>(&bytes).unwrap_err();
+        assert_eq!(err.to_string(), "Unsupported binary store version 9 (expected 1)");
+
+        let err = decode(br#"{"format": "msgpack", "version": 1, "entries": {}}"#).unwrap_err();
+        assert_eq!(err.to_string(), r#"Unsupported store format "msgpack""#);
+    }
+}
@@ -0,0 +1,137 @@
+use clap::ValueEnum;
+use std::collections::HashSet;
+
+// A cleanup step applied to the agent's analysis before it is output
+pub type PostProcessor = Box<dyn Fn(String) -> String + Send + Sync>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum BuiltinPostProcessor {
+    /// Remove markdown headings, emphasis and code markers, normalizing bullets to "- "
+    MarkdownStrip,
+    /// Replace any customer ID from the dataset that the model echoed with [REDACTED]
+    PiiRedact,
+}
+
+// Output format every analysis in a run should share: asked for in the
+// preamble, then enforced on the response in case the model drifts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum AnalysisFormat {
+    /// `## ` section headings, `- ` bullets and **bold** key figures
+    Markdown,
+    /// Plain prose with no markdown syntax
+    Plain,
+}
+
+impl AnalysisFormat {
+    pub fn directive(self) -> &'static str {
+        match self {
+            AnalysisFormat::Markdown => {
+                "\n    Format the analysis in markdown: a `## ` heading for each section, `- ` bullets beneath it,\n    and **bold** only for key figures. Do not use other heading levels or numbered lists.\n"
+            }
+            AnalysisFormat::Plain => {
+                "\n    Write the analysis as plain prose, one short paragraph per section, with no markdown:\n    no headings, bullets, bold, italics or code formatting.\n"
+            }
+        }
+    }
+
+    pub fn enforcer(self) -> PostProcessor {
+        match self {
+            AnalysisFormat::Markdown => Box::new(normalize_markdown),
+            AnalysisFormat::Plain => Box::new(|text| {
+                strip_markdown(text)
+                    .lines()
+                    .map(|line| line.trim_start().strip_prefix("- ").unwrap_or(line))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            }),
+        }
+    }
+}
+
+// Bring markdown to one dialect: `- ` bullets, `**` bold and `## ` headings
+pub fn normalize_markdown(text: String) -> String {
+    text.lines()
+        .map(|line| {
+            let indent = line.len() - line.trim_start().len();
+            let trimmed = line.trim_start();
+            let normalized = if let Some(rest) = trimmed.strip_prefix("* ").or_else(|| trimmed.strip_prefix("+ ")) {
+                format!("- {}", rest)
+            } else if trimmed.starts_with('#') {
+                format!("## {}", trimmed.trim_start_matches('#').trim_start())
+            } else {
+                trimmed.to_string()
+            };
+            format!("{}{}", &line[..indent], normalized.replace("__", "**"))
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+pub fn strip_markdown(text: String) -> String {
+    text.lines()
+        .map(|line| {
+            let indent = line.len() - line.trim_start().len();
+            let trimmed = line.trim_start();
+            let trimmed = trimmed.trim_start_matches('#').trim_start();
+            let trimmed = match trimmed.strip_prefix("* ").or_else(|| trimmed.strip_prefix("+ ")) {
+                Some(rest) => format!("- {}", rest),
+                None => trimmed.to_string(),
+            };
+            let cleaned = trimmed.replace("**", "").replace("__", "").replace('`', "");
+            format!("{}{}", &line[..indent], cleaned)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// Replace whole tokens that match a known customer ID
+pub fn redact_ids(text: &str, ids: &HashSet<String>) -> String {
+    let is_id_char = |c: char| c.is_alphanumeric() || c == '-' || c == '_';
+    let mut output = String::with_capacity(text.len());
+    let mut token_start = None;
+
+    for (i, c) in text.char_indices().chain(std::iter::once((text.len(), ' '))) {
+        match (is_id_char(c) && i < text.len(), token_start) {
+            (true, None) => token_start = Some(i),
+            (true, Some(_)) => {}
+            (false, start) => {
+                if let Some(start) = start {
+                    let token = &text[start..i];
+                    output.push_str(if ids.contains(token) { "[REDACTED]" } else { token });
+                    token_start = None;
+                }
+                if i < text.len() {
+                    output.push(c);
+                }
+            }
+        }
+    }
+
+    output
+}
+
+pub fn builtin(kind: BuiltinPostProcessor, customer_ids: &HashSet<String>) -> PostProcessor {
+    match kind {
+        BuiltinPostProcessor::MarkdownStrip => Box::new(strip_markdown),
+        BuiltinPostProcessor::PiiRedact => {
+            let ids = customer_ids.clone();
+            Box::new(move |text| redact_ids(&text, &ids))
+        }
+    }
+}
+
+// Applies processors in the order they were added
+#[derive(Default)]
+pub struct PostProcessChain {
+    processors: Vec<PostProcessor>,
+}
+
+impl PostProcessChain {
+    pub fn push(&mut self, processor: PostProcessor) {
+        self.processors.push(processor);
+    }
+
+    pub fn apply(&self, text: String) -> String {
+        self.processors.iter().fold(text, |text, processor| processor(text))
+    }
+}
@@ -0,0 +1,52 @@
+use rig::embeddings::{Embedding, EmbeddingError, EmbeddingModel};
+
+// Prepends a fixed instruction to every text before it is embedded, for
+// instruction-tuned models that expect e.g. "query: " or "passage: ". The
+// returned embeddings keep the caller's original text as their document, so
+// matching vectors back to records by text is unaffected.
+#[derive(Clone)]
+pub struct PrefixedEmbedding<M> {
+    model: M,
+    prefix: String,
+}
+
+impl<M: EmbeddingModel> PrefixedEmbedding<M> {
+    pub fn new(model: M, prefix: &str) -> Self {
+        Self { model, prefix: prefix.to_string() }
+    }
+}
+
+impl<M: EmbeddingModel> EmbeddingModel for PrefixedEmbedding<M> {
+    const MAX_DOCUMENTS: usize = M::MAX_DOCUMENTS;
+
+    fn ndims(&self) -> usize {
+        self.model.ndims()
+    }
+
+    async fn embed_texts(
+        &self,
+        texts: impl IntoIterator<Item = String> + Send,
+    ) -> Result<Vec<Embedding>, EmbeddingError> {
+        if self.prefix.is_empty() {
+            return self.model.embed_texts(texts).await;
+        }
+        let texts: Vec<String> = texts.into_iter().map(|text| format!("{}{}", self.prefix, text)).collect();
+        let mut embeddings = self.model.embed_texts(texts).await?;
+        for embedding in &mut embeddings {
+            if let Some(original) = embedding.document.strip_prefix(self.prefix.as_str()) {
+                embedding.document = original.to_string();
+            }
+        }
+        Ok(embeddings)
+    }
+}
+
+// Model identity for the summary cache: vectors embedded with a document prefix
+// are different vectors, so they must not be served to runs without it
+pub fn cache_identity(model: &str, document_prefix: &str) -> String {
+    if document_prefix.is_empty() {
+        model.to_string()
+    } else {
+        format!("{} with document prefix {:?}", model, document_prefix)
+    }
+}
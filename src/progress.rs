@@ -0,0 +1,59 @@
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use std::io::{self, Write};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+// Every bar is drawn through one MultiProgress so that log lines and status
+// messages can be printed above it without tearing. Bars are hidden when
+// stderr is not a terminal, leaving redirected output unchanged.
+fn bars() -> &'static MultiProgress {
+    static BARS: OnceLock<MultiProgress> = OnceLock::new();
+    BARS.get_or_init(MultiProgress::new)
+}
+
+// Print a line above any bar on screen
+pub fn println(line: impl AsRef<str>) {
+    bars().suspend(|| println!("{}", line.as_ref()));
+}
+
+pub fn eprintln(line: impl AsRef<str>) {
+    bars().suspend(|| eprintln!("{}", line.as_ref()));
+}
+
+// Bar over the embedding chunks, with the embedded record count as its
+// message. Streamed loads don't know the chunk count and get a spinner.
+pub fn chunk_bar(total_chunks: Option<usize>) -> ProgressBar {
+    let bar = match total_chunks {
+        Some(total) => ProgressBar::new(total as u64).with_style(
+            ProgressStyle::with_template("{bar:40} {pos}/{len} chunks [{elapsed_precise}, eta {eta}] {msg}")
+                .expect("valid progress template"),
+        ),
+        None => ProgressBar::new_spinner().with_style(
+            ProgressStyle::with_template("{spinner} {pos} chunks [{elapsed_precise}] {msg}").expect("valid progress template"),
+        ),
+    };
+    let bar = bars().add(bar);
+    bar.enable_steady_tick(Duration::from_millis(200));
+    bar
+}
+
+// Spinner shown while waiting on an analysis
+pub fn query_spinner(message: String) -> ProgressBar {
+    let spinner = bars().add(ProgressBar::new_spinner().with_message(message));
+    spinner.enable_steady_tick(Duration::from_millis(100));
+    spinner
+}
+
+// Writer for the tracing subscriber, so warnings logged while a bar is
+// drawn appear above it
+pub struct LogWriter;
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        bars().suspend(|| io::stdout().write(buf))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        bars().suspend(|| io::stdout().flush())
+    }
+}
@@ -0,0 +1,142 @@
+use crate::cache::sha256_hex;
+use rig::embeddings::{Embedding, EmbeddingError, EmbeddingModel};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::Arc;
+
+// Random projection to fewer dimensions. Each output component is a ±1
+// combination of the inputs scaled by 1/sqrt(output_dims), which keeps
+// distances and cosine similarities approximately intact (Johnson-Lindenstrauss)
+// at a fraction of the memory.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Projection {
+    input_dims: usize,
+    output_dims: usize,
+    // output_dims rows of input_dims signs
+    signs: Vec<Vec<i8>>,
+    #[serde(skip)]
+    fingerprint: String,
+}
+
+// splitmix64, enough to draw the signs without a rand dependency
+pub fn next_random(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+impl Projection {
+    fn random(input_dims: usize, output_dims: usize) -> Self {
+        let mut state = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_nanos() as u64)
+            .unwrap_or(0);
+        let signs = (0..output_dims)
+            .map(|_| (0..input_dims).map(|_| if next_random(&mut state) & 1 == 0 { 1 } else { -1 }).collect())
+            .collect();
+        Self { input_dims, output_dims, signs, fingerprint: String::new() }
+    }
+
+    // Reuse the projection saved at `path`, or create and save one. Stored
+    // vectors (including the summary cache) are only comparable with queries
+    // projected by the same matrix, so a saved one with other dimensions is an
+    // error rather than silently replaced.
+    pub fn load_or_create(path: &Path, input_dims: usize, output_dims: usize) -> Result<Self, anyhow::Error> {
+        if output_dims == 0 || output_dims >= input_dims {
+            return Err(anyhow::anyhow!(
+                "--reduce-dims must be between 1 and {} (the model's dimensions)",
+                input_dims - 1
+            ));
+        }
+        let content = if path.exists() {
+            let content = std::fs::read(path)?;
+            let saved: Projection = serde_json::from_slice(&content)
+                .map_err(|e| anyhow::anyhow!("{} is not a projection file: {}", path.display(), e))?;
+            if (saved.input_dims, saved.output_dims) != (input_dims, output_dims) {
+                return Err(anyhow::anyhow!(
+                    "{} projects {} to {} dimensions, but this run needs {} to {}; pass another --projection-file or delete it (and any summary cache built with it)",
+                    path.display(),
+                    saved.input_dims,
+                    saved.output_dims,
+                    input_dims,
+                    output_dims
+                ));
+            }
+            if saved.signs.len() != output_dims || saved.signs.iter().any(|row| row.len() != input_dims) {
+                return Err(anyhow::anyhow!("{} has a malformed projection matrix", path.display()));
+            }
+            println!("Loaded projection from {} ({} to {} dimensions)", path.display(), input_dims, output_dims);
+            content
+        } else {
+            let content = serde_json::to_vec(&Self::random(input_dims, output_dims))?;
+            std::fs::write(path, &content)?;
+            println!("Created projection from {} to {} dimensions, saved to {}", input_dims, output_dims, path.display());
+            content
+        };
+        let mut projection: Projection = serde_json::from_slice(&content)?;
+        projection.fingerprint = sha256_hex(&content);
+        Ok(projection)
+    }
+
+    pub fn input_dims(&self) -> usize {
+        self.input_dims
+    }
+
+    pub fn output_dims(&self) -> usize {
+        self.output_dims
+    }
+
+    // Identifies the matrix, so cached vectors from another one are not reused
+    pub fn fingerprint(&self) -> &str {
+        &self.fingerprint
+    }
+
+    // Vectors of any other length (e.g. already projected) are returned unchanged
+    pub fn project(&self, vec: &[f64]) -> Vec<f64> {
+        if vec.len() != self.input_dims {
+            return vec.to_vec();
+        }
+        let scale = 1.0 / (self.output_dims as f64).sqrt();
+        self.signs
+            .iter()
+            .map(|row| row.iter().zip(vec).map(|(&sign, &x)| sign as f64 * x).sum::<f64>() * scale)
+            .collect()
+    }
+}
+
+// Projects every embedding the wrapped model returns; without a projection it
+// passes vectors through untouched
+#[derive(Clone)]
+pub struct ProjectedEmbedding<M> {
+    model: M,
+    projection: Option<Arc<Projection>>,
+}
+
+impl<M: EmbeddingModel> ProjectedEmbedding<M> {
+    pub fn new(model: M, projection: Option<Arc<Projection>>) -> Self {
+        Self { model, projection }
+    }
+}
+
+impl<M: EmbeddingModel> EmbeddingModel for ProjectedEmbedding<M> {
+    const MAX_DOCUMENTS: usize = M::MAX_DOCUMENTS;
+
+    fn ndims(&self) -> usize {
+        self.projection.as_ref().map_or_else(|| self.model.ndims(), |projection| projection.output_dims())
+    }
+
+    async fn embed_texts(
+        &self,
+        texts: impl IntoIterator<Item = String> + Send,
+    ) -> Result<Vec<Embedding>, EmbeddingError> {
+        let mut embeddings = self.model.embed_texts(texts).await?;
+        if let Some(projection) = &self.projection {
+            for embedding in &mut embeddings {
+                embedding.vec = projection.project(&embedding.vec);
+            }
+        }
+        Ok(embeddings)
+    }
+}
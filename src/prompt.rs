@@ -0,0 +1,251 @@
+use crate::confidence;
+use crate::errors::PipelineError;
+use crate::models::{AnalysisRequest, CustomerFeedback, RetrievedProfile};
+use crate::record::EmbeddableRecord;
+use crate::redact::{ProfileField, Redaction};
+use crate::tokens;
+use clap::ValueEnum;
+use rig::vector_store::VectorStoreError;
+
+// Turns a query and its retrieved profiles (score, record) into the prompt text
+// sent to the analysis agent
+pub trait PromptBuilder<T = CustomerFeedback>: Send + Sync {
+    fn build(&self, query: &str, profiles: &[(f64, &T)], redaction: &Redaction) -> String;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum PromptFormat {
+    /// One bulleted block per profile
+    Blocks,
+    /// A Markdown table with one row per profile
+    Table,
+    /// The profiles as a JSON array
+    Json,
+    /// One short line per profile
+    Minimal,
+}
+
+impl PromptFormat {
+    pub fn builder<T: EmbeddableRecord>(self) -> &'static dyn PromptBuilder<T> {
+        match self {
+            PromptFormat::Blocks => &BlockPrompt,
+            PromptFormat::Table => &TablePrompt,
+            PromptFormat::Json => &JsonPrompt,
+            PromptFormat::Minimal => &MinimalPrompt,
+        }
+    }
+}
+
+// How retrieved profiles are rendered into the analysis prompt
+#[derive(Debug, Clone)]
+pub struct PromptOptions {
+    pub max_context_tokens: usize,
+    pub redaction: Redaction,
+    pub format: PromptFormat,
+    // Per-profile similarity threshold and expected profile count for the
+    // confidence score; None leaves it uncomputed
+    pub confidence: Option<(f64, usize)>,
+    // State the confidence in the prompt so the agent can hedge
+    pub hedge_low_confidence: bool,
+}
+
+// Profile block as it appears in the analysis prompt
+pub fn format_profile(position: usize, score: f64, profile: &CustomerFeedback, redaction: &Redaction) -> String {
+    let demographics: Vec<String> = [
+        redaction.show(ProfileField::Age, profile.age.to_string()).map(|age| format!("{} year old", age)),
+        redaction.show(ProfileField::Gender, profile.gender.clone()),
+        redaction.show(ProfileField::Country, profile.country.clone()).map(|country| format!("from {}", country)),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+
+    let lines = [
+        Some(format!("Similarity Score: {:.3}", score)),
+        redaction.show(ProfileField::CustomerId, profile.customer_id.clone()).map(|id| format!("Customer ID: {}", id)),
+        (!demographics.is_empty()).then(|| format!("Demographics: {}", demographics.join(" "))),
+        redaction.show(ProfileField::Income, profile.income_display()).map(|v| format!("Income: {}", v)),
+        redaction.show(ProfileField::Satisfaction, profile.satisfaction_display()).map(|v| format!("Satisfaction: {}", v)),
+        redaction.show(ProfileField::Loyalty, profile.loyalty_level.clone()).map(|v| format!("Loyalty Level: {}", v)),
+        redaction
+            .show(ProfileField::PurchaseFrequency, profile.purchase_frequency.to_string())
+            .map(|v| format!("Purchase Frequency: {} purchases/year", v)),
+        redaction.show(ProfileField::ProductQuality, profile.product_quality.to_string()).map(|v| format!("Product Quality: {}/10", v)),
+        redaction.show(ProfileField::ServiceQuality, profile.service_quality.to_string()).map(|v| format!("Service Quality: {}/10", v)),
+        redaction.show(ProfileField::FeedbackScore, profile.feedback_score.clone()).map(|v| format!("Feedback Score: {}", v)),
+    ];
+
+    let mut block = format!("Profile {}:\n", position);
+    for line in lines.into_iter().flatten() {
+        block.push_str("* ");
+        block.push_str(&line);
+        block.push('\n');
+    }
+    block
+}
+
+// Labelled field values left after redaction, in prompt order. Redaction
+// drops the same fields from every profile, so rows line up across profiles.
+pub fn visible_fields(profile: &CustomerFeedback, redaction: &Redaction) -> Vec<(&'static str, String)> {
+    [
+        (ProfileField::CustomerId, "Customer ID", profile.customer_id.clone()),
+        (ProfileField::Age, "Age", profile.age.to_string()),
+        (ProfileField::Gender, "Gender", profile.gender.clone()),
+        (ProfileField::Country, "Country", profile.country.clone()),
+        (ProfileField::Income, "Income", profile.income_display()),
+        (ProfileField::Satisfaction, "Satisfaction", profile.satisfaction_display()),
+        (ProfileField::Loyalty, "Loyalty", profile.loyalty_level.clone()),
+        (ProfileField::PurchaseFrequency, "Purchases/Year", profile.purchase_frequency.to_string()),
+        (ProfileField::ProductQuality, "Product Quality", format!("{}/10", profile.product_quality)),
+        (ProfileField::ServiceQuality, "Service Quality", format!("{}/10", profile.service_quality)),
+        (ProfileField::FeedbackScore, "Feedback", profile.feedback_score.clone()),
+    ]
+    .into_iter()
+    .filter_map(|(field, label, value)| redaction.show(field, value).map(|value| (label, value)))
+    .collect()
+}
+
+// The original layout: a bulleted block per profile, for any record type
+pub fn block_prompt<T: EmbeddableRecord>(query: &str, profiles: &[(f64, &T)], redaction: &Redaction) -> String {
+    let blocks: String = profiles
+        .iter()
+        .enumerate()
+        .map(|(i, (score, profile))| profile.format_for_prompt(*score, i + 1, redaction))
+        .collect();
+    format!("Analysis Query: {}\n\nRelevant Customer Profiles ({} found):\n{}", query, profiles.len(), blocks)
+}
+
+pub struct BlockPrompt;
+
+impl<T: EmbeddableRecord> PromptBuilder<T> for BlockPrompt {
+    fn build(&self, query: &str, profiles: &[(f64, &T)], redaction: &Redaction) -> String {
+        block_prompt(query, profiles, redaction)
+    }
+}
+
+// Column headers once, then a row per profile; the most compact structured layout
+pub struct TablePrompt;
+
+impl<T: EmbeddableRecord> PromptBuilder<T> for TablePrompt {
+    fn build(&self, query: &str, profiles: &[(f64, &T)], redaction: &Redaction) -> String {
+        let rows: Vec<Vec<(&str, String)>> = profiles
+            .iter()
+            .map(|(score, profile)| {
+                let mut row = vec![("Score", format!("{:.3}", score))];
+                row.extend(profile.prompt_fields(redaction));
+                row
+            })
+            .collect();
+        let header: Vec<&str> = rows.first().map(|row| row.iter().map(|(label, _)| *label).collect()).unwrap_or_default();
+
+        let mut table = format!("| {} |\n|{}\n", header.join(" | "), " --- |".repeat(header.len()));
+        for row in &rows {
+            let cells: Vec<&str> = row.iter().map(|(_, value)| value.as_str()).collect();
+            table.push_str(&format!("| {} |\n", cells.join(" | ")));
+        }
+        format!("Analysis Query: {}\n\nRelevant Customer Profiles ({} found):\n{}", query, profiles.len(), table)
+    }
+}
+
+// Profiles as a JSON array, for comparing how the agent handles structured input
+pub struct JsonPrompt;
+
+impl<T: EmbeddableRecord> PromptBuilder<T> for JsonPrompt {
+    fn build(&self, query: &str, profiles: &[(f64, &T)], redaction: &Redaction) -> String {
+        let objects: Vec<serde_json::Value> = profiles
+            .iter()
+            .map(|(score, profile)| {
+                let mut object = serde_json::Map::new();
+                object.insert("similarity".to_string(), serde_json::json!((score * 1000.0).round() / 1000.0));
+                for (label, value) in profile.prompt_fields(redaction) {
+                    object.insert(label.to_string(), serde_json::Value::String(value));
+                }
+                serde_json::Value::Object(object)
+            })
+            .collect();
+        let json = serde_json::to_string_pretty(&objects).unwrap_or_default();
+        format!("Analysis Query: {}\n\nRelevant Customer Profiles (JSON, {} found):\n{}\n", query, profiles.len(), json)
+    }
+}
+
+// Values only, one line per profile, for the lowest token cost
+pub struct MinimalPrompt;
+
+impl<T: EmbeddableRecord> PromptBuilder<T> for MinimalPrompt {
+    fn build(&self, query: &str, profiles: &[(f64, &T)], redaction: &Redaction) -> String {
+        let lines: String = profiles
+            .iter()
+            .enumerate()
+            .map(|(i, (score, profile))| {
+                let values: Vec<String> = profile
+                    .prompt_fields(redaction)
+                    .into_iter()
+                    .map(|(label, value)| format!("{} {}", label.to_lowercase(), value))
+                    .collect();
+                format!("{}. ({:.3}) {}\n", i + 1, score, values.join("; "))
+            })
+            .collect();
+        format!("{}\n\nProfiles:\n{}", query, lines)
+    }
+}
+
+// Turn a query and its retrieval result into the prompt sent to the analysis
+// agent with the configured builder, trimming profiles to the context budget.
+// A failed lookup is an error, so no prompt is built for it. A query left
+// with no profiles, by --min-score or the context budget, is still sent with a
+// warning in their place.
+pub fn build_analysis_request<T: EmbeddableRecord>(
+    query: &str,
+    maybe_profiles: Result<Vec<(f64, String, T)>, VectorStoreError>,
+    options: &PromptOptions,
+) -> Result<AnalysisRequest<T>, PipelineError> {
+    match maybe_profiles {
+        Ok(mut profiles) => {
+            // Drop the lowest-scoring profiles until the profile part of the
+            // prompt fits the budget, whatever the format
+            let builder = options.format.builder::<T>();
+            let base = tokens::estimate_tokens(&builder.build(query, &[], &options.redaction));
+            let total = profiles.len();
+            while !profiles.is_empty() {
+                let candidates: Vec<(f64, &T)> =
+                    profiles.iter().map(|(score, _, profile)| (*score, profile)).collect();
+                let prompt = builder.build(query, &candidates, &options.redaction);
+                if tokens::estimate_tokens(&prompt).saturating_sub(base) <= options.max_context_tokens {
+                    break;
+                }
+                profiles.pop();
+            }
+            if profiles.len() < total {
+                tracing::warn!(
+                    "Profile context exceeds ~{} tokens; dropped {} lowest-scoring of {} profiles",
+                    options.max_context_tokens,
+                    total - profiles.len(),
+                    total
+                );
+            }
+            let retrieved: Vec<RetrievedProfile> = profiles
+                .iter()
+                .map(|(score, _, profile)| RetrievedProfile {
+                    customer_id: profile.record_id().to_string(),
+                    score: *score,
+                })
+                .collect();
+            let confidence = options
+                .confidence
+                .map(|(threshold, top_k)| confidence::assess(&retrieved, threshold, top_k));
+            let mut prompt = if profiles.is_empty() {
+                format!("Analysis Query: {}\n\nWarning: No relevant customer profiles found.", query)
+            } else {
+                let candidates: Vec<(f64, &T)> =
+                    profiles.iter().map(|(score, _, profile)| (*score, profile)).collect();
+                builder.build(query, &candidates, &options.redaction)
+            };
+            if let (true, Some(confidence)) = (options.hedge_low_confidence, &confidence) {
+                prompt.push_str(&format!("\nRetrieval confidence: {}\n", confidence));
+            }
+            let documents = profiles.into_iter().map(|(_, _, profile)| profile).collect();
+            Ok(AnalysisRequest { query: query.to_string(), prompt, profiles: retrieved, documents, confidence })
+        }
+        Err(err) => Err(PipelineError::Lookup(err)),
+    }
+}
@@ -0,0 +1,259 @@
+use clap::ValueEnum;
+use rig::{
+    agent::AgentBuilder,
+    completion::{self, CompletionError, CompletionModel, CompletionRequest},
+    embeddings::{Embedding, EmbeddingError, EmbeddingModel},
+    extractor::ExtractorBuilder,
+    providers::{cohere, openai},
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Duration;
+
+// Request pattern for one provider. Unset fields keep the tool's defaults.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProviderSettings {
+    // Per-request timeout; a timed-out request counts as a transient failure
+    pub timeout_secs: Option<f64>,
+    // Requests allowed in flight at once
+    pub max_in_flight: Option<usize>,
+    // Retries per request for transient failures
+    pub retries: Option<usize>,
+    // Pause after each request (embedding) or query (completion)
+    pub delay_ms: Option<u64>,
+}
+
+impl ProviderSettings {
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout_secs.map(Duration::from_secs_f64)
+    }
+
+    pub fn max_in_flight(&self) -> usize {
+        self.max_in_flight.unwrap_or(1).max(1)
+    }
+
+    pub fn retries(&self, default: usize) -> usize {
+        self.retries.unwrap_or(default)
+    }
+
+    pub fn delay(&self, default: Duration) -> Duration {
+        self.delay_ms.map(Duration::from_millis).unwrap_or(default)
+    }
+}
+
+// `[embedding]` and `[completion]` tables from --provider-config
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProviderConfig {
+    // API root replacing the provider's own, for compatible or proxied backends
+    pub base_url: Option<String>,
+    pub embedding: ProviderSettings,
+    pub completion: ProviderSettings,
+}
+
+// Where requests for a provider go and the key they carry. The rig client and
+// the --stream requests are both built from it, so they reach the same API.
+#[derive(Debug, Clone)]
+pub struct Endpoint {
+    pub provider: Provider,
+    pub base_url: String,
+    pub api_key: String,
+}
+
+impl ProviderConfig {
+    pub fn from_file(path: &Path) -> Result<Self, anyhow::Error> {
+        let content = std::fs::read_to_string(path)?;
+        let config: ProviderConfig = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => toml::from_str(&content)?,
+            Some("json") => serde_json::from_str(&content)?,
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "Unsupported provider config format: {} (expected .toml or .json)",
+                    path.display()
+                ))
+            }
+        };
+        Ok(config)
+    }
+
+    // The configured base URL, or the provider's own, with the key from the
+    // provider's environment variable
+    pub fn endpoint(&self, provider: Provider) -> Result<Endpoint, anyhow::Error> {
+        let (variable, default_url) = match provider {
+            Provider::OpenAi => ("OPENAI_API_KEY", "https://api.openai.com/v1"),
+            Provider::Cohere => ("COHERE_API_KEY", "https://api.cohere.ai"),
+        };
+        let api_key = std::env::var(variable).map_err(|_| anyhow::anyhow!("{} is not set", variable))?;
+        let base_url = self.base_url.as_deref().unwrap_or(default_url).trim_end_matches('/').to_string();
+        Ok(Endpoint { provider, base_url, api_key })
+    }
+}
+
+// Which API serves embeddings and completions. Each reads its key from the
+// environment (OPENAI_API_KEY, COHERE_API_KEY); see ProviderConfig::endpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Provider {
+    #[value(name = "openai")]
+    OpenAi,
+    Cohere,
+}
+
+impl Provider {
+    pub fn default_embedding_model(&self) -> &'static str {
+        match self {
+            Provider::OpenAi => openai::TEXT_EMBEDDING_ADA_002,
+            Provider::Cohere => cohere::EMBED_ENGLISH_V3,
+        }
+    }
+
+    pub fn default_chat_model(&self) -> &'static str {
+        match self {
+            Provider::OpenAi => "gpt-4",
+            Provider::Cohere => cohere::COMMAND_R,
+        }
+    }
+
+    // Vector length of the embedding models rig knows for this provider, which
+    // are the only ones accepted
+    pub fn embedding_dims(&self, model: &str) -> Option<usize> {
+        match (self, model) {
+            (Provider::OpenAi, openai::TEXT_EMBEDDING_ADA_002 | openai::TEXT_EMBEDDING_3_SMALL) => Some(1536),
+            (Provider::OpenAi, openai::TEXT_EMBEDDING_3_LARGE) => Some(3072),
+            (Provider::Cohere, cohere::EMBED_ENGLISH_V3 | cohere::EMBED_MULTILINGUAL_V3) => Some(1024),
+            (Provider::Cohere, cohere::EMBED_ENGLISH_LIGHT_V3 | cohere::EMBED_MULTILINGUAL_LIGHT_V3) => Some(384),
+            _ => None,
+        }
+    }
+
+    pub fn embedding_models(&self) -> &'static [&'static str] {
+        match self {
+            Provider::OpenAi => &[openai::TEXT_EMBEDDING_ADA_002, openai::TEXT_EMBEDDING_3_SMALL, openai::TEXT_EMBEDDING_3_LARGE],
+            Provider::Cohere => &[
+                cohere::EMBED_ENGLISH_V3,
+                cohere::EMBED_MULTILINGUAL_V3,
+                cohere::EMBED_ENGLISH_LIGHT_V3,
+                cohere::EMBED_MULTILINGUAL_LIGHT_V3,
+            ],
+        }
+    }
+
+    // Documents the provider accepts per embedding request
+    pub fn max_documents(&self) -> usize {
+        match self {
+            Provider::OpenAi => <openai::EmbeddingModel as EmbeddingModel>::MAX_DOCUMENTS,
+            Provider::Cohere => <cohere::EmbeddingModel as EmbeddingModel>::MAX_DOCUMENTS,
+        }
+    }
+}
+
+// Whether texts are embedded for storage or for searching. Cohere embeds the
+// two differently; OpenAI ignores it.
+#[derive(Debug, Clone, Copy)]
+pub enum EmbeddingRole {
+    Document,
+    Query,
+}
+
+#[derive(Clone)]
+pub enum ProviderClient {
+    OpenAi(openai::Client),
+    Cohere(cohere::Client),
+}
+
+impl ProviderClient {
+    pub fn new(endpoint: &Endpoint) -> Self {
+        match endpoint.provider {
+            Provider::OpenAi => ProviderClient::OpenAi(openai::Client::from_url(&endpoint.api_key, &endpoint.base_url)),
+            Provider::Cohere => ProviderClient::Cohere(cohere::Client::from_url(&endpoint.api_key, &endpoint.base_url)),
+        }
+    }
+
+    pub fn embedding_model(&self, model: &str, role: EmbeddingRole) -> ProviderEmbedding {
+        match self {
+            ProviderClient::OpenAi(client) => ProviderEmbedding::OpenAi(client.embedding_model(model)),
+            ProviderClient::Cohere(client) => {
+                let input_type = match role {
+                    EmbeddingRole::Document => "search_document",
+                    EmbeddingRole::Query => "search_query",
+                };
+                ProviderEmbedding::Cohere(client.embedding_model(model, input_type))
+            }
+        }
+    }
+
+    pub fn agent(&self, model: &str) -> AgentBuilder<ProviderCompletion> {
+        AgentBuilder::new(match self {
+            ProviderClient::OpenAi(client) => ProviderCompletion::OpenAi(client.completion_model(model)),
+            ProviderClient::Cohere(client) => ProviderCompletion::Cohere(client.completion_model(model)),
+        })
+    }
+
+    pub fn extractor<T>(&self, model: &str) -> ExtractorBuilder<T, ProviderCompletion>
+    where
+        T: JsonSchema + for<'a> Deserialize<'a> + Serialize + Send + Sync,
+    {
+        ExtractorBuilder::new(match self {
+            ProviderClient::OpenAi(client) => ProviderCompletion::OpenAi(client.completion_model(model)),
+            ProviderClient::Cohere(client) => ProviderCompletion::Cohere(client.completion_model(model)),
+        })
+    }
+}
+
+// An embedding model of either provider, so the store, index and pipeline are
+// built the same way whichever is selected
+#[derive(Clone)]
+pub enum ProviderEmbedding {
+    OpenAi(openai::EmbeddingModel),
+    Cohere(cohere::EmbeddingModel),
+}
+
+impl EmbeddingModel for ProviderEmbedding {
+    // The larger provider limit; Cohere requests are split to its own below
+    const MAX_DOCUMENTS: usize = <openai::EmbeddingModel as EmbeddingModel>::MAX_DOCUMENTS;
+
+    fn ndims(&self) -> usize {
+        match self {
+            ProviderEmbedding::OpenAi(model) => model.ndims(),
+            ProviderEmbedding::Cohere(model) => model.ndims(),
+        }
+    }
+
+    async fn embed_texts(
+        &self,
+        texts: impl IntoIterator<Item = String> + Send,
+    ) -> Result<Vec<Embedding>, EmbeddingError> {
+        match self {
+            ProviderEmbedding::OpenAi(model) => model.embed_texts(texts).await,
+            ProviderEmbedding::Cohere(model) => {
+                let texts: Vec<String> = texts.into_iter().collect();
+                let mut embeddings = Vec::with_capacity(texts.len());
+                for request in texts.chunks(<cohere::EmbeddingModel as EmbeddingModel>::MAX_DOCUMENTS) {
+                    embeddings.extend(model.embed_texts(request.to_vec()).await?);
+                }
+                Ok(embeddings)
+            }
+        }
+    }
+}
+
+// A completion model of either provider. The raw provider response is
+// dropped, since only the completion choice is used.
+#[derive(Clone)]
+pub enum ProviderCompletion {
+    OpenAi(openai::CompletionModel),
+    Cohere(cohere::CompletionModel),
+}
+
+impl CompletionModel for ProviderCompletion {
+    type Response = ();
+
+    async fn completion(&self, request: CompletionRequest) -> Result<completion::CompletionResponse<()>, CompletionError> {
+        let choice = match self {
+            ProviderCompletion::OpenAi(model) => model.completion(request).await?.choice,
+            ProviderCompletion::Cohere(model) => model.completion(request).await?.choice,
+        };
+        Ok(completion::CompletionResponse { choice, raw_response: () })
+    }
+}
@@ -0,0 +1,72 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+// Shorter queries rarely retrieve anything meaningful
+const MIN_QUERY_CHARS: usize = 12;
+
+// One query per line; blank lines and lines starting with `#` are skipped.
+// Returns (line number, query) pairs.
+pub fn parse(content: &str) -> Vec<(usize, String)> {
+    content
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(line_no, line)| (line_no, line.to_string()))
+        .collect()
+}
+
+// The queries a run will execute, as read from --queries-file
+pub fn read(path: &Path) -> Result<Vec<String>, anyhow::Error> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Cannot read queries file {}: {}", path.display(), e))?;
+    let queries: Vec<String> = parse(&content).into_iter().map(|(_, query)| query).collect();
+    if queries.is_empty() {
+        return Err(anyhow::anyhow!("Queries file {} contains no queries", path.display()));
+    }
+    Ok(queries)
+}
+
+pub struct LintIssue {
+    pub line: usize,
+    pub error: bool,
+    pub message: String,
+}
+
+// Problems in a queries file, in line order. Duplicates and overlong lines are
+// errors; blank lines and very short queries are warnings.
+pub fn lint(content: &str, max_chars: usize) -> Vec<LintIssue> {
+    let mut issues = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            issues.push(LintIssue { line: i + 1, error: false, message: "empty line (skipped)".to_string() });
+        }
+    }
+
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    for (line, query) in parse(content) {
+        let length = query.chars().count();
+        if length > max_chars {
+            issues.push(LintIssue {
+                line,
+                error: true,
+                message: format!("query is {} characters, over the limit of {}", length, max_chars),
+            });
+        } else if length < MIN_QUERY_CHARS {
+            issues.push(LintIssue { line, error: false, message: format!("suspiciously short query '{}'", query) });
+        }
+        match seen.get(&query.to_lowercase()) {
+            Some(first) => issues.push(LintIssue {
+                line,
+                error: true,
+                message: format!("duplicate of the query on line {}", first),
+            }),
+            None => {
+                seen.insert(query.to_lowercase(), line);
+            }
+        }
+    }
+
+    issues.sort_by_key(|issue| issue.line);
+    issues
+}
@@ -0,0 +1,44 @@
+use crate::models::CustomerFeedback;
+use crate::prompt;
+use crate::redact::Redaction;
+use rig::Embed;
+use serde::{de::DeserializeOwned, Serialize};
+
+// What chunk processing, embedding, retrieval and prompt building need from a
+// record, so datasets with other schemas can reuse them. The serde and Eq
+// bounds are the in-memory index's. CustomerFeedback is the only
+// implementation the binary loads.
+pub trait EmbeddableRecord: Clone + Eq + Embed + Serialize + DeserializeOwned + Send + Sync + 'static {
+    // Identifies the record in warnings
+    fn record_id(&self) -> &str;
+
+    // The text that is embedded, which also keys the summary cache
+    fn summary(&self) -> &str;
+
+    // The record's block in an analysis prompt; `idx` is its 1-based position
+    // among the retrieved records. Implementations withhold whatever fields
+    // `redaction` names, if they have them.
+    fn format_for_prompt(&self, score: f64, idx: usize, redaction: &Redaction) -> String;
+
+    // Labelled field values left after redaction, in prompt order, for the
+    // table, JSON and minimal prompt formats
+    fn prompt_fields(&self, redaction: &Redaction) -> Vec<(&'static str, String)>;
+}
+
+impl EmbeddableRecord for CustomerFeedback {
+    fn record_id(&self) -> &str {
+        &self.customer_id
+    }
+
+    fn summary(&self) -> &str {
+        &self.profile_summary
+    }
+
+    fn format_for_prompt(&self, score: f64, idx: usize, redaction: &Redaction) -> String {
+        prompt::format_profile(idx, score, self, redaction)
+    }
+
+    fn prompt_fields(&self, redaction: &Redaction) -> Vec<(&'static str, String)> {
+        prompt::visible_fields(self, redaction)
+    }
+}
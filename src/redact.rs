@@ -0,0 +1,52 @@
+use clap::ValueEnum;
+
+const MASK: &str = "[REDACTED]";
+
+// Fields of the profile block sent to the agent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ProfileField {
+    CustomerId,
+    Age,
+    Gender,
+    Country,
+    Income,
+    Satisfaction,
+    Loyalty,
+    PurchaseFrequency,
+    ProductQuality,
+    ServiceQuality,
+    FeedbackScore,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum RedactMode {
+    /// Leave the field out of the profile block
+    Drop,
+    /// Keep the field but replace its value with [REDACTED]
+    Mask,
+}
+
+// Which profile fields to withhold from prompts. Only the prompt text is
+// affected; retrieval still uses the full embeddings.
+#[derive(Debug, Clone, Default)]
+pub struct Redaction {
+    fields: Vec<ProfileField>,
+    mode: Option<RedactMode>,
+}
+
+impl Redaction {
+    pub fn new(fields: Vec<ProfileField>, mode: RedactMode) -> Self {
+        Self { fields, mode: Some(mode) }
+    }
+
+    // The value to show for `field`, or None if it should be left out
+    pub fn show(&self, field: ProfileField, value: String) -> Option<String> {
+        if !self.fields.contains(&field) {
+            return Some(value);
+        }
+        match self.mode {
+            Some(RedactMode::Drop) => None,
+            Some(RedactMode::Mask) | None => Some(MASK.to_string()),
+        }
+    }
+}
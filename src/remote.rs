@@ -0,0 +1,40 @@
+use flate2::read::GzDecoder;
+use std::io::Read;
+
+pub fn is_url(location: &str) -> bool {
+    location.starts_with("http://") || location.starts_with("https://")
+}
+
+// Download a CSV (or gzip-compressed CSV) into memory, following redirects and
+// printing progress as it arrives
+pub async fn download(url: &str) -> Result<String, anyhow::Error> {
+    let mut response = reqwest::get(url).await?.error_for_status()?;
+    if response.url().as_str() != url {
+        println!("Redirected to {}", response.url());
+    }
+
+    let total = response.content_length();
+    let mut body = Vec::with_capacity(total.unwrap_or(0) as usize);
+    let mut next_report = 0.1;
+    while let Some(chunk) = response.chunk().await? {
+        body.extend_from_slice(&chunk);
+        if let Some(total) = total.filter(|total| *total > 0) {
+            let fraction = body.len() as f64 / total as f64;
+            if fraction >= next_report {
+                println!("Downloaded {:.0}% ({} of {} bytes)", fraction * 100.0, body.len(), total);
+                next_report = (fraction * 10.0).floor() / 10.0 + 0.1;
+            }
+        }
+    }
+    println!("Downloaded {} bytes from {}", body.len(), url);
+
+    // gzip magic number, covering .csv.gz exports regardless of Content-Type
+    if body.starts_with(&[0x1f, 0x8b]) {
+        let mut decompressed = String::new();
+        GzDecoder::new(body.as_slice()).read_to_string(&mut decompressed)?;
+        println!("Decompressed to {} bytes", decompressed.len());
+        return Ok(decompressed);
+    }
+
+    Ok(String::from_utf8(body)?)
+}
@@ -0,0 +1,152 @@
+use crate::cache::sha256_hex;
+use crate::confidence::Confidence;
+use crate::models::{AnalysisRequest, RetrievedProfile};
+use crate::score_display::ScoreFormatter;
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ReportFormat {
+    // One JSON array, written when the run finishes
+    Json,
+    // One line per query, written and flushed as soon as it completes
+    Jsonl,
+}
+
+// One analyzed query. Field names match what compare-runs reads back.
+#[derive(Debug, Serialize)]
+struct ReportRecord {
+    query: String,
+    analysis: Option<String>,
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    refusal: Option<String>,
+    profiles: Vec<RetrievedProfile>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    confidence: Option<Confidence>,
+    timestamp: DateTime<Utc>,
+    // Fingerprint of the run's whole query list, to notice when a resumed run's
+    // queries differ from the original's
+    queries_sha256: String,
+}
+
+// The parts of an existing record needed to resume a run
+#[derive(Deserialize)]
+struct ResumeRecord {
+    query: String,
+    analysis: Option<String>,
+    queries_sha256: Option<String>,
+}
+
+pub fn queries_fingerprint(queries: &[String]) -> String {
+    sha256_hex(queries.join("\n").as_bytes())
+}
+
+pub struct ReportWriter {
+    out: BufWriter<File>,
+    format: ReportFormat,
+    buffered: Vec<ReportRecord>,
+    queries_sha256: String,
+    scores: Option<ScoreFormatter>,
+}
+
+impl ReportWriter {
+    pub fn create(path: &Path, format: ReportFormat, queries_sha256: String) -> Result<Self, anyhow::Error> {
+        Ok(Self { out: BufWriter::new(File::create(path)?), format, buffered: Vec::new(), queries_sha256, scores: None })
+    }
+
+    // Write profile scores as the formatter shows them instead of raw
+    pub fn with_score_display(mut self, scores: ScoreFormatter) -> Self {
+        self.scores = Some(scores);
+        self
+    }
+
+    // Reopen a JSONL report for appending. Returns the writer and the queries
+    // that already have an analysis; failed queries are not counted, so they
+    // run again. Warns when the file was written for a different query list.
+    pub fn resume(path: &Path, queries_sha256: String) -> Result<(Self, HashSet<String>), anyhow::Error> {
+        let content = std::fs::read_to_string(path)?;
+        let mut done = HashSet::new();
+        let mut other_list = false;
+        for (i, line) in content.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+            let record: ResumeRecord = serde_json::from_str(line).map_err(|e| {
+                anyhow::anyhow!(
+                    "{} line {} is not a JSONL report record ({}); only --report-format jsonl output can be resumed",
+                    path.display(),
+                    i + 1,
+                    e
+                )
+            })?;
+            other_list |= record.queries_sha256.is_some_and(|hash| hash != queries_sha256);
+            if record.analysis.is_some() {
+                done.insert(record.query);
+            }
+        }
+        if other_list {
+            tracing::warn!(
+                "{} was written for a different list of queries; queries are matched by exact text, so edited or reordered ones may run again or be skipped",
+                path.display()
+            );
+        }
+
+        let file = OpenOptions::new().append(true).open(path)?;
+        let writer = Self {
+            out: BufWriter::new(file),
+            format: ReportFormat::Jsonl,
+            buffered: Vec::new(),
+            queries_sha256,
+            scores: None,
+        };
+        Ok((writer, done))
+    }
+
+    // `outcome` is the final analysis text or the error message; `refusal` is the
+    // reason when the analysis was judged a non-answer
+    pub fn record(
+        &mut self,
+        request: &AnalysisRequest,
+        outcome: Result<&str, &str>,
+        refusal: Option<&str>,
+        started_at: DateTime<Utc>,
+    ) -> Result<(), anyhow::Error> {
+        let mut profiles = request.profiles.clone();
+        if let Some(scores) = &self.scores {
+            for profile in &mut profiles {
+                profile.score = scores.value(profile.score);
+            }
+        }
+        let record = ReportRecord {
+            query: request.query.clone(),
+            analysis: outcome.ok().map(str::to_string),
+            error: outcome.err().map(str::to_string),
+            refusal: refusal.map(str::to_string),
+            profiles,
+            confidence: request.confidence.clone(),
+            timestamp: started_at,
+            queries_sha256: self.queries_sha256.clone(),
+        };
+        match self.format {
+            ReportFormat::Json => self.buffered.push(record),
+            ReportFormat::Jsonl => {
+                writeln!(self.out, "{}", serde_json::to_string(&record)?)?;
+                self.out.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    // Write the JSON array, if buffering; JSONL lines are already on disk
+    pub fn finish(mut self) -> Result<(), anyhow::Error> {
+        if self.format == ReportFormat::Json {
+            serde_json::to_writer_pretty(&mut self.out, &self.buffered)?;
+            writeln!(self.out)?;
+        }
+        self.out.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,201 @@
+use crate::models::AnalysisResult;
+use rig::{
+    agent::Agent,
+    completion::{CompletionError, CompletionModel, Prompt, PromptError},
+    embeddings::EmbeddingError,
+    extractor::{ExtractionError, Extractor},
+    pipeline::Op,
+};
+use std::time::Duration;
+use tokio::time::sleep;
+
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub max_retries: usize,
+    pub base_delay: Duration,
+    // Per-attempt limit; an attempt that runs over fails as a timeout
+    pub timeout: Option<Duration>,
+}
+
+impl RetryPolicy {
+    // Exponential backoff: base, 2x base, 4x base, ...
+    pub fn delay(&self, attempt: usize) -> Duration {
+        self.base_delay * 2u32.saturating_pow(attempt as u32)
+    }
+}
+
+// Provider error messages that indicate a temporary condition worth retrying.
+// Anything else (content filter, invalid request, auth) fails fast.
+fn is_transient_message(message: &str) -> bool {
+    let message = message.to_lowercase();
+    [
+        "rate limit",
+        "429",
+        "timeout",
+        "timed out",
+        "overloaded",
+        "server error",
+        "server had an error",
+        "bad gateway",
+        "service unavailable",
+        "502",
+        "503",
+        "504",
+    ]
+    .iter()
+    .any(|needle| message.contains(needle))
+}
+
+fn is_transient_http(err: &reqwest::Error) -> bool {
+    err.is_timeout()
+        || err.is_connect()
+        || err
+            .status()
+            .map(|status| status.as_u16() == 429 || status.is_server_error())
+            .unwrap_or(false)
+}
+
+pub fn is_retryable_prompt_error(err: &PromptError) -> bool {
+    match err {
+        PromptError::CompletionError(CompletionError::HttpError(err)) => is_transient_http(err),
+        PromptError::CompletionError(CompletionError::ProviderError(message)) => {
+            is_transient_message(message)
+        }
+        _ => false,
+    }
+}
+
+pub fn is_retryable_embedding_error(err: &EmbeddingError) -> bool {
+    match err {
+        EmbeddingError::HttpError(err) => is_transient_http(err),
+        EmbeddingError::ProviderError(message) => is_transient_message(message),
+        _ => false,
+    }
+}
+
+// What answers the analysis prompt: an agent's freeform text, or the
+// extractor's typed result rendered as pretty JSON
+pub enum Analyst<M: CompletionModel> {
+    Prose(Agent<M>),
+    Structured(Extractor<M, AnalysisResult>),
+}
+
+impl<M: CompletionModel> From<Agent<M>> for Analyst<M> {
+    fn from(agent: Agent<M>) -> Self {
+        Analyst::Prose(agent)
+    }
+}
+
+impl<M: CompletionModel> From<Extractor<M, AnalysisResult>> for Analyst<M> {
+    fn from(extractor: Extractor<M, AnalysisResult>) -> Self {
+        Analyst::Structured(extractor)
+    }
+}
+
+impl<M: CompletionModel> Analyst<M> {
+    async fn respond(&self, input: &str) -> Result<String, PromptError> {
+        match self {
+            Analyst::Prose(agent) => agent.prompt(input).await,
+            Analyst::Structured(extractor) => match extractor.extract(input).await {
+                Ok(result) => serde_json::to_string_pretty(&result)
+                    .map_err(|err| PromptError::CompletionError(CompletionError::JsonError(err))),
+                Err(ExtractionError::PromptError(err)) => Err(err),
+                Err(err) => Err(PromptError::CompletionError(CompletionError::ResponseError(err.to_string()))),
+            },
+        }
+    }
+}
+
+// Terminal pipeline op that prompts the analysis agent, retrying transient
+// failures with backoff and switching to the fallback agent (if configured)
+// once `fallback_after` attempts have failed.
+pub struct RetryingPrompt<M: CompletionModel> {
+    model_name: String,
+    agent: Analyst<M>,
+    fallback: Option<(String, Analyst<M>)>,
+    fallback_after: usize,
+    policy: RetryPolicy,
+}
+
+impl<M: CompletionModel> RetryingPrompt<M> {
+    pub fn new(model_name: &str, agent: impl Into<Analyst<M>>, policy: RetryPolicy) -> Self {
+        Self {
+            model_name: model_name.to_string(),
+            agent: agent.into(),
+            fallback: None,
+            fallback_after: usize::MAX,
+            policy,
+        }
+    }
+
+    pub fn with_fallback(mut self, model_name: &str, agent: impl Into<Analyst<M>>, after: usize) -> Self {
+        self.fallback = Some((model_name.to_string(), agent.into()));
+        self.fallback_after = after;
+        self
+    }
+
+    pub fn describe(&self) -> String {
+        let mut description = format!("model {}, up to {} retries", self.model_name, self.policy.max_retries);
+        if let Analyst::Structured(_) = self.agent {
+            description.push_str(", structured extraction");
+        }
+        if let Some(timeout) = self.policy.timeout {
+            description.push_str(&format!(", {:?} timeout per request", timeout));
+        }
+        if let Some((model_name, _)) = &self.fallback {
+            description.push_str(&format!(", falling back to {} after {} failures", model_name, self.fallback_after));
+        }
+        description
+    }
+}
+
+impl<M: CompletionModel> Op for RetryingPrompt<M> {
+    type Input = String;
+    type Output = Result<String, PromptError>;
+
+    #[tracing::instrument(name = "prompt", skip_all, fields(model = %self.model_name))]
+    async fn call(&self, input: Self::Input) -> Self::Output {
+        let mut attempt = 0;
+        loop {
+            let agent = match &self.fallback {
+                Some((model_name, fallback)) if attempt >= self.fallback_after => {
+                    if attempt == self.fallback_after {
+                        tracing::warn!(
+                            "Analysis failed {} times, falling back to model {}",
+                            attempt,
+                            model_name
+                        );
+                        tracing::Span::current().record("model", model_name.as_str());
+                    }
+                    fallback
+                }
+                _ => &self.agent,
+            };
+
+            let result = match self.policy.timeout {
+                Some(limit) => tokio::time::timeout(limit, agent.respond(&input)).await.unwrap_or_else(|_| {
+                    Err(PromptError::CompletionError(CompletionError::ProviderError(format!(
+                        "request timed out after {:?}",
+                        limit
+                    ))))
+                }),
+                None => agent.respond(&input).await,
+            };
+            match result {
+                Ok(response) => return Ok(response),
+                Err(err) if attempt < self.policy.max_retries && is_retryable_prompt_error(&err) => {
+                    let delay = self.policy.delay(attempt);
+                    tracing::warn!(
+                        "Analysis attempt {} failed ({}), retrying in {:?}",
+                        attempt + 1,
+                        err,
+                        delay
+                    );
+                    sleep(delay).await;
+                    attempt += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
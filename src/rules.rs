@@ -0,0 +1,48 @@
+use crate::models::CustomerFeedback;
+use crate::search::ScoreAdjustment;
+use clap::ValueEnum;
+
+// Score added per matching signal; small next to typical similarity gaps
+// between unrelated profiles, large enough to reorder close matches
+const SIGNAL_BOOST: f64 = 0.02;
+
+// Domain heuristics layered on top of semantic similarity
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum RuleSet {
+    /// Boost profiles showing churn signals: satisfaction below 50%, fewer
+    /// than 5 purchases a year, Bronze loyalty
+    ChurnRisk,
+    /// Boost valuable customers: income of $100,000 or more, 15 or more
+    /// purchases a year, Gold or Platinum loyalty
+    HighValue,
+}
+
+fn churn_signals(customer: &CustomerFeedback) -> usize {
+    [
+        customer.satisfaction_score.is_some_and(|score| score < 50.0),
+        customer.purchase_frequency < 5,
+        customer.loyalty_level.eq_ignore_ascii_case("bronze"),
+    ]
+    .into_iter()
+    .filter(|signal| *signal)
+    .count()
+}
+
+fn value_signals(customer: &CustomerFeedback) -> usize {
+    [
+        customer.income.is_some_and(|income| income >= 100_000.0),
+        customer.purchase_frequency >= 15,
+        ["gold", "platinum"].iter().any(|level| customer.loyalty_level.eq_ignore_ascii_case(level)),
+    ]
+    .into_iter()
+    .filter(|signal| *signal)
+    .count()
+}
+
+pub fn rule_set(kind: RuleSet) -> ScoreAdjustment {
+    let signals: fn(&CustomerFeedback) -> usize = match kind {
+        RuleSet::ChurnRisk => churn_signals,
+        RuleSet::HighValue => value_signals,
+    };
+    Box::new(move |customer, score| score + SIGNAL_BOOST * signals(customer) as f64)
+}
@@ -0,0 +1,145 @@
+use crate::metrics::RunMetrics;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunStatus {
+    Running,
+    Complete,
+    Interrupted,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct InputInfo {
+    pub location: String,
+    pub sha256: String,
+}
+
+// Records loaded is unknown for streaming loads until they finish
+#[derive(Debug, Default, Serialize)]
+pub struct RecordCounts {
+    pub loaded: Option<usize>,
+    pub embedded: usize,
+    pub not_embedded: usize,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct QueryCounts {
+    pub planned: usize,
+    pub completed: usize,
+    pub refused: usize,
+}
+
+// Estimated like --explain-cost; a cost is absent when the model has no price on file
+#[derive(Debug, Default, Serialize)]
+pub struct CostSummary {
+    pub embedding_requests: usize,
+    pub embedding_tokens: usize,
+    pub completion_requests: usize,
+    pub completion_input_tokens: usize,
+    pub completion_output_tokens: usize,
+    pub embedding_usd: Option<f64>,
+    pub completion_usd: Option<f64>,
+    pub total_usd: Option<f64>,
+}
+
+// Everything needed to reproduce or audit a run: how it was invoked, what it
+// read, what it embedded and asked, and what that cost
+#[derive(Debug, Serialize)]
+pub struct RunManifest {
+    pub status: RunStatus,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub command_line: Vec<String>,
+    // The parsed arguments with every default filled in
+    pub resolved_args: String,
+    pub input: InputInfo,
+    pub records: RecordCounts,
+    pub embedding_model: String,
+    pub embedding_dimensions: usize,
+    pub analysis_model: String,
+    pub queries: QueryCounts,
+    pub cost: CostSummary,
+}
+
+impl RunManifest {
+    pub fn new(resolved_args: String, input: InputInfo, embedding_model: &str, analysis_model: &str) -> Self {
+        Self {
+            status: RunStatus::Running,
+            started_at: Utc::now(),
+            finished_at: None,
+            command_line: std::env::args().collect(),
+            resolved_args,
+            input,
+            records: RecordCounts::default(),
+            embedding_model: embedding_model.to_string(),
+            embedding_dimensions: 0,
+            analysis_model: analysis_model.to_string(),
+            queries: QueryCounts::default(),
+            cost: CostSummary::default(),
+        }
+    }
+
+    pub fn record_usage(&mut self, metrics: &RunMetrics) {
+        let (embedding, completion) = (metrics.embedding.cost(), metrics.completion.cost());
+        self.cost = CostSummary {
+            embedding_requests: metrics.embedding.requests,
+            embedding_tokens: metrics.embedding.input_tokens,
+            completion_requests: metrics.completion.requests,
+            completion_input_tokens: metrics.completion.input_tokens,
+            completion_output_tokens: metrics.completion.output_tokens,
+            embedding_usd: embedding,
+            completion_usd: completion,
+            total_usd: embedding.zip(completion).map(|(embedding, completion)| embedding + completion),
+        };
+        self.queries.refused = metrics.refused_queries.len();
+    }
+}
+
+// Shared with the Ctrl-C handler, which writes whatever the run has recorded
+// so far before exiting
+#[derive(Clone)]
+pub struct RunManifestWriter {
+    path: PathBuf,
+    manifest: Arc<Mutex<RunManifest>>,
+}
+
+impl RunManifestWriter {
+    pub fn new(path: PathBuf, manifest: RunManifest) -> Self {
+        Self { path, manifest: Arc::new(Mutex::new(manifest)) }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn update(&self, f: impl FnOnce(&mut RunManifest)) {
+        f(&mut self.manifest.lock().unwrap());
+    }
+
+    pub fn write(&self, status: RunStatus) -> Result<(), anyhow::Error> {
+        let mut manifest = self.manifest.lock().unwrap();
+        manifest.status = status;
+        manifest.finished_at = Some(Utc::now());
+        std::fs::write(&self.path, serde_json::to_string_pretty(&*manifest)?)?;
+        Ok(())
+    }
+
+    // On Ctrl-C, save the manifest marked interrupted and exit as the default
+    // handler would have
+    pub fn write_on_interrupt(&self) {
+        let writer = self.clone();
+        tokio::spawn(async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                match writer.write(RunStatus::Interrupted) {
+                    Ok(()) => eprintln!("\nInterrupted; wrote a partial run manifest to {}", writer.path.display()),
+                    Err(e) => eprintln!("\nInterrupted; could not write the run manifest: {}", e),
+                }
+                std::process::exit(130);
+            }
+        });
+    }
+}
@@ -0,0 +1,76 @@
+use crate::models::CustomerFeedback;
+use crate::projection::next_random;
+use crate::stratify::StratifyField;
+use clap::ValueEnum;
+use std::collections::HashMap;
+use std::fmt;
+
+// How --target-store-size chooses which records to embed
+#[derive(Debug, Clone, Copy)]
+pub enum SampleMethod {
+    Random,
+    Stratified(StratifyField),
+}
+
+impl fmt::Display for SampleMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SampleMethod::Random => write!(f, "random"),
+            SampleMethod::Stratified(field) => match field.to_possible_value() {
+                Some(value) => write!(f, "stratified by {}", value.get_name()),
+                None => write!(f, "stratified by {:?}", field),
+            },
+        }
+    }
+}
+
+fn shuffle<T>(items: &mut [T], state: &mut u64) {
+    for i in (1..items.len()).rev() {
+        let j = (next_random(state) % (i as u64 + 1)) as usize;
+        items.swap(i, j);
+    }
+}
+
+// Reorder `customers` so that embedding them front to back draws a sample of
+// the whole file, and stopping at any point leaves a representative store. For
+// random sampling every prefix is a uniform sample. For stratified sampling
+// each stratum's members are spread evenly over the order (the i-th of n sorts
+// at (i + 0.5) / n), so every prefix holds each value in proportion to its
+// share of the file.
+pub fn sample_order(mut customers: Vec<CustomerFeedback>, method: SampleMethod, seed: u64) -> Vec<CustomerFeedback> {
+    let mut state = seed;
+    shuffle(&mut customers, &mut state);
+    let SampleMethod::Stratified(field) = method else {
+        return customers;
+    };
+
+    let mut sizes: HashMap<String, usize> = HashMap::new();
+    for customer in &customers {
+        *sizes.entry(field.value(customer).to_string()).or_default() += 1;
+    }
+    let mut taken: HashMap<String, usize> = HashMap::new();
+    let mut keyed: Vec<(f64, CustomerFeedback)> = customers
+        .into_iter()
+        .map(|customer| {
+            let value = field.value(&customer);
+            let rank = taken.entry(value.to_string()).or_default();
+            let key = (*rank as f64 + 0.5) / sizes[value] as f64;
+            *rank += 1;
+            (key, customer)
+        })
+        .collect();
+    // Stable, so strata tied at a position keep their shuffled order
+    keyed.sort_by(|a, b| a.0.total_cmp(&b.0));
+    keyed.into_iter().map(|(_, customer)| customer).collect()
+}
+
+// Profiles per value of `field`, largest first
+pub fn strata_counts<'a>(customers: impl Iterator<Item = &'a CustomerFeedback>, field: StratifyField) -> Vec<(String, usize)> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for customer in customers {
+        *counts.entry(field.value(customer).to_string()).or_default() += 1;
+    }
+    let mut counts: Vec<(String, usize)> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    counts
+}
@@ -0,0 +1,109 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+// Column names `CustomerFeedback` deserializes from (its serde renames)
+pub const REQUIRED_COLUMNS: &[&str] = &[
+    "CustomerID",
+    "Age",
+    "Gender",
+    "Country",
+    "Income",
+    "ProductQuality",
+    "ServiceQuality",
+    "PurchaseFrequency",
+    "FeedbackScore",
+    "LoyaltyLevel",
+    "SatisfactionScore",
+];
+
+// Maps the expected column names to the headers used by a particular export,
+// e.g. `CustomerID = "cust_id"`. Columns that aren't listed keep their name.
+#[derive(Debug, Default, Deserialize)]
+#[serde(transparent)]
+pub struct ColumnMapping {
+    columns: HashMap<String, String>,
+}
+
+impl ColumnMapping {
+    // Load a mapping from a `.toml` or `.json` file
+    pub fn from_file(path: &Path) -> Result<Self, anyhow::Error> {
+        let content = std::fs::read_to_string(path)?;
+        let mapping: ColumnMapping = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => toml::from_str(&content)?,
+            Some("json") => serde_json::from_str(&content)?,
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "Unsupported column mapping format: {} (expected .toml or .json)",
+                    path.display()
+                ))
+            }
+        };
+
+        if let Some(unknown) = mapping.columns.keys().find(|k| !REQUIRED_COLUMNS.contains(&k.as_str())) {
+            return Err(anyhow::anyhow!(
+                "Unknown column '{}' in mapping (expected one of: {})",
+                unknown,
+                REQUIRED_COLUMNS.join(", ")
+            ));
+        }
+
+        Ok(mapping)
+    }
+
+    // Read `column` from `header` instead, e.g. from `--id-column`
+    pub fn set(&mut self, column: &str, header: &str) -> Result<(), anyhow::Error> {
+        if !REQUIRED_COLUMNS.contains(&column) {
+            return Err(anyhow::anyhow!("Unknown column '{}'", column));
+        }
+        self.columns.insert(column.to_string(), header.to_string());
+        Ok(())
+    }
+
+    // The renamed columns in a stable order, for the saved store identity
+    pub fn fingerprint(&self) -> String {
+        let mut pairs: Vec<String> = self.columns.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+        pairs.sort();
+        pairs.join(",")
+    }
+
+    fn source_header<'a>(&'a self, column: &'a str) -> &'a str {
+        self.columns.get(column).map(String::as_str).unwrap_or(column)
+    }
+
+    // Rename the reader's headers to the expected column names so deserialization
+    // into `CustomerFeedback` works unchanged. Fails if any required column is missing.
+    pub fn apply<R: std::io::Read>(&self, rdr: &mut csv::Reader<R>) -> Result<(), anyhow::Error> {
+        let headers = rdr.headers()?.clone();
+
+        let missing: Vec<String> = REQUIRED_COLUMNS
+            .iter()
+            .map(|column| self.source_header(column))
+            .filter(|source| !headers.iter().any(|h| h == *source))
+            .map(|source| source.to_string())
+            .collect();
+        if !missing.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Missing required column(s) in data file: {}",
+                missing.join(", ")
+            ));
+        }
+
+        // A header that is itself an expected name but was mapped elsewhere (say
+        // the file has both CustomerID and the chosen id column) is moved aside
+        // so it can't shadow the mapped column
+        let renamed: csv::StringRecord = headers
+            .iter()
+            .map(|header| {
+                match REQUIRED_COLUMNS.iter().find(|column| self.source_header(column) == header) {
+                    Some(column) => column.to_string(),
+                    None if REQUIRED_COLUMNS.contains(&header) => format!("{} (unmapped)", header),
+                    None => header.to_string(),
+                }
+            })
+            .collect();
+        rdr.set_headers(renamed);
+
+        Ok(())
+    }
+}
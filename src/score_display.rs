@@ -0,0 +1,50 @@
+use clap::ValueEnum;
+
+// Sigmoid steepness: a score 0.1 either side of the midpoint maps to about 23% or 77%
+const SIGMOID_STEEPNESS: f64 = 12.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ScoreDisplay {
+    /// Similarity scores as computed
+    Raw,
+    /// 0-100 relevance percentage, see --percent-transform
+    Percent,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum PercentTransform {
+    /// Rescale cosine similarity from [-1, 1] to [0, 100]
+    Linear,
+    /// Logistic curve centred on --low-confidence-below, spreading out the
+    /// narrow band real matches fall in
+    Sigmoid,
+}
+
+// How profile scores are shown to readers. Only presentation: ranking,
+// thresholds and the prompt always use the raw score.
+#[derive(Debug, Clone, Copy)]
+pub struct ScoreFormatter {
+    pub display: ScoreDisplay,
+    pub transform: PercentTransform,
+    pub midpoint: f64,
+}
+
+impl ScoreFormatter {
+    pub fn value(&self, score: f64) -> f64 {
+        let percent = match (self.display, self.transform) {
+            (ScoreDisplay::Raw, _) => return score,
+            (ScoreDisplay::Percent, PercentTransform::Linear) => (score + 1.0) / 2.0 * 100.0,
+            (ScoreDisplay::Percent, PercentTransform::Sigmoid) => {
+                100.0 / (1.0 + (-SIGMOID_STEEPNESS * (score - self.midpoint)).exp())
+            }
+        };
+        (percent.clamp(0.0, 100.0) * 10.0).round() / 10.0
+    }
+
+    pub fn format(&self, score: f64) -> String {
+        match self.display {
+            ScoreDisplay::Raw => format!("{:.3}", score),
+            ScoreDisplay::Percent => format!("{:.1}%", self.value(score)),
+        }
+    }
+}
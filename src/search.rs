@@ -0,0 +1,426 @@
+use chrono::NaiveDate;
+use rig::{
+    embeddings::{distance::VectorDistance, Embedding, EmbeddingModel},
+    pipeline::Op,
+    vector_store::{in_memory_store::InMemoryVectorIndex, VectorStoreError},
+};
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use crate::aspects::{self, AspectBreakdown, AspectLog, AspectSplitter};
+use crate::broaden::{self, QueryBroadener};
+use crate::cache::RetrievalCache;
+use crate::features;
+use crate::filter::Filter;
+use crate::stratify::{self, StratifyField};
+use crate::field_match::FieldMatcher;
+use crate::models::CustomerFeedback;
+use crate::record::EmbeddableRecord;
+
+// Highest score first; equal scores fall back to record id so results don't
+// depend on the store's (hash map) iteration order
+fn by_score_then_id<T: EmbeddableRecord>(a: (f64, &T), b: (f64, &T)) -> Ordering {
+    b.0.total_cmp(&a.0).then_with(|| a.1.record_id().cmp(b.1.record_id()))
+}
+
+// Final lookup results, as the pipeline receives them
+type Retrieved<T> = Vec<(f64, String, T)>;
+
+// Adjusts a profile's similarity score before top-k selection
+pub type ScoreAdjustment<T = CustomerFeedback> = Box<dyn Fn(&T, f64) -> f64 + Send + Sync>;
+
+// Record-specific retrieval steps, stored as closures so the retriever itself
+// works over any record type. The builders that install them read
+// CustomerFeedback fields.
+type RecordFilter<T> = Box<dyn Fn(&T) -> bool + Send + Sync>;
+type RecordBonus<T> = Box<dyn Fn(&T) -> f64 + Send + Sync>;
+// Builds the per-record bonus for one query
+type QueryBonus<T> = Box<dyn Fn(&str) -> RecordBonus<T> + Send + Sync>;
+// The value a record is stratified by
+type Stratum<T> = Box<dyn Fn(&T) -> &str + Send + Sync>;
+
+// Embed the query and return the `top_k` closest stored vectors together with
+// their cosine similarity, highest first. Documents are ignored entirely, so this
+// exercises only the embedding model and the index.
+pub async fn raw_search<M: EmbeddingModel, D: Serialize>(
+    index: &InMemoryVectorIndex<M, D>,
+    embedding_model: &M,
+    query: &str,
+    top_k: usize,
+) -> Result<Vec<(f32, Vec<f32>)>, anyhow::Error> {
+    let query_embedding = embedding_model.embed_text(query).await?;
+
+    let mut results: Vec<(f32, Vec<f32>)> = index
+        .iter()
+        .flat_map(|(_, (_, embeddings))| embeddings.iter())
+        .map(|embedding| {
+            (
+                features::query_similarity(embedding, &query_embedding) as f32,
+                embedding.vec.iter().map(|v| *v as f32).collect(),
+            )
+        })
+        .collect();
+
+    results.sort_by(|a, b| b.0.total_cmp(&a.0));
+    results.truncate(top_k);
+
+    Ok(results)
+}
+
+// Return the `k` customers whose stored embeddings are closest to the given
+// customer's, excluding the customer itself. Uses only stored vectors, so no
+// API calls are made.
+pub fn similar_customers<M: EmbeddingModel>(
+    index: &InMemoryVectorIndex<M, CustomerFeedback>,
+    customer_id: &str,
+    k: usize,
+) -> Result<Vec<(f64, CustomerFeedback)>, anyhow::Error> {
+    let (_, (_, target)) = index
+        .iter()
+        .find(|(_, (doc, _))| doc.customer_id == customer_id)
+        .ok_or_else(|| anyhow::anyhow!("Customer {} is not in the vector store", customer_id))?;
+    let target = target.first();
+
+    let mut neighbors: Vec<(f64, CustomerFeedback)> = index
+        .iter()
+        .filter(|(_, (doc, _))| doc.customer_id != customer_id)
+        .map(|(_, (doc, embeddings))| {
+            let score = embeddings
+                .iter()
+                .map(|embedding| embedding.cosine_similarity(&target, false))
+                .fold(f64::NEG_INFINITY, f64::max);
+            (score, doc.clone())
+        })
+        .collect();
+
+    neighbors.sort_by(|a, b| by_score_then_id((a.0, &a.1), (b.0, &b.1)));
+    neighbors.truncate(k);
+
+    Ok(neighbors)
+}
+
+// Weight similarity by feedback age: a record `half_life_days` older than the
+// newest dated record counts half as much. Records without a date are left as is.
+pub fn recency_weight(reference: NaiveDate, half_life_days: f64) -> ScoreAdjustment {
+    Box::new(move |customer, score| match customer.parsed_date() {
+        Some(date) => {
+            let age_days = (reference - date).num_days().max(0) as f64;
+            score * 0.5f64.powf(age_days / half_life_days)
+        }
+        None => score,
+    })
+}
+
+// Semantic lookup over the in-memory index that scores every stored profile,
+// applies the configured score adjustments, and only then selects the top k.
+// Output matches rig's `lookup` op so it can be dropped into the same pipeline.
+//
+// With an aspect splitter, each aspect of the query is ranked separately and
+// the rankings are combined by reciprocal rank fusion, so the returned scores
+// are fused RRF scores rather than cosine similarities.
+pub struct Retriever<M: EmbeddingModel, In, T: EmbeddableRecord = CustomerFeedback> {
+    index: InMemoryVectorIndex<M, T>,
+    embedding_model: M,
+    top_k: usize,
+    adjustments: Vec<(String, ScoreAdjustment<T>)>,
+    aspects: Option<(AspectSplitter, AspectLog)>,
+    field_bonus: Option<QueryBonus<T>>,
+    // Described by the filter expression
+    filter: Option<(String, RecordFilter<T>)>,
+    // Described by the field name, with the score window
+    stratify: Option<(String, f64, Stratum<T>)>,
+    broaden: Option<(QueryBroadener, usize, f64)>,
+    min_score: Option<f64>,
+    cache: Option<Arc<RetrievalCache<Retrieved<T>>>>,
+    _in: PhantomData<In>,
+}
+
+impl<M: EmbeddingModel, In, T: EmbeddableRecord> Retriever<M, In, T> {
+    pub fn new(index: InMemoryVectorIndex<M, T>, embedding_model: M, top_k: usize) -> Self {
+        Self {
+            index,
+            embedding_model,
+            top_k,
+            adjustments: Vec::new(),
+            aspects: None,
+            field_bonus: None,
+            filter: None,
+            stratify: None,
+            broaden: None,
+            min_score: None,
+            cache: None,
+            _in: PhantomData,
+        }
+    }
+
+    // `name` only labels the adjustment in `describe`
+    pub fn with_adjustment(mut self, name: impl Into<String>, adjustment: ScoreAdjustment<T>) -> Self {
+        self.adjustments.push((name.into(), adjustment));
+        self
+    }
+
+    // Retrieve per aspect and fuse; returns the log the per-query breakdowns are written to
+    pub fn with_aspects(&mut self, splitter: AspectSplitter) -> AspectLog {
+        let log = AspectLog::default();
+        self.aspects = Some((splitter, log.clone()));
+        log
+    }
+
+    // When every result scores below `threshold`, ask the broadener for a more
+    // general query and search again, up to `attempts` times
+    pub fn with_empty_retry(mut self, broadener: QueryBroadener, attempts: usize, threshold: f64) -> Self {
+        self.broaden = Some((broadener, attempts.min(broaden::MAX_ATTEMPTS), threshold));
+        self
+    }
+
+    // Drop selected profiles scoring below `threshold`, so weak matches never
+    // reach the prompt; a query left with none is sent with a warning that no
+    // relevant profiles were found
+    pub fn with_min_score(mut self, threshold: f64) -> Self {
+        self.min_score = Some(threshold);
+        self
+    }
+
+    // One line per configured retrieval step, in the order they are applied
+    pub fn describe(&self) -> Vec<String> {
+        let mut steps = Vec::new();
+        if let Some(cache) = &self.cache {
+            steps.push(format!("answer repeated queries from the retrieval cache ({} queries)", cache.capacity()));
+        }
+        match &self.filter {
+            Some((description, filter)) => steps.push(format!(
+                "score the {} of {} stored profiles matching {} by cosine similarity to the query",
+                self.index.iter().filter(|(_, (doc, _))| filter(doc)).count(),
+                self.index.iter().count(),
+                description
+            )),
+            None => steps.push(format!(
+                "score all {} stored profiles by cosine similarity to the query",
+                self.index.iter().count()
+            )),
+        }
+        if self.aspects.is_some() {
+            steps.push("split the query into aspects and fuse per-aspect rankings (reciprocal rank fusion)".to_string());
+        }
+        steps.extend(self.adjustments.iter().map(|(name, _)| format!("adjust scores: {}", name)));
+        if self.field_bonus.is_some() {
+            steps.push("add field match bonuses for values named in the query".to_string());
+        }
+        match &self.stratify {
+            Some((field, within, _)) => steps.push(format!(
+                "keep the best {}, then stratify the top {} by {} when scores are within {}",
+                self.pool_size(),
+                self.top_k,
+                field,
+                within
+            )),
+            None => steps.push(format!("keep the top {}", self.top_k)),
+        }
+        if let Some((_, attempts, threshold)) = &self.broaden {
+            steps.push(format!(
+                "if nothing scores {} or more, broaden the query and search again (up to {} times)",
+                threshold, attempts
+            ));
+        }
+        if let Some(threshold) = self.min_score {
+            steps.push(format!("drop profiles scoring below {}", threshold));
+        }
+        steps
+    }
+
+    // Remember the results of the last `capacity` distinct queries; returns the
+    // cache so its hit rate can be reported
+    pub fn with_cache(&mut self, capacity: usize) -> Arc<RetrievalCache<Retrieved<T>>> {
+        let cache = Arc::new(RetrievalCache::new(capacity));
+        self.cache = Some(cache.clone());
+        cache
+    }
+
+    // Candidates kept before the final selection: more than k when stratifying
+    fn pool_size(&self) -> usize {
+        match self.stratify {
+            Some(_) => self.top_k * stratify::POOL_FACTOR,
+            None => self.top_k,
+        }
+    }
+
+    // Adjusted score of every stored profile passing the filter against one
+    // query embedding, in store order
+    fn score_all(
+        &self,
+        query_embedding: &Embedding,
+        bonus: Option<&RecordBonus<T>>,
+    ) -> Vec<(f64, &String, &T)> {
+        self.index
+            .iter()
+            .filter(|(_, (doc, _))| self.filter.as_ref().is_none_or(|(_, filter)| filter(doc)))
+            .map(|(id, (doc, embeddings))| {
+                let similarity = embeddings
+                    .iter()
+                    .map(|embedding| features::query_similarity(embedding, query_embedding))
+                    .fold(f64::NEG_INFINITY, f64::max);
+                let score = self
+                    .adjustments
+                    .iter()
+                    .fold(similarity, |score, (_, adjust)| adjust(doc, score));
+                let bonus = bonus.map(|bonus| bonus(doc)).unwrap_or(0.0);
+                (score + bonus, id, doc)
+            })
+            .collect()
+    }
+}
+
+impl<M: EmbeddingModel, In> Retriever<M, In, CustomerFeedback> {
+    // Add a bonus to profiles whose structured fields match values named in the query
+    pub fn with_field_matcher(mut self, matcher: FieldMatcher) -> Self {
+        self.field_bonus = Some(Box::new(move |query| {
+            let hints = matcher.hints(query);
+            Box::new(move |customer| hints.bonus(customer))
+        }));
+        self
+    }
+
+    // Only consider profiles matching `filter`, before any scoring
+    pub fn with_filter(mut self, filter: Filter) -> Self {
+        self.filter = Some((filter.to_string(), Box::new(move |customer| filter.matches(customer))));
+        self
+    }
+
+    // When the best matches are within `within` of each other, spread the top k
+    // across values of `field` instead of returning near-duplicates
+    pub fn with_stratification(mut self, field: StratifyField, within: f64) -> Self {
+        self.stratify = Some((format!("{:?}", field), within, Box::new(move |customer| field.value(customer))));
+        self
+    }
+}
+
+impl<M: EmbeddingModel, In, T: EmbeddableRecord> Retriever<M, In, T> {
+    // One search for `query`. Aspect breakdowns are logged under `log_key`, the
+    // caller's original query, so they can still be found after a rephrasing.
+    async fn retrieve(
+        &self,
+        query: &str,
+        log_key: &str,
+    ) -> Result<Retrieved<T>, VectorStoreError> {
+        let query = query.to_string();
+        let bonus = self.field_bonus.as_ref().map(|field_bonus| field_bonus(&query));
+
+        let mut scored = match &self.aspects {
+            None => {
+                let query_embedding = self.embedding_model.embed_text(&query).await?;
+                self.score_all(&query_embedding, bonus.as_ref())
+            }
+            Some((splitter, log)) => {
+                let mut aspect_queries = splitter(query.clone()).await;
+                if aspect_queries.is_empty() {
+                    aspect_queries.push(query.clone());
+                }
+                let aspect_embeddings = self.embedding_model.embed_texts(aspect_queries.clone()).await?;
+
+                let per_aspect: Vec<Vec<(f64, &String, &T)>> =
+                    aspect_embeddings.iter().map(|embedding| self.score_all(embedding, bonus.as_ref())).collect();
+                let rankings: Vec<Vec<usize>> = per_aspect
+                    .iter()
+                    .map(|scores| {
+                        let mut order: Vec<usize> = (0..scores.len()).collect();
+                        order.sort_by(|&a, &b| by_score_then_id((scores[a].0, scores[a].2), (scores[b].0, scores[b].2)));
+                        order
+                    })
+                    .collect();
+                let items = per_aspect.first().map(Vec::len).unwrap_or(0);
+
+                let mut breakdown = AspectBreakdown { aspects: aspect_queries, ..Default::default() };
+                let mut fused = aspects::reciprocal_rank_fusion(&rankings, items);
+                fused.sort_by(|a, b| by_score_then_id((a.1, per_aspect[0][a.0].2), (b.1, per_aspect[0][b.0].2)));
+                let fused: Vec<(f64, &String, &T)> = fused
+                    .into_iter()
+                    .take(self.pool_size())
+                    .map(|(item, score, contributions)| {
+                        let (_, id, doc) = per_aspect[0][item];
+                        breakdown.contributions.insert(doc.record_id().to_string(), contributions);
+                        (score, id, doc)
+                    })
+                    .collect();
+                log.lock().unwrap().insert(log_key.to_string(), breakdown);
+                fused
+            }
+        };
+
+        scored.sort_by(|a, b| by_score_then_id((a.0, a.2), (b.0, b.2)));
+        scored.truncate(self.pool_size());
+        let selected: Vec<(f64, (&String, &T))> = match &self.stratify {
+            Some((_, within, stratum)) => stratify::stratified_top_k(
+                scored.into_iter().map(|(score, id, doc)| (score, (id, doc))).collect(),
+                self.top_k,
+                *within,
+                |(_, doc)| stratum(doc),
+            ),
+            None => scored.into_iter().map(|(score, id, doc)| (score, (id, doc))).collect(),
+        };
+
+        Ok(selected
+            .into_iter()
+            .map(|(score, (id, doc))| (score, id.clone(), doc.clone()))
+            .collect())
+    }
+}
+
+impl<M: EmbeddingModel, In: Into<String> + Send + Sync, T: EmbeddableRecord> Op for Retriever<M, In, T> {
+    type Input = In;
+    type Output = Result<Vec<(f64, String, T)>, VectorStoreError>;
+
+    #[tracing::instrument(name = "lookup", skip_all, fields(top_k = self.top_k))]
+    async fn call(&self, query: Self::Input) -> Self::Output {
+        let original: String = query.into();
+        // The retriever's configuration is fixed for the run, so the query and
+        // k identify a lookup
+        let cache_key = format!("{}\n{}", self.top_k, original);
+        if let Some(results) = self.cache.as_ref().and_then(|cache| cache.get(&cache_key)) {
+            return Ok(results);
+        }
+        let mut results = self.retrieve(&original, &original).await?;
+
+        if let Some((broadener, attempts, threshold)) = &self.broaden {
+            let mut query = original.clone();
+            for attempt in 1..=*attempts {
+                if !broaden::is_empty(&results, *threshold) {
+                    break;
+                }
+                let Some(rephrased) = broadener(query.clone()).await else {
+                    break;
+                };
+                tracing::info!(
+                    "No profiles scored {} or more for '{}'; retrying as '{}' (attempt {} of {})",
+                    threshold,
+                    query,
+                    rephrased,
+                    attempt,
+                    attempts
+                );
+                results = self.retrieve(&rephrased, &original).await?;
+                query = rephrased;
+            }
+        }
+
+        if let Some(threshold) = self.min_score {
+            let retrieved = results.len();
+            results.retain(|(score, _, _)| *score >= threshold);
+            if results.len() < retrieved {
+                tracing::info!(
+                    "Dropped {} of {} profiles scoring below {} for '{}'",
+                    retrieved - results.len(),
+                    retrieved,
+                    threshold,
+                    original
+                );
+            }
+        }
+
+        if let Some(cache) = &self.cache {
+            cache.insert(&cache_key, results.clone());
+        }
+        Ok(results)
+    }
+}
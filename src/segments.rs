@@ -0,0 +1,98 @@
+use crate::cluster::Clustering;
+use crate::models::CustomerFeedback;
+use crate::prompt;
+use crate::redact::Redaction;
+use rig::{
+    agent::Agent,
+    completion::{CompletionModel, Prompt},
+    embeddings::Embedding,
+    OneOrMany,
+};
+
+pub const SEGMENT_PREAMBLE: &str = r#"
+    You are an expert customer insights analyst naming customer segments.
+    You will be given representative profiles from one segment found by
+    clustering. Reply with a short segment name on the first line (no
+    numbering or quotes), then two to four sentences describing what the
+    members have in common and what sets them apart. Only describe what the
+    profiles show.
+"#;
+
+pub struct Segment {
+    pub cluster: usize,
+    pub size: usize,
+    pub name: String,
+    pub description: String,
+    pub representatives: Vec<String>,
+}
+
+// Ask the agent to name and characterize each non-empty cluster from its `sample`
+// most central members. Empty clusters are skipped; a failed request leaves the
+// segment unnamed rather than aborting the report.
+pub async fn label_segments<M: CompletionModel>(
+    agent: &Agent<M>,
+    embeddings: &[(CustomerFeedback, OneOrMany<Embedding>)],
+    clustering: &Clustering,
+    sample: usize,
+    redaction: &Redaction,
+) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    for (cluster, &size) in clustering.sizes().iter().enumerate() {
+        if size == 0 {
+            continue;
+        }
+        let members = clustering.members(embeddings, cluster);
+        let sampled = &members[..sample.min(members.len())];
+        let profiles: String = sampled
+            .iter()
+            .enumerate()
+            .map(|(i, (similarity, customer))| prompt::format_profile(i + 1, *similarity, customer, redaction))
+            .collect();
+        let request = format!(
+            "Segment with {} customers. Its {} most representative profiles (similarity to the segment centre):\n{}",
+            size,
+            sampled.len(),
+            profiles
+        );
+
+        let (name, description) = match agent.prompt(&request).await {
+            Ok(response) => {
+                let response = response.trim();
+                let (name, description) = response.split_once('\n').unwrap_or((response, ""));
+                (name.trim().trim_matches(['#', '*', '"']).trim().to_string(), description.trim().to_string())
+            }
+            Err(err) => {
+                tracing::warn!("Labeling segment {} failed: {}", cluster, err);
+                ("(unlabeled)".to_string(), String::new())
+            }
+        };
+        segments.push(Segment {
+            cluster,
+            size,
+            name,
+            description,
+            representatives: sampled.iter().map(|(_, customer)| customer.customer_id.clone()).collect(),
+        });
+    }
+    segments.sort_by_key(|segment| std::cmp::Reverse(segment.size));
+    segments
+}
+
+pub fn report(segments: &[Segment], total: usize) -> String {
+    let mut report = String::new();
+    for segment in segments {
+        report.push_str(&format!(
+            "\n=== Segment {}: {} ===\n{} customers ({:.1}%)\nRepresentative members: {}\n",
+            segment.cluster,
+            segment.name,
+            segment.size,
+            100.0 * segment.size as f64 / total.max(1) as f64,
+            segment.representatives.join(", ")
+        ));
+        if !segment.description.is_empty() {
+            report.push_str(&segment.description);
+            report.push('\n');
+        }
+    }
+    report
+}
@@ -0,0 +1,171 @@
+use crate::export::CsvExportOptions;
+use crate::models::AnalysisRequest;
+use crate::score_display::ScoreFormatter;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::fs::File;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SheetFormat {
+    /// Tab-separated, for pasting straight into a spreadsheet
+    Tsv,
+    /// Comma-separated, for File > Import
+    Csv,
+}
+
+// The four parts the analysis preamble asks for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Section {
+    Patterns,
+    Risks,
+    Recommendations,
+    Opportunities,
+}
+
+// Which section a heading introduces, judged by its wording ("Key Behavioral
+// Patterns", "Risk Factors", "Actionable Recommendations", ...). Opportunities
+// come first because their heading usually mentions satisfaction too.
+fn classify(heading: &str) -> Option<Section> {
+    let heading = heading.to_lowercase();
+    if heading.contains("opportunit") {
+        Some(Section::Opportunities)
+    } else if heading.contains("pattern") || heading.contains("trend") {
+        Some(Section::Patterns)
+    } else if heading.contains("risk") || heading.contains("concern") {
+        Some(Section::Risks)
+    } else if heading.contains("recommend") || heading.contains("actionable") || heading.contains("next step") {
+        Some(Section::Recommendations)
+    } else {
+        None
+    }
+}
+
+// Split a heading line into its title and any text after a colon, e.g.
+// `2. **Risk factors:** churn among...`. Recognizes markdown headings, lines
+// that are entirely bold, and short numbered or colon-terminated lines.
+fn heading(line: &str) -> Option<(&str, &str)> {
+    let line = line.trim();
+    if let Some(title) = line.strip_prefix('#') {
+        return Some((title.trim_start_matches('#').trim(), ""));
+    }
+    let unnumbered = line.trim_start_matches(|c: char| c.is_ascii_digit());
+    let numbered = unnumbered.len() < line.len() && unnumbered.starts_with(['.', ')']);
+    let rest = if numbered { unnumbered[1..].trim() } else { line };
+    let (title, after) = match rest.split_once(':') {
+        Some((title, after)) => (title, after),
+        None => (rest, ""),
+    };
+    let after = after.trim_start_matches('*').trim();
+    let bold = title.starts_with("**") && (title.ends_with("**") || rest.starts_with("**"));
+    let title = title.trim_matches('*').trim();
+    let short = !title.is_empty() && title.len() <= 60;
+    (short && (bold || numbered || rest.contains(':'))).then_some((title, after))
+}
+
+// Analysis text grouped by section; text under no recognized heading (an
+// introduction, a closing summary) is kept in `other`
+#[derive(Debug, Default)]
+struct Sections {
+    patterns: Vec<String>,
+    risks: Vec<String>,
+    recommendations: Vec<String>,
+    opportunities: Vec<String>,
+    other: Vec<String>,
+}
+
+impl Sections {
+    fn parse(analysis: &str) -> Self {
+        let mut sections = Sections::default();
+        let mut current = None;
+        for line in analysis.lines() {
+            let text = match heading(line).and_then(|(title, after)| classify(title).map(|section| (section, after))) {
+                Some((section, after)) => {
+                    current = Some(section);
+                    after
+                }
+                None => line.trim_end(),
+            };
+            if text.trim().is_empty() {
+                continue;
+            }
+            let target = match current {
+                Some(Section::Patterns) => &mut sections.patterns,
+                Some(Section::Risks) => &mut sections.risks,
+                Some(Section::Recommendations) => &mut sections.recommendations,
+                Some(Section::Opportunities) => &mut sections.opportunities,
+                None => &mut sections.other,
+            };
+            target.push(text.to_string());
+        }
+        sections
+    }
+}
+
+#[derive(Serialize)]
+struct SheetRow<'a> {
+    #[serde(rename = "Query")]
+    query: &'a str,
+    #[serde(rename = "TopCustomerIDs")]
+    top_ids: String,
+    #[serde(rename = "MeanScore")]
+    mean_score: Option<f64>,
+    #[serde(rename = "Patterns")]
+    patterns: String,
+    #[serde(rename = "Risks")]
+    risks: String,
+    #[serde(rename = "Recommendations")]
+    recommendations: String,
+    #[serde(rename = "Opportunities")]
+    opportunities: String,
+    #[serde(rename = "Other")]
+    other: String,
+    #[serde(rename = "Error")]
+    error: Option<&'a str>,
+}
+
+// One wide row per query. Multi-line sections stay in one cell: fields with
+// newlines, tabs or quotes are quoted, which spreadsheets read back intact in
+// both formats.
+pub struct SheetExport {
+    writer: csv::Writer<File>,
+    scores: Option<ScoreFormatter>,
+}
+
+impl SheetExport {
+    pub fn create(path: &Path, format: SheetFormat, options: &CsvExportOptions) -> Result<Self, anyhow::Error> {
+        let delimiter = match format {
+            SheetFormat::Tsv => b'\t',
+            SheetFormat::Csv => b',',
+        };
+        Ok(Self { writer: options.create_delimited(path, delimiter)?, scores: None })
+    }
+
+    // Write the mean score as the formatter shows scores instead of raw
+    pub fn with_score_display(mut self, scores: ScoreFormatter) -> Self {
+        self.scores = Some(scores);
+        self
+    }
+
+    pub fn record(&mut self, request: &AnalysisRequest, outcome: Result<&str, &str>) -> Result<(), anyhow::Error> {
+        let scores: Vec<f64> = request
+            .profiles
+            .iter()
+            .map(|profile| self.scores.map_or(profile.score, |scores| scores.value(profile.score)))
+            .collect();
+        let sections = outcome.map(Sections::parse).unwrap_or_default();
+        self.writer.serialize(SheetRow {
+            query: &request.query,
+            top_ids: request.documents.iter().map(|doc| doc.customer_id.as_str()).collect::<Vec<_>>().join(", "),
+            mean_score: (!scores.is_empty()).then(|| scores.iter().sum::<f64>() / scores.len() as f64),
+            patterns: sections.patterns.join("\n"),
+            risks: sections.risks.join("\n"),
+            recommendations: sections.recommendations.join("\n"),
+            opportunities: sections.opportunities.join("\n"),
+            other: sections.other.join("\n"),
+            error: outcome.err(),
+        })?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
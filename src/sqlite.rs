@@ -0,0 +1,41 @@
+use std::process::Command;
+
+// Run `query` against the SQLite database at `path` and return the result set
+// as CSV with a header row, ready for the usual column mapping. Uses the
+// sqlite3 command-line shell; NULLs come out as empty fields, which the
+// missing-value policies handle like blank CSV cells.
+pub fn query_csv(path: &str, query: &str) -> Result<String, anyhow::Error> {
+    // The shell runs an argument starting with '.' as a dot-command, and
+    // -readonly doesn't stop .shell, .system or .output
+    if query.trim_start().starts_with('.') {
+        return Err(anyhow::anyhow!("--sql must be an SQL query; sqlite3 dot-commands are not allowed"));
+    }
+    if !std::path::Path::new(path).is_file() {
+        return Err(anyhow::anyhow!("SQLite database not found: {}", path));
+    }
+    let output = Command::new("sqlite3")
+        .args(["-bail", "-readonly", "-csv", "-header", path, query])
+        .output()
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => {
+                anyhow::anyhow!("--sqlite needs the sqlite3 command-line shell, which was not found on PATH")
+            }
+            _ => anyhow::anyhow!("Could not run sqlite3: {}", e),
+        })?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "SQLite query failed on {}: {}",
+            path,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    // The shell prints no header at all for an empty result, so say so here
+    // rather than reporting every column as missing
+    let content = String::from_utf8(output.stdout)?;
+    if content.trim().is_empty() {
+        return Err(anyhow::anyhow!("SQLite query returned no rows from {}", path));
+    }
+    println!("Read {} rows from {}", content.lines().count().saturating_sub(1), path);
+    Ok(content)
+}
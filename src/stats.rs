@@ -0,0 +1,187 @@
+use crate::currency::Currency;
+use crate::loyalty::LoyaltyOrder;
+use crate::models::CustomerFeedback;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+// Spread of one numeric field over the records that have a value for it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FieldStats {
+    pub count: usize,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub p25: f64,
+    pub median: f64,
+    pub p75: f64,
+}
+
+impl FieldStats {
+    // None when no record has a value
+    pub fn from_values(mut values: Vec<f64>) -> Option<Self> {
+        values.retain(|value| value.is_finite());
+        if values.is_empty() {
+            return None;
+        }
+        values.sort_by(f64::total_cmp);
+        Some(Self {
+            count: values.len(),
+            min: values[0],
+            max: values[values.len() - 1],
+            mean: values.iter().sum::<f64>() / values.len() as f64,
+            p25: percentile(&values, 0.25),
+            median: percentile(&values, 0.5),
+            p75: percentile(&values, 0.75),
+        })
+    }
+
+    fn describe(&self, format: impl Fn(f64) -> String) -> String {
+        format!(
+            "min {}, 25th percentile {}, median {}, 75th percentile {}, max {}, mean {}",
+            format(self.min),
+            format(self.p25),
+            format(self.median),
+            format(self.p75),
+            format(self.max),
+            format(self.mean)
+        )
+    }
+}
+
+// Linear interpolation between the closest ranks of sorted, non-empty values
+fn percentile(sorted: &[f64], fraction: f64) -> f64 {
+    let rank = fraction * (sorted.len() - 1) as f64;
+    let (lower, upper) = (rank.floor() as usize, rank.ceil() as usize);
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (rank - lower as f64)
+}
+
+// Population-level context for the analyst, which otherwise only sees the
+// handful of profiles retrieved for each query
+#[derive(Debug, Clone, PartialEq)]
+pub struct DatasetStats {
+    pub records: usize,
+    pub income: Option<FieldStats>,
+    pub satisfaction_score: Option<FieldStats>,
+    pub purchase_frequency: Option<FieldStats>,
+    // Loyalty level and number of customers at it
+    pub loyalty_levels: Vec<(String, usize)>,
+    currency: Currency,
+}
+
+pub fn compute_stats(customers: &[CustomerFeedback]) -> DatasetStats {
+    stats_of(customers.iter())
+}
+
+// Same as `compute_stats` for records that aren't held in one slice
+pub fn stats_of<'a>(customers: impl Iterator<Item = &'a CustomerFeedback> + Clone) -> DatasetStats {
+    let mut loyalty: BTreeMap<&str, usize> = BTreeMap::new();
+    for customer in customers.clone() {
+        *loyalty.entry(customer.loyalty_level.trim()).or_default() += 1;
+    }
+    DatasetStats {
+        records: loyalty.values().sum(),
+        income: FieldStats::from_values(customers.clone().filter_map(|c| c.income).collect()),
+        satisfaction_score: FieldStats::from_values(customers.clone().filter_map(|c| c.satisfaction_score).collect()),
+        purchase_frequency: FieldStats::from_values(customers.clone().map(|c| c.purchase_frequency as f64).collect()),
+        loyalty_levels: loyalty.into_iter().map(|(level, count)| (level.to_string(), count)).collect(),
+        currency: customers.clone().next().map(|c| c.currency).unwrap_or_default(),
+    }
+}
+
+impl DatasetStats {
+    // Appended to the analyst preamble; loyalty levels are listed in business order
+    pub fn preamble_section(&self, loyalty_order: &LoyaltyOrder) -> String {
+        let mut section = format!(
+            "\n\nPopulation statistics for all {} customers in the dataset, for judging what counts as high or low:\n",
+            self.records
+        );
+        let fields = [
+            ("Income", self.income, true),
+            ("Satisfaction score", self.satisfaction_score, false),
+            ("Purchase frequency", self.purchase_frequency, false),
+        ];
+        for (name, stats, is_income) in fields {
+            if let Some(stats) = stats {
+                let described = if is_income {
+                    stats.describe(|value| self.currency.format(value))
+                } else {
+                    stats.describe(|value| format!("{:.2}", value))
+                };
+                let _ = writeln!(section, "- {}: {}", name, described);
+            }
+        }
+        if !self.loyalty_levels.is_empty() {
+            let mut levels: Vec<&(String, usize)> = self.loyalty_levels.iter().collect();
+            levels.sort_by(|a, b| loyalty_order.compare(&a.0, &b.0, false));
+            let distribution: Vec<String> = levels
+                .iter()
+                .map(|(level, count)| {
+                    format!("{} {} ({:.1}%)", level, count, *count as f64 * 100.0 / self.records.max(1) as f64)
+                })
+                .collect();
+            let _ = writeln!(section, "- Loyalty levels: {}", distribution.join(", "));
+        }
+        section
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn customer(income: Option<f64>, satisfaction: Option<f64>, frequency: i32, loyalty: &str) -> CustomerFeedback {
+        CustomerFeedback {
+            customer_id: String::new(),
+            age: 30,
+            gender: "Male".to_string(),
+            country: "UK".to_string(),
+            income,
+            product_quality: 5,
+            service_quality: 5,
+            purchase_frequency: frequency,
+            feedback_score: "Low".to_string(),
+            loyalty_level: loyalty.to_string(),
+            satisfaction_score: satisfaction,
+            date: None,
+            profile_summary: String::new(),
+            currency: Default::default(),
+        }
+    }
+
+    #[test]
+    fn computes_mean_and_percentiles() {
+        let customers = vec![
+            customer(Some(40.0), Some(90.0), 10, "Gold"),
+            customer(Some(10.0), None, 2, "Bronze"),
+            customer(Some(30.0), Some(50.0), 4, "Gold"),
+            customer(Some(20.0), Some(70.0), 8, "Silver"),
+            customer(None, Some(60.0), 6, "Gold"),
+        ];
+        let stats = compute_stats(&customers);
+        assert_eq!(stats.records, 5);
+
+        let income = stats.income.unwrap();
+        assert_eq!(income.count, 4);
+        assert_eq!((income.min, income.max, income.mean), (10.0, 40.0, 25.0));
+        assert_eq!((income.p25, income.median, income.p75), (17.5, 25.0, 32.5));
+
+        let frequency = stats.purchase_frequency.unwrap();
+        assert_eq!((frequency.mean, frequency.p25, frequency.median, frequency.p75), (6.0, 4.0, 6.0, 8.0));
+
+        assert_eq!(stats.satisfaction_score.unwrap().median, 65.0);
+        assert_eq!(
+            stats.loyalty_levels,
+            vec![("Bronze".to_string(), 1), ("Gold".to_string(), 3), ("Silver".to_string(), 1)]
+        );
+    }
+
+    #[test]
+    fn empty_fields_have_no_stats() {
+        let stats = compute_stats(&[customer(None, None, 1, "Gold")]);
+        assert!(stats.income.is_none());
+        assert!(stats.satisfaction_score.is_none());
+        let section = stats.preamble_section(&LoyaltyOrder::default());
+        assert!(!section.contains("Income"));
+        assert!(section.contains("Gold 1 (100.0%)"));
+    }
+}
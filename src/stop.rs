@@ -0,0 +1,59 @@
+use std::time::{Duration, Instant};
+
+// Parse a wall-clock budget such as `90s`, `15m`, `2h`, or plain seconds
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let (number, unit) = match value.find(|c: char| !c.is_ascii_digit() && c != '.') {
+        Some(split) => value.split_at(split),
+        None => (value, "s"),
+    };
+    let number: f64 = number.parse().map_err(|_| format!("invalid duration '{}'", value))?;
+    let seconds = match unit {
+        "s" => number,
+        "m" => number * 60.0,
+        "h" => number * 3600.0,
+        _ => return Err(format!("invalid duration unit in '{}' (use s, m or h)", value)),
+    };
+    Ok(Duration::from_secs_f64(seconds))
+}
+
+// When an unattended batch run should give up early
+#[derive(Debug, Clone, Default)]
+pub struct StopConditions {
+    pub on_error: bool,
+    pub low_confidence_streak: Option<usize>,
+    pub max_duration: Option<Duration>,
+}
+
+// Tracks a batch run against its stop conditions
+pub struct StopTracker {
+    conditions: StopConditions,
+    started: Instant,
+    low_confidence_run: usize,
+}
+
+impl StopTracker {
+    pub fn new(conditions: StopConditions) -> Self {
+        Self { conditions, started: Instant::now(), low_confidence_run: 0 }
+    }
+
+    // Checked before starting a query, so a query in flight is never cut off
+    pub fn out_of_time(&self) -> Option<String> {
+        let budget = self.conditions.max_duration?;
+        (self.started.elapsed() >= budget).then(|| format!("time budget of {:?} used up", budget))
+    }
+
+    // Record a finished query; returns why the run should stop, if it should
+    pub fn record(&mut self, failed: bool, low_confidence: bool) -> Option<String> {
+        if failed && self.conditions.on_error {
+            return Some("query failed and --stop-on-error is set".to_string());
+        }
+        self.low_confidence_run = if low_confidence { self.low_confidence_run + 1 } else { 0 };
+        match self.conditions.low_confidence_streak {
+            Some(limit) if self.low_confidence_run >= limit => {
+                Some(format!("{} consecutive low-confidence results", self.low_confidence_run))
+            }
+            _ => None,
+        }
+    }
+}
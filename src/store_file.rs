@@ -0,0 +1,123 @@
+use crate::cache::sha256_hex;
+use crate::currency::Currency;
+use crate::models::CustomerFeedback;
+use crate::persist::{self, StoreFormat};
+use rig::embeddings::Embedding;
+use rig::OneOrMany;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+
+const STORE_FILE_VERSION: u8 = 2;
+
+type Documents = Vec<(CustomerFeedback, OneOrMany<Embedding>)>;
+
+// One stored profile. `profile_summary` and `currency` are skipped when a
+// CustomerFeedback is serialized, so they are kept next to it and put back on
+// load; the summary is also the document text of every embedding. Its vectors
+// follow the header, `embeddings` of them per profile in profile order.
+#[derive(Serialize, Deserialize)]
+struct StoredProfile {
+    customer: CustomerFeedback,
+    summary: String,
+    currency: Currency,
+    embeddings: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoreFile {
+    version: u8,
+    // Hash of the input data and the settings that shaped the vectors
+    identity: String,
+    profiles: Vec<StoredProfile>,
+}
+
+// Hash of a file's contents, for inputs whose name says nothing about what they
+// hold, such as --embeddings-file
+pub fn file_sha256(path: &Path) -> Result<String, anyhow::Error> {
+    let mut hasher = Sha256::new();
+    std::fs::File::open(path)
+        .and_then(|mut file| std::io::copy(&mut file, &mut hasher))
+        .map_err(|e| anyhow::anyhow!("Cannot read {}: {}", path.display(), e))?;
+    Ok(hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+// What a saved store must have been built from to be reused: the embedding
+// identity, the input file's hash and the settings that decide which records
+// are stored, what their summaries say and how their vectors are built
+pub fn store_identity(cache_identity: &str, input_sha256: &str, settings: &str) -> String {
+    sha256_hex(format!("{}\n{}\n{}", cache_identity, input_sha256, settings).as_bytes())
+}
+
+// Written with --store-format, like the summary cache
+pub fn save_store(
+    documents: &[(CustomerFeedback, OneOrMany<Embedding>)],
+    path: &Path,
+    identity: &str,
+    format: StoreFormat,
+) -> Result<(), anyhow::Error> {
+    let file = StoreFile {
+        version: STORE_FILE_VERSION,
+        identity: identity.to_string(),
+        profiles: documents
+            .iter()
+            .map(|(customer, embeddings)| StoredProfile {
+                customer: customer.clone(),
+                summary: customer.profile_summary.clone(),
+                currency: customer.currency,
+                embeddings: embeddings.len(),
+            })
+            .collect(),
+    };
+    let vectors: Vec<Vec<f64>> = documents
+        .iter()
+        .flat_map(|(_, embeddings)| embeddings.iter().map(|embedding| embedding.vec.clone()))
+        .collect();
+    std::fs::write(path, persist::encode_documents(&file, &vectors, format)?)
+        .map_err(|e| anyhow::anyhow!("Cannot write store file {}: {}", path.display(), e))
+}
+
+// The documents of a saved store, ready for `InMemoryVectorStore::from_documents`.
+// None when the file was built from other data or settings and must be rebuilt.
+// Either format is read, whatever --store-format says.
+pub fn load_store(path: &Path, identity: &str) -> Result<Option<Documents>, anyhow::Error> {
+    let (file, vectors): (StoreFile, Vec<Vec<f64>>) = persist::decode_documents(&std::fs::read(path)?).map_err(|e| {
+        anyhow::anyhow!("Cannot read store file {}: {} (use --rebuild-store to replace it)", path.display(), e)
+    })?;
+    if file.version != STORE_FILE_VERSION {
+        return Err(anyhow::anyhow!(
+            "Unsupported store file version {} in {} (expected {})",
+            file.version,
+            path.display(),
+            STORE_FILE_VERSION
+        ));
+    }
+    if file.identity != identity {
+        tracing::warn!(
+            "Store file {} was built from different data or settings; rebuilding it",
+            path.display()
+        );
+        return Ok(None);
+    }
+    if vectors.len() != file.profiles.iter().map(|profile| profile.embeddings).sum::<usize>() {
+        return Err(anyhow::anyhow!("Store file {} has the wrong number of vectors for its profiles", path.display()));
+    }
+
+    let mut vectors = vectors.into_iter();
+    let mut documents = Vec::with_capacity(file.profiles.len());
+    for profile in file.profiles {
+        let mut customer = profile.customer;
+        customer.profile_summary = profile.summary;
+        customer.currency = profile.currency;
+        let embeddings = vectors
+            .by_ref()
+            .take(profile.embeddings)
+            .map(|vec| Embedding { document: customer.profile_summary.clone(), vec })
+            .collect();
+        let embeddings = OneOrMany::many(embeddings).map_err(|_| {
+            anyhow::anyhow!("Store file {} has no embedding for customer {}", path.display(), customer.customer_id)
+        })?;
+        documents.push((customer, embeddings));
+    }
+    Ok(Some(documents))
+}
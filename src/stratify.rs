@@ -0,0 +1,67 @@
+use crate::models::CustomerFeedback;
+use clap::ValueEnum;
+
+// How many candidates beyond top-k are considered when stratifying
+pub const POOL_FACTOR: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum StratifyField {
+    Country,
+    Gender,
+    LoyaltyLevel,
+}
+
+impl StratifyField {
+    pub fn value<'a>(&self, customer: &'a CustomerFeedback) -> &'a str {
+        match self {
+            StratifyField::Country => &customer.country,
+            StratifyField::Gender => &customer.gender,
+            StratifyField::LoyaltyLevel => &customer.loyalty_level,
+        }
+    }
+}
+
+// Pick `k` of the candidates (sorted by descending score). When more than `k`
+// candidates are within `within` of the best score the matches are effectively
+// interchangeable, so instead of the first `k` take the best of each
+// `stratum` value (a StratifyField, for customers) in turn. Otherwise this is
+// plain top-k. The result stays score-sorted.
+pub fn stratified_top_k<T>(
+    candidates: Vec<(f64, T)>,
+    k: usize,
+    within: f64,
+    stratum: impl Fn(&T) -> &str,
+) -> Vec<(f64, T)> {
+    let Some(best) = candidates.first().map(|(score, _)| *score) else {
+        return candidates;
+    };
+    let pool_size = candidates.iter().take_while(|(score, _)| best - score <= within).count();
+    if pool_size <= k {
+        return candidates.into_iter().take(k).collect();
+    }
+
+    // Strata in order of their best candidate, each holding indices in score order
+    let mut strata: Vec<(&str, Vec<usize>)> = Vec::new();
+    for (i, (_, item)) in candidates[..pool_size].iter().enumerate() {
+        let value = stratum(item);
+        match strata.iter_mut().find(|(v, _)| *v == value) {
+            Some((_, members)) => members.push(i),
+            None => strata.push((value, vec![i])),
+        }
+    }
+
+    let mut chosen: Vec<usize> = Vec::with_capacity(k);
+    let mut round = 0;
+    while chosen.len() < k {
+        let picks: Vec<usize> = strata.iter().filter_map(|(_, members)| members.get(round).copied()).collect();
+        if picks.is_empty() {
+            break;
+        }
+        chosen.extend(picks.into_iter().take(k - chosen.len()));
+        round += 1;
+    }
+    chosen.sort_unstable();
+
+    let mut candidates: Vec<Option<(f64, T)>> = candidates.into_iter().map(Some).collect();
+    chosen.into_iter().filter_map(|i| candidates[i].take()).collect()
+}
@@ -0,0 +1,167 @@
+use crate::providers::{Endpoint, Provider};
+use crate::retry::{is_retryable_prompt_error, RetryPolicy};
+use rig::completion::{CompletionError, PromptError};
+use serde_json::{json, Value};
+use std::io::Write;
+use tokio::time::sleep;
+
+// rig 0.6 has no streaming completion API, so --stream talks to the
+// provider's chat endpoint directly, sending the same preamble and prompt the
+// agent would and printing text to stdout as it arrives. Failures before the
+// first token are retried like RetryingPrompt; once text has been printed a
+// failure ends the line and is returned, since the output can't be taken back.
+pub struct StreamingPrompt {
+    endpoint: Endpoint,
+    model_name: String,
+    preamble: String,
+    http: reqwest::Client,
+    policy: RetryPolicy,
+}
+
+fn provider_error(message: impl Into<String>) -> PromptError {
+    PromptError::CompletionError(CompletionError::ProviderError(message.into()))
+}
+
+impl StreamingPrompt {
+    // Sends to the same endpoint, with the same key, as the rig client
+    pub fn new(endpoint: &Endpoint, model_name: &str, preamble: &str, policy: RetryPolicy) -> Self {
+        Self {
+            endpoint: endpoint.clone(),
+            model_name: model_name.to_string(),
+            preamble: preamble.to_string(),
+            http: reqwest::Client::new(),
+            policy,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        format!("model {}, streamed, up to {} retries before the first token", self.model_name, self.policy.max_retries)
+    }
+
+    fn request(&self, prompt: &str) -> reqwest::RequestBuilder {
+        let (path, body) = match self.endpoint.provider {
+            Provider::OpenAi => (
+                "/chat/completions",
+                json!({
+                    "model": self.model_name,
+                    "messages": [
+                        { "role": "system", "content": self.preamble },
+                        { "role": "user", "content": prompt },
+                    ],
+                    "stream": true,
+                }),
+            ),
+            Provider::Cohere => (
+                "/v1/chat",
+                json!({
+                    "model": self.model_name,
+                    "preamble": self.preamble,
+                    "message": prompt,
+                    "stream": true,
+                }),
+            ),
+        };
+        self.http
+            .post(format!("{}{}", self.endpoint.base_url, path))
+            .bearer_auth(&self.endpoint.api_key)
+            .json(&body)
+    }
+
+    // Text carried by one line of the response, or None for lines without any.
+    // OpenAI sends server-sent events ending in `data: [DONE]`; Cohere sends
+    // one JSON event per line ending in a `stream-end` event.
+    fn parse_line(&self, line: &str) -> Result<Option<String>, PromptError> {
+        let payload = match self.endpoint.provider {
+            Provider::OpenAi => match line.strip_prefix("data:").map(str::trim) {
+                Some("[DONE]") | None => return Ok(None),
+                Some(payload) => payload,
+            },
+            Provider::Cohere => line,
+        };
+        let event: Value = serde_json::from_str(payload)
+            .map_err(|e| PromptError::CompletionError(CompletionError::JsonError(e)))?;
+        if let Some(error) = event.get("error") {
+            return Err(provider_error(error.get("message").and_then(Value::as_str).unwrap_or("stream error")));
+        }
+        let text = match self.endpoint.provider {
+            Provider::OpenAi => event.pointer("/choices/0/delta/content").and_then(Value::as_str),
+            Provider::Cohere => match event.get("event_type").and_then(Value::as_str) {
+                Some("text-generation") => event.get("text").and_then(Value::as_str),
+                Some("stream-end") => match event.get("finish_reason").and_then(Value::as_str) {
+                    Some("COMPLETE" | "MAX_TOKENS") | None => None,
+                    Some(reason) => return Err(provider_error(format!("stream ended with {}", reason))),
+                },
+                _ => None,
+            },
+        };
+        Ok(text.map(str::to_string))
+    }
+
+    // One attempt. `printed` tells the caller whether any text reached stdout.
+    async fn attempt(&self, prompt: &str, printed: &mut bool) -> Result<String, PromptError> {
+        let send = self.request(prompt).send();
+        let response = match self.policy.timeout {
+            Some(limit) => tokio::time::timeout(limit, send)
+                .await
+                .map_err(|_| provider_error(format!("request timed out after {:?}", limit)))?,
+            None => send.await,
+        }
+        .map_err(|e| PromptError::CompletionError(CompletionError::HttpError(e)))?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(provider_error(format!("{}: {}", status, body.trim())));
+        }
+
+        let mut response = response;
+        let mut analysis = String::new();
+        let mut pending = Vec::new();
+        let mut stdout = std::io::stdout();
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .map_err(|e| PromptError::CompletionError(CompletionError::HttpError(e)))?
+        {
+            pending.extend_from_slice(&chunk);
+            while let Some(end) = pending.iter().position(|byte| *byte == b'\n') {
+                let line: Vec<u8> = pending.drain(..=end).collect();
+                let line = String::from_utf8_lossy(&line);
+                if line.trim().is_empty() {
+                    continue;
+                }
+                if let Some(text) = self.parse_line(line.trim())? {
+                    print!("{}", text);
+                    let _ = stdout.flush();
+                    *printed = true;
+                    analysis.push_str(&text);
+                }
+            }
+        }
+        Ok(analysis)
+    }
+
+    pub async fn call(&self, prompt: &str) -> Result<String, PromptError> {
+        let mut attempt = 0;
+        loop {
+            let mut printed = false;
+            let result = self.attempt(prompt, &mut printed).await;
+            // Finish the streamed line whether or not the stream completed
+            if printed {
+                println!();
+            }
+            match result {
+                Ok(analysis) => return Ok(analysis),
+                Err(err) if !printed && attempt < self.policy.max_retries && is_retryable_prompt_error(&err) => {
+                    let delay = self.policy.delay(attempt);
+                    tracing::warn!("Streaming attempt {} failed ({}), retrying in {:?}", attempt + 1, err, delay);
+                    sleep(delay).await;
+                    attempt += 1;
+                }
+                Err(err) if printed => {
+                    return Err(provider_error(format!("stream interrupted after partial output: {}", err)))
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use crate::models::CustomerFeedback;
+use crate::tokens::estimate_tokens;
+use rig::{
+    agent::Agent,
+    completion::{CompletionModel, Prompt},
+};
+
+pub const SUMMARIZER_PREAMBLE: &str = r#"
+    You are an expert customer insights analyst writing an executive summary.
+    You will be given either a batch of customer profiles or a set of partial summaries
+    produced from earlier batches. Condense them into a concise summary covering:
+    1. Overall satisfaction and loyalty trends
+    2. Notable segments (demographics, income, countries) and how they differ
+    3. Key risks and opportunities
+
+    Preserve concrete figures where they matter and do not invent data.
+"#;
+
+pub struct DatasetSummary {
+    pub chunk_summaries: Vec<String>,
+    pub final_summary: String,
+}
+
+// Greedily pack texts into groups whose estimated token count stays within
+// `max_tokens`. A single oversized text still gets a group of its own.
+fn pack_by_tokens(texts: Vec<String>, max_tokens: usize) -> Vec<Vec<String>> {
+    let mut groups = Vec::new();
+    let mut current = Vec::new();
+    let mut current_tokens = 0;
+
+    for text in texts {
+        let tokens = estimate_tokens(&text);
+        if !current.is_empty() && current_tokens + tokens > max_tokens {
+            groups.push(std::mem::take(&mut current));
+            current_tokens = 0;
+        }
+        current_tokens += tokens;
+        current.push(text);
+    }
+    if !current.is_empty() {
+        groups.push(current);
+    }
+
+    groups
+}
+
+// Map-reduce summarization: summarize batches of profiles sized for the
+// completion model's context, then repeatedly summarize the summaries until a
+// single report remains.
+pub async fn summarize_dataset<M: CompletionModel>(
+    agent: &Agent<M>,
+    customers: &[CustomerFeedback],
+    max_chunk_tokens: usize,
+) -> Result<DatasetSummary, anyhow::Error> {
+    if customers.is_empty() {
+        return Err(anyhow::anyhow!("No customer records to summarize"));
+    }
+
+    let chunks = pack_by_tokens(
+        customers.iter().map(|c| c.profile_summary.clone()).collect(),
+        max_chunk_tokens,
+    );
+    println!("Summarizing {} records in {} chunks...", customers.len(), chunks.len());
+
+    let mut chunk_summaries = Vec::with_capacity(chunks.len());
+    for (chunk_num, chunk) in chunks.iter().enumerate() {
+        println!("Summarizing chunk {} of {}...", chunk_num + 1, chunks.len());
+        let prompt = format!(
+            "Summarize the following {} customer profiles:\n\n{}",
+            chunk.len(),
+            chunk.join("\n")
+        );
+        chunk_summaries.push(agent.prompt(&prompt).await?);
+    }
+
+    let mut summaries = chunk_summaries.clone();
+    while summaries.len() > 1 {
+        let mut groups = pack_by_tokens(summaries, max_chunk_tokens);
+        // If no two summaries fit together, combine them all at once rather than loop forever
+        if groups.iter().all(|group| group.len() == 1) {
+            groups = vec![groups.into_iter().flatten().collect()];
+        }
+        println!("Combining {} groups of partial summaries...", groups.len());
+
+        summaries = Vec::with_capacity(groups.len());
+        for group in groups {
+            // A lone summary that could not be packed with others is already condensed
+            if group.len() == 1 {
+                summaries.extend(group);
+                continue;
+            }
+            let prompt = format!(
+                "Combine these {} partial summaries into a single executive summary:\n\n{}",
+                group.len(),
+                group.join("\n\n---\n\n")
+            );
+            summaries.push(agent.prompt(&prompt).await?);
+        }
+    }
+
+    Ok(DatasetSummary {
+        chunk_summaries,
+        final_summary: summaries.pop().unwrap_or_default(),
+    })
+}
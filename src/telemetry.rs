@@ -0,0 +1,52 @@
+// Flushes buffered spans when dropped at the end of `main`
+pub struct TelemetryGuard;
+
+impl Drop for TelemetryGuard {
+    fn drop(&mut self) {
+        #[cfg(feature = "otel")]
+        opentelemetry::global::shutdown_tracer_provider();
+    }
+}
+
+// Logging setup. With the `otel` feature enabled and OTEL_EXPORTER_OTLP_ENDPOINT
+// set, spans are also exported to an OTLP collector; otherwise this is plain
+// local logging.
+#[cfg(not(feature = "otel"))]
+pub fn init() -> Result<TelemetryGuard, anyhow::Error> {
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::INFO)
+        .with_writer(|| crate::progress::LogWriter)
+        .init();
+    Ok(TelemetryGuard)
+}
+
+#[cfg(feature = "otel")]
+pub fn init() -> Result<TelemetryGuard, anyhow::Error> {
+    use opentelemetry_otlp::WithExportConfig;
+    use tracing_subscriber::{filter::LevelFilter, layer::SubscriberExt, util::SubscriberInitExt, Layer};
+
+    let fmt = tracing_subscriber::fmt::layer()
+        .with_writer(|| crate::progress::LogWriter)
+        .with_filter(LevelFilter::INFO);
+    let registry = tracing_subscriber::registry().with(fmt);
+
+    match std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT") {
+        Ok(endpoint) => {
+            let tracer = opentelemetry_otlp::new_pipeline()
+                .tracing()
+                .with_exporter(opentelemetry_otlp::new_exporter().tonic().with_endpoint(endpoint))
+                .with_trace_config(opentelemetry_sdk::trace::config().with_resource(
+                    opentelemetry_sdk::Resource::new(vec![opentelemetry::KeyValue::new(
+                        "service.name",
+                        env!("CARGO_PKG_NAME"),
+                    )]),
+                ))
+                .install_batch(opentelemetry_sdk::runtime::Tokio)?;
+            registry
+                .with(tracing_opentelemetry::layer().with_tracer(tracer).with_filter(LevelFilter::INFO))
+                .init();
+        }
+        Err(_) => registry.init(),
+    }
+    Ok(TelemetryGuard)
+}
@@ -0,0 +1,5 @@
+// Rough token estimate for OpenAI models (~4 characters per token). Good enough
+// for sizing requests without pulling in a tokenizer.
+pub fn estimate_tokens(text: &str) -> usize {
+    text.len().div_ceil(4)
+}
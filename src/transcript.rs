@@ -0,0 +1,72 @@
+use crate::confidence::Confidence;
+use crate::errors::PipelineError;
+use crate::models::{AnalysisRequest, RetrievedProfile};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+#[derive(Serialize)]
+struct TranscriptRecord<'a> {
+    query: &'a str,
+    prompt: &'a str,
+    profiles: &'a [RetrievedProfile],
+    response: Option<&'a str>,
+    error: Option<String>,
+    model: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    confidence: Option<&'a Confidence>,
+    started_at: DateTime<Utc>,
+    finished_at: DateTime<Utc>,
+}
+
+// Appends one JSON line per analyzed query so the exact prompt/response pair can
+// be inspected later
+pub struct TranscriptWriter {
+    file: File,
+    api_keys: Vec<String>,
+}
+
+impl TranscriptWriter {
+    pub fn open(path: &Path) -> Result<Self, anyhow::Error> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file,
+            api_keys: ["OPENAI_API_KEY", "COHERE_API_KEY"]
+                .iter()
+                .filter_map(|name| std::env::var(name).ok())
+                .filter(|key| !key.is_empty())
+                .collect(),
+        })
+    }
+
+    pub fn record(
+        &mut self,
+        request: &AnalysisRequest,
+        result: &Result<String, PipelineError>,
+        model: &str,
+        started_at: DateTime<Utc>,
+    ) -> Result<(), anyhow::Error> {
+        let record = TranscriptRecord {
+            query: &request.query,
+            prompt: &request.prompt,
+            profiles: &request.profiles,
+            response: result.as_ref().ok().map(String::as_str),
+            error: result.as_ref().err().map(|e| e.to_string()),
+            model,
+            confidence: request.confidence.as_ref(),
+            started_at,
+            finished_at: Utc::now(),
+        };
+
+        let mut line = serde_json::to_string(&record)?;
+        // Provider errors can echo request details, so never let a key reach disk
+        for api_key in &self.api_keys {
+            line = line.replace(api_key.as_str(), "[REDACTED]");
+        }
+        writeln!(self.file, "{}", line)?;
+
+        Ok(())
+    }
+}
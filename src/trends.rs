@@ -0,0 +1,40 @@
+use crate::models::CustomerFeedback;
+use std::collections::HashMap;
+
+// Collapse multiple rows per customer into one record carrying a trend-aware
+// summary. Rows are ordered by their Date column when every row in the group
+// has a parseable date, otherwise by their order in the file. Customers with a
+// single row get the regular snapshot summary.
+pub fn collapse_histories(customers: Vec<CustomerFeedback>) -> Vec<CustomerFeedback> {
+    let mut order = Vec::new();
+    let mut groups: HashMap<String, Vec<CustomerFeedback>> = HashMap::new();
+    for customer in customers {
+        let group = groups.entry(customer.customer_id.clone()).or_default();
+        if group.is_empty() {
+            order.push(customer.customer_id.clone());
+        }
+        group.push(customer);
+    }
+
+    let mut trended = 0;
+    let collapsed: Vec<CustomerFeedback> = order
+        .into_iter()
+        .filter_map(|id| groups.remove(&id))
+        .filter_map(|mut history| {
+            if history.iter().all(|c| c.parsed_date().is_some()) {
+                history.sort_by_key(|c| c.parsed_date());
+            }
+            let mut latest = history.last()?.clone();
+            if history.len() > 1 {
+                trended += 1;
+                latest.generate_trend_summary(&history);
+            } else {
+                latest.generate_summary();
+            }
+            Some(latest)
+        })
+        .collect();
+
+    println!("Generated trend summaries for {} customers with multiple records", trended);
+    collapsed
+}
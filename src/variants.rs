@@ -0,0 +1,142 @@
+use crate::errors::{describe_error, PipelineError};
+use crate::metrics::UsageLine;
+use crate::models::AnalysisRequest;
+use crate::postprocess::PostProcessChain;
+use crate::retry::RetryingPrompt;
+use futures::{stream, StreamExt};
+use rig::{completion::CompletionModel, pipeline::Op};
+use std::fmt::Write;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+// One analyst preamble under test: `builtin` for the built-in preamble,
+// `NAME=PATH`, or a bare `PATH` named after its file stem
+#[derive(Debug, Clone)]
+pub struct PreambleVariant {
+    pub name: String,
+    pub path: Option<PathBuf>,
+}
+
+impl FromStr for PreambleVariant {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("builtin") {
+            return Ok(Self { name: "builtin".to_string(), path: None });
+        }
+        let (name, path) = match s.split_once('=') {
+            Some((name, path)) => (name.trim().to_string(), PathBuf::from(path.trim())),
+            None => {
+                let path = PathBuf::from(s.trim());
+                let name = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+                (name, path)
+            }
+        };
+        if name.is_empty() || path.as_os_str().is_empty() {
+            return Err(format!("expected builtin, NAME=PATH or PATH, got '{}'", s));
+        }
+        Ok(Self { name, path: Some(path) })
+    }
+}
+
+impl PreambleVariant {
+    pub fn read(&self, builtin: &str) -> Result<String, anyhow::Error> {
+        match &self.path {
+            None => Ok(builtin.to_string()),
+            Some(path) => {
+                let preamble = std::fs::read_to_string(path)
+                    .map_err(|e| anyhow::anyhow!("Cannot read preamble variant {} ({}): {}", self.name, path.display(), e))?;
+                if preamble.trim().is_empty() {
+                    return Err(anyhow::anyhow!("Preamble variant {} ({}) is empty", self.name, path.display()));
+                }
+                Ok(preamble)
+            }
+        }
+    }
+}
+
+// A variant ready to run: its name, full preamble (for token estimates) and agent
+pub struct Variant<M: CompletionModel> {
+    pub name: String,
+    pub preamble: String,
+    pub analyst: RetryingPrompt<M>,
+}
+
+// Run every query once through retrieval, then through each variant's analyst
+// on the same prompt, so answers differ only by preamble. Prints each query's
+// answers grouped together and returns per-variant usage for the summary.
+pub async fn run_experiment<M, R>(
+    retrieval: &R,
+    variants: &[Variant<M>],
+    queries: &[String],
+    model: &str,
+    post_processors: &PostProcessChain,
+    max_in_flight: usize,
+    verbose_errors: bool,
+) -> Result<Vec<UsageLine>, anyhow::Error>
+where
+    M: CompletionModel,
+    R: Op<Input = String, Output = (String, Result<AnalysisRequest, PipelineError>)>,
+{
+    println!(
+        "Preamble experiment: {} queries x {} variants = {} analysis requests, {}x the completion cost of a single run",
+        queries.len(),
+        variants.len(),
+        queries.len() * variants.len(),
+        variants.len()
+    );
+    let mut usage: Vec<UsageLine> = variants.iter().map(|_| UsageLine::new(model)).collect();
+
+    for query in queries {
+        let request = match retrieval.call(query.clone()).await {
+            (_, Ok(request)) => request,
+            // Skipped for every variant alike, so usage stays comparable
+            (query, Err(e)) => {
+                println!("\n=== Query: {} ===\n\nError analyzing query: {}", query, describe_error(e, verbose_errors));
+                continue;
+            }
+        };
+        let answers: Vec<_> = stream::iter(variants)
+            .map(|variant| variant.analyst.call(request.prompt.clone()))
+            .buffered(max_in_flight)
+            .collect()
+            .await;
+
+        let mut out = String::new();
+        writeln!(out, "\n=== Query: {} ===\n", request.query)?;
+        writeln!(out, "Profiles sent to agent: {}", request.profiles.len())?;
+        for ((variant, answer), usage) in variants.iter().zip(answers).zip(&mut usage) {
+            usage.record_completion(&format!("{}{}", variant.preamble, request.prompt), answer.as_ref().ok().map(String::as_str));
+            writeln!(out, "\n--- Variant: {} ---", variant.name)?;
+            match answer {
+                Ok(analysis) => writeln!(out, "{}", post_processors.apply(analysis))?,
+                Err(e) => writeln!(out, "Error analyzing query: {}", describe_error(e, verbose_errors))?,
+            }
+        }
+        print!("{}", out);
+    }
+    Ok(usage)
+}
+
+// Requests, estimated tokens and cost per variant
+pub fn usage_table(variants: &[Variant<impl CompletionModel>], usage: &[UsageLine]) -> String {
+    let mut table = format!("{:<20} {:>8} {:>14} {:>14} {:>12}\n", "Variant", "Requests", "Input tokens", "Output tokens", "Cost (USD)");
+    let mut total = Some(0.0);
+    for (variant, line) in variants.iter().zip(usage) {
+        let cost = line.cost();
+        total = total.zip(cost).map(|(total, cost)| total + cost);
+        table.push_str(&format!(
+            "{:<20} {:>8} {:>14} {:>14} {:>12}\n",
+            variant.name,
+            line.requests,
+            format!("~{}", line.input_tokens),
+            format!("~{}", line.output_tokens),
+            cost.map_or_else(|| "unknown".to_string(), |cost| format!("~${:.4}", cost)),
+        ));
+    }
+    match total {
+        Some(total) => table.push_str(&format!("{:>72}", format!("Total ~${:.4}", total))),
+        None => table.push_str("Total unknown: no price on file for the model"),
+    }
+    table
+}